@@ -0,0 +1,97 @@
+//! Compares writing and reading a full FIFO payload byte by byte with a single burst transaction,
+//! using the emulated SX1276. Run with `cargo bench --bench fifo`.
+
+use rfm9x::registers::Register;
+use rfm9x::testing::{IncomingPacket, MockDio0, MockSpi, Sx1276};
+use rfm9x::{Band, Channel, DataRate, NoPin, RFM95};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 10_000;
+const PAYLOAD: [u8; 254] = [0xA5; 254];
+
+type MockRFM95 = RFM95<MockSpi, MockDio0, NoPin, NoPin>;
+
+fn radio() -> (Sx1276, MockRFM95) {
+    let chip = Sx1276::new();
+    let rfm = RFM95::new(
+        chip.spi(),
+        chip.dio0(),
+        None,
+        None,
+        DataRate::SF7_BW125,
+        Band::EU863,
+        Channel::Ch0,
+    );
+    (chip, rfm)
+}
+
+/** Run `f` `ITERATIONS` times, returning the mean duration and SPI transactions per iteration */
+fn measure<F: FnMut(&mut MockRFM95)>(mut f: F) -> (Duration, usize) {
+    let (chip, mut rfm) = radio();
+    let transactions = chip.transactions();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f(&mut rfm);
+    }
+    (
+        start.elapsed() / ITERATIONS,
+        (chip.transactions() - transactions) / ITERATIONS as usize,
+    )
+}
+
+fn report(name: &str, (duration, transactions): (Duration, usize)) {
+    println!(
+        "{:<24} {:>10.2?} {:>6} transactions",
+        name, duration, transactions
+    );
+}
+
+fn main() {
+    report(
+        "write FIFO per byte",
+        measure(|rfm| {
+            for byte in PAYLOAD.iter() {
+                rfm.write_register(Register::FIFO, *byte).unwrap();
+            }
+        }),
+    );
+    report(
+        "write FIFO burst",
+        measure(|rfm| rfm.write_registers(Register::FIFO, &PAYLOAD).unwrap()),
+    );
+
+    let mut buffer = [0u8; PAYLOAD.len()];
+    report(
+        "read FIFO per byte",
+        measure(|rfm| {
+            for byte in buffer.iter_mut() {
+                *byte = rfm.read_register(Register::FIFO).unwrap();
+            }
+        }),
+    );
+    report(
+        "read FIFO burst",
+        measure(|rfm| rfm.read_registers(Register::FIFO, &mut buffer).unwrap()),
+    );
+
+    // Transactions for a complete send/receive cycle (not timed, as mode changes sleep)
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    let before = chip.transactions();
+    rfm.send_packet(&PAYLOAD).unwrap();
+    println!(
+        "send_packet ({} bytes): {} transactions",
+        PAYLOAD.len(),
+        chip.transactions() - before
+    );
+
+    chip.receive(IncomingPacket::new(&PAYLOAD));
+    let before = chip.transactions();
+    rfm.receive_packet_on_tx(true, Duration::from_secs(1))
+        .unwrap();
+    println!(
+        "receive_packet ({} bytes): {} transactions",
+        PAYLOAD.len(),
+        chip.transactions() - before
+    );
+}
@@ -1,10 +1,11 @@
 use std::process::Command;
+use std::thread;
 
 
 use std::error::Error;
 use rppal::gpio::Gpio;
 use rppal::gpio::Trigger;
-use rppal::gpio::Level;
+use rppal::gpio::Event;
 
 // Gpio uses BCM pin numbering. BCM GPIO 23 is tied to physical pin 16.
 const GPIO_BUTTON_LEFT: u8 = 5;
@@ -12,24 +13,27 @@ const GPIO_BUTTON_CENTRE: u8 = 6;
 const GPIO_BUTTON_RIGHT: u8 = 12;
 
 
-type Callback = fn(Level);
+type Callback = fn(Event);
 
-fn ping(_lvl: Level){
+fn ping(_event: Event){
     println!("starting ping");
-    Command::new("/home/casey/rfm95x/pingpong")
+    let mut child = Command::new("/home/casey/rfm95x/pingpong")
         .args(["ping", "--count=6", "--delay=10 --timeout=0"])
         .spawn()
         .expect("failed to start external executable");
+    // Reap the process once it exits, without blocking the interrupt thread
+    thread::spawn(move || child.wait());
 }
 
-fn pong(_lvl: Level){
-    Command::new("/home/casey/rfm95x/pingpong")
+fn pong(_event: Event){
+    let mut child = Command::new("/home/casey/rfm95x/pingpong")
         .args(["pong", "--timeout=0"])
         .spawn()
         .expect("failed to start external executable");
+    thread::spawn(move || child.wait());
 }
 
-fn nothing_here(_lvl: Level){
+fn nothing_here(_event: Event){
 
 }
 
@@ -44,10 +48,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     let pong: Callback = pong;
     let nothin: Callback = nothing_here;
     
-    pin1.set_async_interrupt(Trigger::FallingEdge, ping).unwrap();
-    pin2.set_async_interrupt(Trigger::FallingEdge, pong).unwrap();
-    pin3.set_async_interrupt(Trigger::FallingEdge, nothin).unwrap();
+    pin1.set_async_interrupt(Trigger::FallingEdge, None, ping).unwrap();
+    pin2.set_async_interrupt(Trigger::FallingEdge, None, pong).unwrap();
+    pin3.set_async_interrupt(Trigger::FallingEdge, None, nothin).unwrap();
     
-    loop {}
+    // The interrupts are handled on their own threads
+    loop {
+        thread::park();
+    }
   
 }
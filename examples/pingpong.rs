@@ -17,7 +17,9 @@ use embedded_graphics::{
 use linux_embedded_hal::I2cdev;
 
 
-use rfm9x::{Band, Channel, DataRate, RFM95};
+use rfm9x::{
+    Band, Channel, DataRate, Error as RadioError, ReceivedPacket, RpiChipSelect, RpiRFM95,
+};
 use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 
 #[derive(Parser)]
@@ -60,65 +62,64 @@ struct PongArgs {
 
 
 
-fn setup_radio() -> Result<RFM95, Box<dyn Error>> {
+fn setup_radio() -> Result<RpiRFM95, Box<dyn Error>> {
     // Define spi
     let spi: rppal::spi::Spi =
         Spi::new(Bus::Spi0, SlaveSelect::Ss1, 4_000_000, Mode::Mode0)?;
 
     // Define radio
-    let mut rfm = RFM95::new(
+    let mut rfm = RpiRFM95::from_rppal(
         spi,
         22,
-        Some(7),
+        RpiChipSelect::Hardware,
+        Some(25),
         DataRate::SF12_BW125,
         Band::US901,
         Channel::Ch3,
     )
     ?;
 
-    rfm.reset(25)?;
+    rfm.reset()?;
 
     // recieve a packet (unused, not sure why but it needs to happen.)
-    let (_pkt, _size) = rfm.receive_packet(
+    match rfm.receive_packet(
         Channel::Ch3,
         DataRate::SF12_BW125,
         false,
         Duration::from_secs(1),
-    )?;
+    ) {
+        Ok(_) | Err(RadioError::ReceiveTimedOut) | Err(RadioError::CrcError(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
     
-    return Ok(rfm);
+    Ok(rfm)
 }
 
 
-fn send_it(rfm: &mut RFM95, m: &str) -> Result<(), Box<dyn Error>> {
+fn send_it(rfm: &mut RpiRFM95, m: &str) -> Result<(), Box<dyn Error>> {
 
-    let msg = format!("{} {}", Utc::now().round_subsecs(2).time().to_string(), m);
+    let msg = format!("{} {}", Utc::now().round_subsecs(2).time(), m);
     println!("TX: {}", msg);
     rfm.send_packet(msg.as_bytes())?;
     Ok(())
 }
 
-fn get_it(rfm: &mut RFM95, timeout: u64) -> Result<String, Box<dyn Error>> {
+fn get_it(rfm: &mut RpiRFM95, timeout: u64) -> Result<Option<ReceivedPacket>, Box<dyn Error>> {
 
-    let mut t:u64 = 120;
+    let t: u64 = if timeout == 0 { 120 } else { timeout };
 
-    if timeout ==0 { 
-        t = 120;
-    } else {
-        t = timeout;
-    }
-
-    let (pkt, _size) = rfm.receive_packet(
+    let pkt = match rfm.receive_packet(
         Channel::Ch3,
         DataRate::SF12_BW125,
         false,
         Duration::from_secs(t + 1),
-    )?;
-
-    let msg = String::from_utf8_lossy(&pkt);
-    let msg2 = msg.strip_suffix(0 as char).unwrap().to_string();
+    ) {
+        Ok(received) => received,
+        Err(RadioError::ReceiveTimedOut) | Err(RadioError::CrcError(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
 
-    return Ok(msg2);
+    Ok(Some(pkt))
 }
 
 fn print_rx_oled(line1: String, line2: String) {
@@ -164,13 +165,18 @@ fn ping(count: u8, delay: u64, timeout: u64) -> Result<(), Box<dyn Error>> {
     let mut now = Instant::now();
     while counter < count {
         let m = get_it(&mut rfm, delay)?;
-        if (now.elapsed().as_secs() >= timeout.into() && timeout !=0) {
+        if now.elapsed().as_secs() >= timeout && timeout != 0 {
             println!("\nTIMEOUT\n");
             let oled_print_l1 = "PING - TIMEOUT".to_string();
             print_rx_oled(oled_print_l1, "".to_string());
             break;
         }
-        println!("RX: [{}] [RSSI: {}] [SNR: {}]- {}", Utc::now().round_subsecs(2).time().to_string(), rfm.get_rssi().unwrap(),rfm.get_rssi().unwrap(), m);
+        let pkt = match m {
+            Some(pkt) => pkt,
+            None => continue,
+        };
+        let m = String::from_utf8_lossy(&pkt.payload).to_string();
+        println!("RX: [{}] [RSSI: {} dBm] [SNR: {} dB]- {}", Utc::now().round_subsecs(2).time(), pkt.rssi, pkt.snr, m);
         sleep(Duration::from_secs(delay));
         counter += 1;
         fm = format!("{}/{} - PING", counter, count);
@@ -200,13 +206,18 @@ fn pong(timeout: u64) -> Result<(), Box<dyn Error>> {
     let mut now = Instant::now();
     loop {
         let m = get_it(&mut rfm, timeout)?;
-        if now.elapsed().as_secs() >= timeout.into() && timeout !=0{
+        if now.elapsed().as_secs() >= timeout && timeout != 0 {
             println!("\nTIMEOUT\n");
             let oled_print_l1 = "PONG - TIMEOUT".to_string();
             print_rx_oled(oled_print_l1, "".to_string());
             break;
         }
-        println!("RX: [{}] [RSSI: {}] [SNR: {}]- {}", Utc::now().round_subsecs(2).time().to_string(), rfm.get_rssi().unwrap(),rfm.get_rssi().unwrap(), m);
+        let pkt = match m {
+            Some(pkt) => pkt,
+            None => continue,
+        };
+        let m = String::from_utf8_lossy(&pkt.payload).to_string();
+        println!("RX: [{}] [RSSI: {} dBm] [SNR: {} dB]- {}", Utc::now().round_subsecs(2).time(), pkt.rssi, pkt.snr, m);
         let (t1, b1) = m.split_at(12);
         let t2 = t1.trim_matches(char::from(0)).trim().to_string();
         let b2 = b1.trim_matches(char::from(0)).trim().to_string();
@@ -0,0 +1,84 @@
+use crate::modulation::{CodingRate, LoRaModulation};
+use std::time::Duration;
+
+/** Preamble length in symbols programmed by `RFM95::reset` */
+pub(crate) const PREAMBLE_LENGTH: u16 = 8;
+
+/** Parameters of a LoRa packet that determine its time on air */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PacketParameters {
+    pub modulation: LoRaModulation,
+    /** Number of programmed preamble symbols (RegPreambleLength), excluding the 4.25 symbols added by the chip */
+    pub preamble_length: u16,
+    pub explicit_header: bool,
+    pub crc: bool,
+    pub low_data_rate_optimize: bool,
+}
+
+impl PacketParameters {
+    /** Parameters as used by the driver: an 8 symbol preamble, explicit header (except for SF6), CRC on and
+     * low data rate optimisation as derived from the modulation */
+    pub fn new(modulation: LoRaModulation) -> PacketParameters {
+        PacketParameters {
+            modulation,
+            preamble_length: PREAMBLE_LENGTH,
+            explicit_header: !modulation.implicit_header(),
+            crc: true,
+            low_data_rate_optimize: modulation.low_data_rate_optimize(),
+        }
+    }
+}
+
+/** Time on air of a LoRa packet, see `time_on_air` */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeOnAir {
+    /** Preamble length in symbols, including the sync word and start of frame delimiter (n + 4.25) */
+    pub preamble_symbols: f32,
+    /** Symbols of the first block, which carries the header (if any) and is always sent at coding rate 4/8 */
+    pub header_symbols: u32,
+    /** Remaining symbols carrying the payload and CRC */
+    pub payload_symbols: u32,
+    pub symbol_time: Duration,
+    pub total: Duration,
+}
+
+/** Time on air of a packet with the given payload length (see SX1276 datasheet, 4.1.1.7) */
+pub fn time_on_air(parameters: &PacketParameters, payload_length: usize) -> TimeOnAir {
+    let sf = parameters.modulation.sf.value() as i64;
+    let cr = match parameters.modulation.cr {
+        CodingRate::CR4_5 => 1,
+        CodingRate::CR4_6 => 2,
+        CodingRate::CR4_7 => 3,
+        CodingRate::CR4_8 => 4,
+    };
+    let crc = parameters.crc as i64;
+    let implicit_header = !parameters.explicit_header as i64;
+    let low_data_rate_optimize = parameters.low_data_rate_optimize as i64;
+
+    let bits = 8 * payload_length as i64 - 4 * sf + 28 + 16 * crc - 20 * implicit_header;
+    let bits_per_block = 4 * (sf - 2 * low_data_rate_optimize);
+    let blocks = if bits > 0 {
+        (bits + bits_per_block - 1) / bits_per_block
+    } else {
+        0
+    };
+    let payload_symbols = (blocks * (cr + 4)) as u32;
+
+    // Work in quarter symbols, as the preamble is n + 4.25 symbols long
+    let quarter_symbols = (parameters.preamble_length as u64 * 4 + 17)
+        + 4 * (HEADER_BLOCK_SYMBOLS + payload_symbols) as u64;
+    let chips = (quarter_symbols as u128) << sf;
+    let bandwidth = parameters.modulation.bw.hz() as u128;
+    let total_nanos = chips * 1_000_000_000 / (4 * bandwidth);
+
+    TimeOnAir {
+        preamble_symbols: parameters.preamble_length as f32 + 4.25,
+        header_symbols: HEADER_BLOCK_SYMBOLS,
+        payload_symbols,
+        symbol_time: parameters.modulation.symbol_time(),
+        total: Duration::from_nanos(total_nanos as u64),
+    }
+}
+
+/** The first block of a packet is always 8 symbols long */
+const HEADER_BLOCK_SYMBOLS: u32 = 8;
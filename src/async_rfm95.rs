@@ -0,0 +1,179 @@
+use crate::error::*;
+use crate::lbt::{ChannelSensing, ListenBeforeTalk};
+use crate::modulation::LoRaModulation;
+use crate::packet::ReceivedPacket;
+use crate::pins::NoPin;
+use crate::rfm95::{check_payload_length, Band, Channel, RFM95};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+use embedded_hal_async::digital::Wait;
+use std::time::{Duration, Instant};
+
+/** Asynchronous driver for an RFM9x module. Instead of polling DIO0, it awaits the interrupt through the
+ * `embedded-hal-async` `Wait` trait, e.g. using `CdevDio0` (Linux GPIO character device) or `RpiDio0`
+ * (rppal interrupts). Requires a Tokio runtime with the time driver enabled.
+ *
+ * Register accesses are still performed synchronously, as they take only a few microseconds each. */
+pub struct AsyncRfm95<SPI, DIO0, CS, RESET> {
+    radio: RFM95<SPI, NoPin, CS, RESET>,
+    dio0: DIO0,
+}
+
+impl<SPI, DIO0, CS, RESET> AsyncRfm95<SPI, DIO0, CS, RESET>
+where
+    SPI: SpiDevice,
+    DIO0: Wait,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    /** Create a driver; the arguments are the same as those of `RFM95::new` */
+    pub fn new<M: Into<LoRaModulation>>(
+        spi: SPI,
+        dio0: DIO0,
+        cs_pin: Option<CS>,
+        reset_pin: Option<RESET>,
+        modulation: M,
+        band: Band,
+        channel: Channel,
+    ) -> AsyncRfm95<SPI, DIO0, CS, RESET> {
+        AsyncRfm95 {
+            radio: RFM95::new(spi, NoPin, cs_pin, reset_pin, modulation, band, channel),
+            dio0,
+        }
+    }
+
+    /** The underlying driver, for resetting and configuring the module. Its blocking send and receive
+     * functions cannot be used, as it has no DIO0 pin to poll. */
+    pub fn radio(&mut self) -> &mut RFM95<SPI, NoPin, CS, RESET> {
+        &mut self.radio
+    }
+
+    /** Release the SPI device and pins owned by the driver */
+    pub fn release(self) -> (SPI, DIO0, Option<CS>, Option<RESET>) {
+        let (spi, _, cs_pin, reset_pin) = self.radio.release();
+        (spi, self.dio0, cs_pin, reset_pin)
+    }
+
+    /** Transmit a packet using the configured channel and modulation (see `RFM95::send_packet`), applying
+     * the duty-cycle tracker and the listen-before-talk policy if they are set. When the future is dropped
+     * before it completes, the transmission is aborted.
+     *
+     * The wait for the duty cycle is computed with the clock of the tracker, but always awaited in real
+     * time on the Tokio timer: `Clock::sleep` is not used, as it blocks. */
+    pub async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        check_payload_length(packet)?;
+        let channel = self.radio.resolve_channel(self.radio.channel())?;
+        let frequency = channel.frequency_hz(&self.radio.band())?;
+        let airtime = self.radio.time_on_air(packet.len());
+        let wait = self.radio.duty_cycle_wait_time(frequency, airtime)?;
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+
+        if let Some(policy) = self.radio.listen_before_talk() {
+            let mut retries = 0;
+            while !self.channel_clear(&policy, channel).await? {
+                if retries == policy.max_retries {
+                    return Err(Error::ChannelBusy);
+                }
+                retries += 1;
+                tokio::time::sleep(policy.backoff()).await;
+            }
+        }
+
+        let timeout = self.radio.transmit_timeout(packet.len());
+        self.radio.start_transmit(packet, channel)?;
+        self.radio.record_transmission(frequency, airtime);
+        let guard = StandbyGuard::new(&mut self.radio);
+        let done = wait_for_interrupt(&mut self.dio0, timeout).await?;
+        guard.disarm().finish_transmit(done)
+    }
+
+    /** Sense the channel for the listen time of the policy (see `RFM95::channel_clear`), awaiting CadDone
+     * on DIO0 for channel activity detection. Returns true when it is clear. */
+    async fn channel_clear(&mut self, policy: &ListenBeforeTalk, channel: Channel) -> Result<bool> {
+        if policy.sensing != ChannelSensing::ChannelActivity {
+            return self.radio.channel_clear(policy, channel);
+        }
+        let deadline = Instant::now() + policy.listen_duration();
+        loop {
+            let modulation = self.radio.modulation();
+            let timeout = self
+                .radio
+                .start_channel_activity_detection(channel, modulation)?;
+            let guard = StandbyGuard::new(&mut self.radio);
+            let done = wait_for_interrupt(&mut self.dio0, timeout).await?;
+            if guard.disarm().finish_channel_activity_detection(done)? {
+                return Ok(false);
+            }
+            if Instant::now() >= deadline {
+                return Ok(true);
+            }
+        }
+    }
+
+    /** Receive a packet (see `RFM95::receive_packet`). When the future is dropped before it completes, the
+     * receiver is put back in standby. */
+    pub async fn receive<M: Into<LoRaModulation>>(
+        &mut self,
+        channel: Channel,
+        modulation: M,
+        with_crc: bool,
+        timeout: Duration,
+    ) -> Result<ReceivedPacket> {
+        let modulation = modulation.into();
+        self.radio.start_receive(channel, modulation, with_crc)?;
+        let guard = StandbyGuard::new(&mut self.radio);
+        let received = wait_for_interrupt(&mut self.dio0, timeout).await?;
+        guard.disarm().finish_receive(received, modulation)
+    }
+}
+
+/** Wait for DIO0 to become high; returns false when `timeout` expires first */
+async fn wait_for_interrupt<DIO0: Wait>(dio0: &mut DIO0, timeout: Duration) -> Result<bool> {
+    match tokio::time::timeout(timeout, dio0.wait_for_high()).await {
+        Ok(result) => result.map(|_| true).map_err(gpio_error),
+        Err(_) => Ok(false),
+    }
+}
+
+/** Puts the transceiver in standby when dropped while armed, so that an operation whose future is dropped
+ * does not leave the transceiver transmitting or receiving */
+struct StandbyGuard<'a, SPI, CS, RESET>
+where
+    SPI: SpiDevice,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    radio: Option<&'a mut RFM95<SPI, NoPin, CS, RESET>>,
+}
+
+impl<'a, SPI, CS, RESET> StandbyGuard<'a, SPI, CS, RESET>
+where
+    SPI: SpiDevice,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    fn new(radio: &'a mut RFM95<SPI, NoPin, CS, RESET>) -> StandbyGuard<'a, SPI, CS, RESET> {
+        StandbyGuard { radio: Some(radio) }
+    }
+
+    /** The operation completed; hand the driver back without changing modes */
+    fn disarm(mut self) -> &'a mut RFM95<SPI, NoPin, CS, RESET> {
+        self.radio.take().unwrap()
+    }
+}
+
+impl<SPI, CS, RESET> Drop for StandbyGuard<'_, SPI, CS, RESET>
+where
+    SPI: SpiDevice,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    fn drop(&mut self) {
+        if let Some(radio) = self.radio.take() {
+            // Nothing sensible can be done with an error here
+            let _ = radio.standby();
+        }
+    }
+}
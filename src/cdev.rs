@@ -0,0 +1,78 @@
+use embedded_hal::digital::{ErrorKind, ErrorType};
+use embedded_hal_async::digital::Wait;
+use futures::stream::StreamExt;
+use gpio_cdev::{AsyncLineEventHandle, Chip, EventRequestFlags, EventType, LineRequestFlags};
+
+/** DIO0 line read through the Linux GPIO character device (`/dev/gpiochipN`), for use with
+ * `AsyncRfm95`. Edges are delivered by the kernel, so waiting does not involve polling. */
+pub struct CdevDio0 {
+    events: AsyncLineEventHandle,
+}
+
+impl CdevDio0 {
+    /** Request edge events for a line (offset) of a GPIO chip, e.g. `Chip::new("/dev/gpiochip0")` */
+    pub fn new(chip: &mut Chip, line: u32) -> Result<CdevDio0, gpio_cdev::Error> {
+        let handle = chip.get_line(line)?.events(
+            LineRequestFlags::INPUT,
+            EventRequestFlags::BOTH_EDGES,
+            "rfm9x-dio0",
+        )?;
+        Ok(CdevDio0 {
+            events: AsyncLineEventHandle::new(handle)?,
+        })
+    }
+
+    fn is_high(&self) -> Result<bool, ErrorKind> {
+        match self.events.as_ref().get_value() {
+            Ok(value) => Ok(value != 0),
+            Err(_) => Err(ErrorKind::Other),
+        }
+    }
+
+    async fn next_edge(&mut self) -> Result<EventType, ErrorKind> {
+        match self.events.next().await {
+            Some(Ok(event)) => Ok(event.event_type()),
+            _ => Err(ErrorKind::Other),
+        }
+    }
+
+    /** Wait until the line is at the given level. Events may be stale, so the level is checked again
+     * after each of them. */
+    async fn wait_for_level(&mut self, high: bool) -> Result<(), ErrorKind> {
+        while self.is_high()? != high {
+            self.next_edge().await?;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_edge(&mut self, edge: EventType) -> Result<(), ErrorKind> {
+        while self.next_edge().await? != edge {}
+        Ok(())
+    }
+}
+
+impl ErrorType for CdevDio0 {
+    type Error = ErrorKind;
+}
+
+impl Wait for CdevDio0 {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_edge(EventType::RisingEdge).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_edge(EventType::FallingEdge).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.next_edge().await.map(|_| ())
+    }
+}
@@ -0,0 +1,104 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/** Strategy for picking the channel `Channel::Multi` resolves to, see `RFM95::set_channel_selector`. The
+ * driver picks a channel for every transmission and reception. */
+pub trait ChannelSelector {
+    /** Pick one of `count` channels (at least one), returning its index */
+    fn select(&mut self, count: usize) -> usize;
+}
+
+/** Picks a channel at random using the thread-local generator; the default */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RandomChannel;
+
+impl ChannelSelector for RandomChannel {
+    fn select(&mut self, count: usize) -> usize {
+        rand::thread_rng().gen_range(0..count)
+    }
+}
+
+/** Cycles through the channels in order */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl RoundRobin {
+    pub fn new() -> RoundRobin {
+        RoundRobin::default()
+    }
+}
+
+impl ChannelSelector for RoundRobin {
+    fn select(&mut self, count: usize) -> usize {
+        let index = self.next % count;
+        self.next = index + 1;
+        index
+    }
+}
+
+/** Picks a channel at random from a seeded generator, so that the sequence can be reproduced */
+#[derive(Clone, Debug)]
+pub struct SeededRandom {
+    rng: StdRng,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl ChannelSelector for SeededRandom {
+    fn select(&mut self, count: usize) -> usize {
+        self.rng.gen_range(0..count)
+    }
+}
+
+/** Uses every channel once in random order before any channel is used again, and never uses the same
+ * channel twice in a row, as recommended for LoRaWAN end devices. Seeded, so that the sequence can be
+ * reproduced. */
+#[derive(Clone, Debug)]
+pub struct RandomWithoutRepeat {
+    rng: StdRng,
+    /** Number of channels the current round was drawn from */
+    count: usize,
+    remaining: Vec<usize>,
+    last: Option<usize>,
+}
+
+impl RandomWithoutRepeat {
+    pub fn new(seed: u64) -> RandomWithoutRepeat {
+        RandomWithoutRepeat {
+            rng: StdRng::seed_from_u64(seed),
+            count: 0,
+            remaining: Vec::new(),
+            last: None,
+        }
+    }
+}
+
+impl ChannelSelector for RandomWithoutRepeat {
+    fn select(&mut self, count: usize) -> usize {
+        // Start over when the number of channels changed, so that added channels are used right away
+        if count != self.count {
+            self.count = count;
+            self.remaining.clear();
+        }
+        if self.remaining.is_empty() {
+            self.remaining = (0..count).collect();
+            self.remaining.shuffle(&mut self.rng);
+            // Channels are taken from the end; do not start the new round with the last channel
+            if count > 1 && self.remaining.last() == self.last.as_ref() {
+                self.remaining.swap(0, count - 1);
+            }
+        }
+        let index = self.remaining.pop().unwrap();
+        self.last = Some(index);
+        index
+    }
+}
@@ -0,0 +1,32 @@
+use std::ops::RangeInclusive;
+
+/** Frequency of the crystal oscillator on the RFM9x modules (Hz) */
+pub const FXOSC: u32 = 32_000_000;
+
+/** Frequencies covered by the HF (RFM95/96/97) and LF (RFM96/98) RF ports respectively (Hz) */
+pub const HF_FREQUENCY_RANGE: RangeInclusive<u32> = 779_000_000..=1_020_000_000;
+pub const LF_FREQUENCY_RANGE: RangeInclusive<u32> = 137_000_000..=525_000_000;
+
+/** Contents of RegVersion on an SX1276/77/78/79 */
+pub const RFM_VERSION: u8 = 0x12;
+
+/** Size of the FIFO shared between transmitter and receiver (bytes) */
+pub const FIFO_SIZE: usize = 256;
+
+/** LoRa sync word used by public LoRaWAN networks */
+pub const SYNC_WORD_LORAWAN: u8 = 0x34;
+
+/** LoRa sync word for private networks (the chip's reset value) */
+pub const SYNC_WORD_PRIVATE: u8 = 0x12;
+
+/** RegInvertIQ2 value for normal and inverted I/Q respectively */
+pub const INVERT_IQ2_OFF: u8 = 0x1D;
+pub const INVERT_IQ2_ON: u8 = 0x19;
+
+/** Values of RegDetectOptimize and RegDetectionThreshold for SF7 to SF12 */
+pub const DETECTION_OPTIMIZE_SF7_TO_SF12: u8 = 0x03;
+pub const DETECTION_THRESHOLD_SF7_TO_SF12: u8 = 0x0A;
+
+/** Values of RegDetectOptimize and RegDetectionThreshold for SF6 */
+pub const DETECTION_OPTIMIZE_SF6: u8 = 0x05;
+pub const DETECTION_THRESHOLD_SF6: u8 = 0x0C;
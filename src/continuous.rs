@@ -0,0 +1,164 @@
+use crate::error::*;
+use crate::modulation::LoRaModulation;
+use crate::packet::ReceivedPacket;
+use crate::rfm95::{Channel, RFM95};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiDevice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/** How long the receive thread waits for RxDone before checking whether it should stop */
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl<SPI, IRQ, CS, RESET> RFM95<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice + Send + 'static,
+    IRQ: InputPin + Send + 'static,
+    CS: OutputPin + Send + 'static,
+    RESET: OutputPin + Send + 'static,
+{
+    /** Put the receiver in continuous receive mode and hand the driver to a thread that reads every packet
+     * as soon as RxDone is signalled, so that no packets are missed between calls. Up to `queue_size`
+     * packets that have not been consumed yet are kept; packets arriving while the queue is full are
+     * dropped (see `ContinuousReceiver::dropped`).
+     *
+     * Packets that fail the CRC check are delivered as `Error::CrcError`. Reception ends after any other
+     * error, which is delivered as the last item. */
+    pub fn start_receiving<M: Into<LoRaModulation>>(
+        mut self,
+        channel: Channel,
+        modulation: M,
+        with_crc: bool,
+        queue_size: usize,
+    ) -> Result<ContinuousReceiver<SPI, IRQ, CS, RESET>> {
+        let modulation = modulation.into();
+        self.start_receive(channel, modulation, with_crc)?;
+
+        let (sender, packets) = sync_channel(queue_size);
+        let stop = Arc::new(AtomicBool::new(false));
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let thread = {
+            let stop = stop.clone();
+            let dropped = dropped.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let result = match self.wait_for_interrupt(STOP_POLL_INTERVAL) {
+                        Ok(true) => match self.take_received_packet(modulation).transpose() {
+                            Some(result) => result,
+                            None => continue,
+                        },
+                        Ok(false) => continue,
+                        Err(error) => Err(error),
+                    };
+                    let fatal = match result {
+                        Ok(_) | Err(Error::CrcError(_)) => false,
+                        Err(_) => true,
+                    };
+                    match sender.try_send(result) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(TrySendError::Disconnected(_)) => break,
+                    }
+                    if fatal {
+                        break;
+                    }
+                }
+                self
+            })
+        };
+
+        Ok(ContinuousReceiver {
+            packets,
+            stop,
+            dropped,
+            thread: Some(thread),
+        })
+    }
+}
+
+/** Packets received in continuous receive mode, see `RFM95::start_receiving`. Iterating blocks until the
+ * next packet arrives. Dropping the receiver stops reception and puts the transceiver in standby. */
+pub struct ContinuousReceiver<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    packets: Receiver<Result<ReceivedPacket>>,
+    stop: Arc<AtomicBool>,
+    dropped: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<RFM95<SPI, IRQ, CS, RESET>>>,
+}
+
+impl<SPI, IRQ, CS, RESET> ContinuousReceiver<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    /** Wait for the next packet for at most `timeout`. Returns `Error::ReceiveTimedOut` when none arrived. */
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<ReceivedPacket>> {
+        match self.packets.recv_timeout(timeout) {
+            Ok(result) => Some(result),
+            Err(RecvTimeoutError::Timeout) => Some(Err(Error::ReceiveTimedOut)),
+            Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /** Number of packets dropped because the queue was full */
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /** Stop reception and return the driver, with the transceiver in standby */
+    pub fn stop(mut self) -> Result<RFM95<SPI, IRQ, CS, RESET>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let thread = self.thread.take().unwrap();
+        let mut radio = match thread.join() {
+            Ok(radio) => radio,
+            Err(panic) => std::panic::resume_unwind(panic),
+        };
+        radio.standby()?;
+        Ok(radio)
+    }
+}
+
+impl<SPI, IRQ, CS, RESET> Iterator for ContinuousReceiver<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    type Item = Result<ReceivedPacket>;
+
+    fn next(&mut self) -> Option<Result<ReceivedPacket>> {
+        self.packets.recv().ok()
+    }
+}
+
+impl<SPI, IRQ, CS, RESET> Drop for ContinuousReceiver<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            // The driver is dropped along with the receiver; leave the transceiver in standby
+            // as it would otherwise keep receiving.
+            if let Ok(mut radio) = thread.join() {
+                let _ = radio.standby();
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::error::*;
+use crate::rfm95::Band;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/** Period over which the duty cycle is measured (ETSI EN 300 220-1 uses one hour) */
+const DUTY_CYCLE_WINDOW: Duration = Duration::from_secs(3600);
+
+/** Source of time for the duty-cycle tracker and the LoRaWAN receive windows, so that they can be driven
+ * by a simulated clock in tests */
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    /** Block until `duration` has passed on this clock. Used by the blocking `RFM95::send_packet`;
+     * `AsyncRfm95::send` awaits the Tokio timer instead. */
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/** The system's monotonic clock */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/** A frequency range with a common duty-cycle limit */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubBand {
+    /** Frequencies (Hz) covered by the sub-band */
+    pub frequencies: RangeInclusive<u32>,
+    /** Share of the time the transmitter may be on, in tenths of a percent */
+    pub duty_cycle_permille: u32,
+}
+
+impl SubBand {
+    fn new(frequencies: RangeInclusive<u32>, duty_cycle_permille: u32) -> SubBand {
+        SubBand {
+            frequencies,
+            duty_cycle_permille,
+        }
+    }
+
+    /** Sub-bands with a duty-cycle limit in the band. The EU863 and EU433 sub-bands follow ERC
+     * Recommendation 70-03, annex 1; the other bands have no duty-cycle limit. */
+    pub fn for_band(band: &Band) -> Vec<SubBand> {
+        match band {
+            Band::EU863 => vec![
+                SubBand::new(863_000_000..=864_999_999, 1),
+                SubBand::new(865_000_000..=867_999_999, 10),
+                SubBand::new(868_000_000..=868_599_999, 10),
+                SubBand::new(868_700_000..=869_199_999, 1),
+                SubBand::new(869_400_000..=869_649_999, 100),
+                SubBand::new(869_700_000..=870_000_000, 10),
+            ],
+            // Band h1.2 (433.05 - 434.79 MHz)
+            Band::EU433 => vec![SubBand::new(433_050_000..=434_790_000, 100)],
+            _ => Vec::new(),
+        }
+    }
+
+    /** Airtime available within the measurement window */
+    pub fn budget(&self) -> Duration {
+        DUTY_CYCLE_WINDOW * self.duty_cycle_permille / 1000
+    }
+}
+
+/** What `send_packet` does when a transmission would exceed the duty cycle of its sub-band */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DutyCycleEnforcement {
+    /** Fail with `Error::DutyCycleExceeded` */
+    Refuse,
+    /** Wait until the transmission is allowed */
+    Delay,
+}
+
+/** Tracks the time on air per sub-band over a sliding window of one hour and decides whether a
+ * transmission fits into the remaining duty-cycle budget of its sub-band. Frequencies outside all
+ * sub-bands of the band are not limited. */
+pub struct DutyCycleTracker {
+    band: Band,
+    sub_bands: Vec<SubBandUsage>,
+    enforcement: DutyCycleEnforcement,
+    clock: Box<dyn Clock + Send>,
+}
+
+struct SubBandUsage {
+    sub_band: SubBand,
+    /** Start and time on air of the transmissions within the window, oldest first */
+    transmissions: VecDeque<(Instant, Duration)>,
+}
+
+impl SubBandUsage {
+    /** Forget transmissions that left the window */
+    fn expire(&mut self, now: Instant) {
+        while let Some((start, _)) = self.transmissions.front() {
+            if now.saturating_duration_since(*start) < DUTY_CYCLE_WINDOW {
+                break;
+            }
+            self.transmissions.pop_front();
+        }
+    }
+
+    fn used(&self, now: Instant) -> Duration {
+        self.transmissions
+            .iter()
+            .filter(|(start, _)| now.saturating_duration_since(*start) < DUTY_CYCLE_WINDOW)
+            .map(|(_, airtime)| *airtime)
+            .sum()
+    }
+
+    fn next_allowed(&self, now: Instant, airtime: Duration) -> Option<Instant> {
+        let budget = self.sub_band.budget();
+        if airtime > budget {
+            return None;
+        }
+        let mut used = self.used(now);
+        if used + airtime <= budget {
+            return Some(now);
+        }
+        // Wait for enough of the earlier transmissions to leave the window
+        for (start, earlier) in &self.transmissions {
+            let expires = *start + DUTY_CYCLE_WINDOW;
+            if expires <= now {
+                continue;
+            }
+            used -= *earlier;
+            if used + airtime <= budget {
+                return Some(expires);
+            }
+        }
+        // Once every transmission in the window has expired, the whole budget is available
+        Some(now)
+    }
+}
+
+impl DutyCycleTracker {
+    /** Track the sub-bands of `band` using the system clock */
+    pub fn new(band: Band, enforcement: DutyCycleEnforcement) -> DutyCycleTracker {
+        DutyCycleTracker::with_clock(band, enforcement, SystemClock)
+    }
+
+    /** Track the sub-bands of `band` using the given clock */
+    pub fn with_clock<C: Clock + Send + 'static>(
+        band: Band,
+        enforcement: DutyCycleEnforcement,
+        clock: C,
+    ) -> DutyCycleTracker {
+        DutyCycleTracker {
+            band,
+            sub_bands: SubBand::for_band(&band)
+                .into_iter()
+                .map(|sub_band| SubBandUsage {
+                    sub_band,
+                    transmissions: VecDeque::new(),
+                })
+                .collect(),
+            enforcement,
+            clock: Box::new(clock),
+        }
+    }
+
+    pub fn band(&self) -> Band {
+        self.band
+    }
+
+    pub fn enforcement(&self) -> DutyCycleEnforcement {
+        self.enforcement
+    }
+
+    /** Sub-band the frequency (Hz) belongs to, if it is limited */
+    pub fn sub_band(&self, frequency: u32) -> Option<&SubBand> {
+        self.usage(frequency).map(|usage| &usage.sub_band)
+    }
+
+    /** Airtime left in the sub-band of the frequency (Hz) within the current window; `None` when the
+     * frequency is not limited */
+    pub fn remaining(&self, frequency: u32) -> Option<Duration> {
+        let now = self.clock.now();
+        self.usage(frequency)
+            .map(|usage| usage.sub_band.budget().saturating_sub(usage.used(now)))
+    }
+
+    /** Earliest time at which a transmission of `airtime` on the frequency (Hz) is allowed. Returns `None`
+     * when the transmission exceeds the budget of the sub-band on its own and can never be sent. */
+    pub fn next_allowed(&self, frequency: u32, airtime: Duration) -> Option<Instant> {
+        let now = self.clock.now();
+        match self.usage(frequency) {
+            Some(usage) => usage.next_allowed(now, airtime),
+            None => Some(now),
+        }
+    }
+
+    /** Record a transmission of `airtime` on the frequency (Hz) starting now */
+    pub fn record(&mut self, frequency: u32, airtime: Duration) {
+        let now = self.clock.now();
+        if let Some(usage) = self
+            .sub_bands
+            .iter_mut()
+            .find(|usage| usage.sub_band.frequencies.contains(&frequency))
+        {
+            usage.expire(now);
+            usage.transmissions.push_back((now, airtime));
+        }
+    }
+
+    /** How long to wait before a transmission of `airtime` on the frequency (Hz) may start. Fails with
+     * `Error::DutyCycleExceeded` when the transmission has to wait, but the enforcement is `Refuse`, or
+     * when it can never be sent. */
+    pub(crate) fn wait_time(&self, frequency: u32, airtime: Duration) -> Result<Duration> {
+        let now = self.clock.now();
+        let next_allowed = self.next_allowed(frequency, airtime);
+        match next_allowed {
+            Some(next) if next <= now => Ok(Duration::ZERO),
+            Some(next) if self.enforcement == DutyCycleEnforcement::Delay => Ok(next - now),
+            _ => Err(Error::DutyCycleExceeded(next_allowed)),
+        }
+    }
+
+    pub(crate) fn sleep(&self, duration: Duration) {
+        self.clock.sleep(duration);
+    }
+
+    fn usage(&self, frequency: u32) -> Option<&SubBandUsage> {
+        self.sub_bands
+            .iter()
+            .find(|usage| usage.sub_band.frequencies.contains(&frequency))
+    }
+}
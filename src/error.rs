@@ -0,0 +1,215 @@
+use crate::packet::ReceivedPacket;
+use crate::power::PaOutput;
+use crate::registers::Mode;
+use crate::rfm95::{Band, Channel};
+use embedded_hal::{digital, spi};
+use std::fmt::{Debug, Display};
+use std::sync::Arc;
+use std::time::Instant;
+
+/** Details of a mode change that was not confirmed by the chip */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeChangeFailedErrorInfo {
+    /** Raw value of RegOpMode before the change */
+    pub old_mode: u8,
+    /** Mode that was requested */
+    pub new_mode: Mode,
+    /** Raw value of RegOpMode read back after the change */
+    pub set_mode: u8,
+}
+
+/** The error behind an `Error::Spi`, `Error::Gpio` or `Error::Io`, returned by `Error::source`. The
+ * errors of the `embedded-hal` traits only have to implement `Debug`, so they are kept as their `Debug`
+ * output; I/O errors are kept as they are. Two sources are equal when they display the same message. */
+#[derive(Clone)]
+pub struct ErrorSource(Arc<dyn std::error::Error + Send + Sync>);
+
+impl ErrorSource {
+    fn new<E: std::error::Error + Send + Sync + 'static>(error: E) -> ErrorSource {
+        ErrorSource(Arc::new(error))
+    }
+}
+
+impl Debug for ErrorSource {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        Debug::fmt(&self.0, out)
+    }
+}
+
+impl Display for ErrorSource {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        Display::fmt(&self.0, out)
+    }
+}
+
+impl PartialEq for ErrorSource {
+    fn eq(&self, other: &ErrorSource) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+/** An `embedded-hal` error, described by its `Debug` output */
+#[derive(Debug)]
+struct HalError(String);
+
+impl Display for HalError {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        out.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HalError {}
+
+/** Errors returned by the RFM9x driver */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /** The SPI device reported an error */
+    Spi(spi::ErrorKind, ErrorSource),
+    /** One of the GPIO pins (IRQ, chip select or reset) reported an error */
+    Gpio(digital::ErrorKind, ErrorSource),
+    /** RegVersion did not contain the expected value; the chip is not an SX1276 or is not connected */
+    InvalidVersion(u8),
+    ModeChangeFailed(ModeChangeFailedErrorInfo),
+    /** The frequency (Hz) is not covered by either RF port */
+    InvalidFrequency(u32),
+    /** The output power (dBm) cannot be reached on the selected power amplifier output */
+    InvalidTxPower(i8, PaOutput),
+    /** A packet to transmit must have 1 - 255 bytes */
+    InvalidPayloadLength(usize),
+    /** TxDone was not signalled in time */
+    TransmissionTimedOut,
+    /** No packet was received in time */
+    ReceiveTimedOut,
+    /** No preamble was detected within the symbol timeout of a single receive (RxTimeout) */
+    SymbolTimeout,
+    /** Listen-before-talk found the channel busy on every attempt */
+    ChannelBusy,
+    /** CadDone was not signalled in time */
+    ChannelActivityDetectionTimedOut,
+    /** The symbol timeout does not fit in 10 bits */
+    InvalidSymbolTimeout(u16),
+    /** A packet was received, but its header or payload CRC did not match. Contains the corrupted
+     * packet when the driver is configured to keep corrupted packets. */
+    CrcError(Option<Box<ReceivedPacket>>),
+    /** The transmission would exceed the duty cycle of its sub-band, or the aggregated duty cycle a
+     * LoRaWAN network set for the device. Contains the earliest time it is allowed, or `None` when it is
+     * longer than the whole budget of the sub-band. */
+    DutyCycleExceeded(Option<Instant>),
+    /** The channel does not exist in the band */
+    InvalidChannel(Channel, Band),
+    /** The channel selector picked an index (first) out of range for the number of channels (second) */
+    InvalidChannelSelection(usize, usize),
+    /** A band-specific setting was created for another band (first) than the radio's (second) */
+    BandMismatch(Band, Band),
+    /** The data rate (DR number) is not defined in the band or not allowed for the operation */
+    InvalidDataRate(u8, Band),
+    /** The RX1 data rate offset is out of range for the band */
+    InvalidDataRateOffset(u8, Band),
+    /** The band has no such sub-band, or none at all */
+    InvalidSubBand(u8, Band),
+    /** A LoRaWAN frame could not be decoded or encoded */
+    InvalidFrame(&'static str),
+    /** The MIC of a LoRaWAN frame does not match */
+    InvalidMic,
+    /** The LoRaWAN device has neither joined a network nor been activated by personalization */
+    NotActivated,
+    /** The LoRaWAN FPort is reserved (0 is used for MAC commands, 224 - 255 are reserved) */
+    InvalidPort(u8),
+    /** The LoRaWAN application payload (bytes) exceeds the maximum (bytes) at the current data rate */
+    PayloadTooLong(usize, usize),
+    /** Reading or writing a file failed, e.g. the file of a `FileSessionStore` */
+    Io(std::io::ErrorKind, ErrorSource),
+    /** A stored LoRaWAN device state could not be decoded or does not fit the device */
+    InvalidSessionState(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            out,
+            "{}",
+            match self {
+                Error::Spi(kind, _) => format!("SPI error: {}", kind),
+                Error::Gpio(kind, _) => format!("GPIO error: {}", kind),
+                Error::InvalidVersion(version) => format!("invalid version {:#04x}", version),
+                Error::ModeChangeFailed(info) => format!("mode change failed: {:?}", info),
+                Error::InvalidFrequency(frequency) => format!("invalid frequency {} Hz", frequency),
+                Error::InvalidTxPower(dbm, output) =>
+                    format!("invalid TX power {} dBm on {:?}", dbm, output),
+                Error::InvalidPayloadLength(length) =>
+                    format!("invalid payload length {} bytes", length),
+                Error::TransmissionTimedOut => String::from("transmission timed out"),
+                Error::ReceiveTimedOut => String::from("receive timed out"),
+                Error::SymbolTimeout => String::from("no preamble detected within symbol timeout"),
+                Error::ChannelBusy => String::from("channel busy"),
+                Error::ChannelActivityDetectionTimedOut =>
+                    String::from("channel activity detection timed out"),
+                Error::InvalidSymbolTimeout(symbols) =>
+                    format!("invalid symbol timeout {} symbols", symbols),
+                Error::CrcError(_) => String::from("payload CRC error"),
+                Error::DutyCycleExceeded(Some(next)) => format!(
+                    "duty cycle exceeded, next transmission allowed in {:?}",
+                    next.saturating_duration_since(Instant::now())
+                ),
+                Error::DutyCycleExceeded(None) =>
+                    String::from("duty cycle exceeded, transmission longer than the budget"),
+                Error::InvalidChannel(channel, band) =>
+                    format!("invalid channel {:?} in band {:?}", channel, band),
+                Error::InvalidChannelSelection(index, count) =>
+                    format!("channel selector picked channel {} of {}", index, count),
+                Error::BandMismatch(band, radio) => format!(
+                    "setting for band {:?} used with a radio in band {:?}",
+                    band, radio
+                ),
+                Error::InvalidDataRate(data_rate, band) =>
+                    format!("invalid data rate DR{} in band {:?}", data_rate, band),
+                Error::InvalidDataRateOffset(offset, band) =>
+                    format!("invalid RX1 data rate offset {} in band {:?}", offset, band),
+                Error::InvalidSubBand(sub_band, band) =>
+                    format!("invalid sub-band {} in band {:?}", sub_band, band),
+                Error::InvalidFrame(reason) => format!("invalid LoRaWAN frame: {}", reason),
+                Error::InvalidMic => String::from("LoRaWAN MIC mismatch"),
+                Error::NotActivated => String::from("LoRaWAN device not activated"),
+                Error::InvalidPort(port) => format!("invalid LoRaWAN FPort {}", port),
+                Error::PayloadTooLong(length, max) => format!(
+                    "payload of {} bytes exceeds the maximum of {} bytes",
+                    length, max
+                ),
+                Error::Io(kind, _) => format!("I/O error: {}", kind),
+                Error::InvalidSessionState(reason) =>
+                    format!("invalid stored LoRaWAN session: {}", reason),
+            }
+        )
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spi(_, source) | Error::Gpio(_, source) | Error::Io(_, source) => {
+                Some(source.0.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn spi_error<E: spi::Error>(error: E) -> Error {
+    Error::Spi(
+        error.kind(),
+        ErrorSource::new(HalError(format!("{:?}", error))),
+    )
+}
+
+pub(crate) fn gpio_error<E: digital::Error>(error: E) -> Error {
+    Error::Gpio(
+        error.kind(),
+        ErrorSource::new(HalError(format!("{:?}", error))),
+    )
+}
+
+pub(crate) fn io_error(error: std::io::Error) -> Error {
+    Error::Io(error.kind(), ErrorSource::new(error))
+}
@@ -0,0 +1,125 @@
+use crate::error::*;
+use crate::rfm95::{Channel, RFM95};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiDevice;
+use rand::Rng;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/** Time the RSSI needs to settle after the receiver was started, before it is sampled (Semtech's
+ * LoRaMac-node waits as long before sensing a channel) */
+const RSSI_SETTLE_TIME: Duration = Duration::from_millis(1);
+
+/** How the channel is sensed before transmitting */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelSensing {
+    /** Channel activity detection with the modulation used for sending. Only detects LoRa preambles. */
+    ChannelActivity,
+    /** Received signal strength, which detects any transmission. The channel is busy when the RSSI exceeds
+     * the threshold (dBm). */
+    Rssi(i16),
+}
+
+/** Listen-before-talk policy for sending. The channel is sensed for `listen_time` plus a random time of
+ * up to `max_extra_listen_time`; when it is busy, the transmitter backs off for a random time between
+ * `min_backoff` and `max_backoff` and tries again, up to `max_retries` times. */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ListenBeforeTalk {
+    pub sensing: ChannelSensing,
+    pub listen_time: Duration,
+    pub max_extra_listen_time: Duration,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_retries: u32,
+}
+
+impl ListenBeforeTalk {
+    /** Parameters modelled on ETSI EN 300 220-1 (863 - 870 MHz): a listen time of 5 ms, a threshold of
+     * -85 dBm and a pseudo-random additional listen time of up to 5 ms */
+    pub fn etsi_en_300_220() -> ListenBeforeTalk {
+        ListenBeforeTalk {
+            sensing: ChannelSensing::Rssi(-85),
+            listen_time: Duration::from_millis(5),
+            max_extra_listen_time: Duration::from_millis(5),
+            min_backoff: Duration::from_millis(0),
+            max_backoff: Duration::from_millis(5),
+            max_retries: 5,
+        }
+    }
+
+    /** Parameters modelled on ARIB STD-T108 (920 MHz band, Japan): a carrier sense time of 5 ms and a
+     * threshold of -80 dBm */
+    pub fn arib_std_t108() -> ListenBeforeTalk {
+        ListenBeforeTalk {
+            sensing: ChannelSensing::Rssi(-80),
+            listen_time: Duration::from_millis(5),
+            max_extra_listen_time: Duration::ZERO,
+            min_backoff: Duration::from_millis(0),
+            max_backoff: Duration::from_millis(10),
+            max_retries: 5,
+        }
+    }
+
+    /** The listen time plus a random extra listen time of up to `max_extra_listen_time` */
+    pub(crate) fn listen_duration(&self) -> Duration {
+        self.listen_time + random_duration(Duration::ZERO, self.max_extra_listen_time)
+    }
+
+    /** A random backoff time between `min_backoff` and `max_backoff` */
+    pub(crate) fn backoff(&self) -> Duration {
+        random_duration(self.min_backoff, self.max_backoff)
+    }
+}
+
+fn random_duration(min: Duration, max: Duration) -> Duration {
+    if max <= min {
+        return min;
+    }
+    rand::thread_rng().gen_range(min..=max)
+}
+
+impl<SPI, IRQ, CS, RESET> RFM95<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    /** Sense the channel for the listen time of the policy. Returns true when it is clear. The listen time
+     * of RSSI sensing starts once the RSSI has settled. */
+    pub(crate) fn channel_clear(
+        &mut self,
+        policy: &ListenBeforeTalk,
+        channel: Channel,
+    ) -> Result<bool> {
+        let clear = match policy.sensing {
+            ChannelSensing::ChannelActivity => {
+                let deadline = Instant::now() + policy.listen_duration();
+                loop {
+                    if self.detect_channel_activity_on(channel, self.modulation())? {
+                        break false;
+                    }
+                    if Instant::now() >= deadline {
+                        break true;
+                    }
+                }
+            }
+            ChannelSensing::Rssi(threshold) => {
+                self.start_receive(channel, self.modulation(), true)?;
+                thread::sleep(RSSI_SETTLE_TIME);
+                let deadline = Instant::now() + policy.listen_duration();
+                let clear = loop {
+                    if self.get_rssi()? > threshold {
+                        break false;
+                    }
+                    if Instant::now() >= deadline {
+                        break true;
+                    }
+                };
+                self.standby()?;
+                clear
+            }
+        };
+        Ok(clear)
+    }
+}
@@ -1,6 +1,45 @@
+mod airtime;
+#[cfg(feature = "async")]
+mod async_rfm95;
+#[cfg(feature = "gpio-cdev")]
+mod cdev;
+mod channel_selection;
+pub mod constants;
+mod continuous;
+mod duty_cycle;
+mod error;
+mod lbt;
+pub mod lorawan;
+mod modulation;
+mod packet;
+mod pins;
+mod power;
+mod region;
+pub mod registers;
 mod rfm95;
+#[cfg(feature = "rppal")]
+mod rpi;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 #[macro_use]
 extern crate bitflags;
 
+pub use airtime::*;
+#[cfg(feature = "async")]
+pub use async_rfm95::*;
+#[cfg(feature = "gpio-cdev")]
+pub use cdev::*;
+pub use channel_selection::*;
+pub use continuous::*;
+pub use duty_cycle::*;
+pub use error::*;
+pub use lbt::*;
+pub use modulation::*;
+pub use packet::*;
+pub use pins::*;
+pub use power::*;
+pub use region::*;
 pub use rfm95::*;
+#[cfg(feature = "rppal")]
+pub use rpi::*;
@@ -0,0 +1,227 @@
+use crate::error::*;
+use crate::rfm95::{Band, Channel};
+
+/** Number of channels a device supports in bands with a dynamic channel plan, of which the first few are
+ * the default channels of the band */
+const MAX_DYNAMIC_CHANNELS: usize = 16;
+
+/** Number of channel frequencies in a CFList of type 0, and the length of each (in units of 100 Hz) */
+const CF_LIST_FREQUENCIES: usize = 5;
+const CF_LIST_FREQUENCY_LENGTH: usize = 3;
+/** Number of 16 bit channel masks in a CFList of type 1 */
+const CF_LIST_MASKS: usize = 5;
+
+/** Number of 125 kHz channels in bands with 500 kHz uplink channels (US902, AU915), whose LinkADRReq
+ * channel mask controls treat the 500 kHz channels 64 - 71 separately */
+const NARROW_CHANNELS: usize = 64;
+const WIDE_CHANNELS: usize = 8;
+
+/** CFListType, the last byte of a CFList */
+const CF_LIST_TYPE_FREQUENCIES: u8 = 0;
+const CF_LIST_TYPE_CHANNEL_MASK: u8 = 1;
+
+/** An uplink channel of a LoRaWAN device */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UplinkChannel {
+    /** Center frequency (Hz) */
+    pub frequency: u32,
+    pub min_data_rate: u8,
+    pub max_data_rate: u8,
+}
+
+impl UplinkChannel {
+    pub fn supports(&self, data_rate: u8) -> bool {
+        (self.min_data_rate..=self.max_data_rate).contains(&data_rate)
+    }
+}
+
+/** The uplink channels a LoRaWAN device uses, numbered as in the regional parameters, and which of them
+ * are enabled. Bands with a fixed channel plan (`RegionalParameters::sub_bands` > 0) have all their
+ * channels defined; in the other bands only the default channels are defined at first, and up to 16
+ * channels in total can be added by the network. */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelPlan {
+    band: Band,
+    channels: Vec<Option<UplinkChannel>>,
+    /** Bit n is set when channel n is enabled */
+    mask: u128,
+}
+
+impl ChannelPlan {
+    /** Channel plan with all channels of the band defined in the regional parameters enabled. In bands
+     * with a fixed channel plan, use `set_mask` with `RegionalParameters::sub_band_mask` to restrict the
+     * device to the sub-band its network uses. */
+    pub fn new(band: Band) -> ChannelPlan {
+        let parameters = band.parameters();
+        let count = parameters.uplink_channel_count();
+        let mut channels: Vec<Option<UplinkChannel>> = (0..count)
+            .map(|n| {
+                let data_rates = parameters.uplink_data_rates(n).unwrap();
+                Some(UplinkChannel {
+                    frequency: parameters.uplink_frequency(n).unwrap(),
+                    min_data_rate: *data_rates.start(),
+                    max_data_rate: *data_rates.end(),
+                })
+            })
+            .collect();
+        if parameters.sub_bands == 0 {
+            channels.resize(MAX_DYNAMIC_CHANNELS, None);
+        }
+        ChannelPlan {
+            band,
+            channels,
+            mask: mask_below(count as usize),
+        }
+    }
+
+    pub fn band(&self) -> Band {
+        self.band
+    }
+
+    /** Number of channels the plan can hold, whether defined or not */
+    pub fn capacity(&self) -> usize {
+        self.channels.len()
+    }
+
+    /** The channel, or `None` when it is not defined */
+    pub fn channel(&self, number: u8) -> Option<UplinkChannel> {
+        self.channels.get(number as usize).copied().flatten()
+    }
+
+    /** Define, replace or (with `None`) remove a channel of a dynamic channel plan. A defined channel is
+     * enabled. The default channels of the band cannot be changed, and channels cannot be added to a fixed
+     * channel plan. */
+    pub fn set_channel(&mut self, number: u8, channel: Option<UplinkChannel>) -> Result<()> {
+        let parameters = self.band.parameters();
+        if parameters.sub_bands > 0
+            || number < parameters.uplink_channel_count()
+            || number as usize >= self.channels.len()
+        {
+            return Err(Error::InvalidChannel(Channel::Uplink(number), self.band));
+        }
+        self.channels[number as usize] = channel;
+        if channel.is_some() {
+            self.mask |= 1 << number;
+        } else {
+            self.mask &= !(1 << number);
+        }
+        Ok(())
+    }
+
+    /** Enabled channels, with bit n set for channel n */
+    pub fn mask(&self) -> u128 {
+        self.mask
+    }
+
+    /** Enable exactly the channels whose bits are set. Bits of channels beyond the capacity are ignored;
+     * undefined channels stay unused even when enabled. */
+    pub fn set_mask(&mut self, mask: u128) {
+        self.mask = mask & mask_below(self.channels.len());
+    }
+
+    pub fn is_enabled(&self, number: u8) -> bool {
+        (number as usize) < self.channels.len() && self.mask & (1 << number) != 0
+    }
+
+    /** Numbers of the enabled channels that support the data rate */
+    pub fn enabled_channels(&self, data_rate: u8) -> Vec<u8> {
+        self.channels_in_mask(self.mask, data_rate)
+    }
+
+    /** Numbers of the channels in the mask that are defined and support the data rate */
+    pub(crate) fn channels_in_mask(&self, mask: u128, data_rate: u8) -> Vec<u8> {
+        (0..self.channels.len() as u8)
+            .filter(|n| mask & (1 << n) != 0)
+            .filter(|n| self.channel(*n).is_some_and(|c| c.supports(data_rate)))
+            .collect()
+    }
+
+    /** Mask of the defined channels */
+    pub(crate) fn defined_mask(&self) -> u128 {
+        self.channels
+            .iter()
+            .enumerate()
+            .filter(|(_, channel)| channel.is_some())
+            .fold(0, |mask, (n, _)| mask | 1 << n)
+    }
+
+    /** The mask resulting from applying the ChMask and ChMaskCntl of a LinkADRReq to `mask`, or `None`
+     * when the ChMaskCntl is not defined for the band (LoRaWAN Regional Parameters RP002-1.0, LinkADRReq
+     * command of each region). ChMaskCntl n < capacity / 16 sets the channels 16n to 16n + 15 and 6
+     * enables all channels; bands with 500 kHz channels use 4 - 7 for those. */
+    pub(crate) fn link_adr_mask(&self, mask: u128, control: u8, channel_mask: u16) -> Option<u128> {
+        let blocks = (self.channels.len() / 16) as u8;
+        let wide = self.channels.len() == NARROW_CHANNELS + WIDE_CHANNELS;
+        let wide_mask = |bits: u16| ((bits & 0xFF) as u128) << NARROW_CHANNELS;
+        match control {
+            _ if control < blocks => {
+                let shift = 16 * control as u32;
+                Some((mask & !(0xFFFF << shift)) | (channel_mask as u128) << shift)
+            }
+            // Channels 64 - 71
+            4 if wide => Some((mask & mask_below(NARROW_CHANNELS)) | wide_mask(channel_mask)),
+            // Bits 0 - 7 enable blocks of eight 125 kHz channels, bits 8 - 15 the 500 kHz channels
+            5 if wide => {
+                let narrow = (0..8)
+                    .filter(|block| channel_mask & (1 << block) != 0)
+                    .fold(0u128, |mask, block| mask | 0xFF << (8 * block));
+                Some(narrow | wide_mask(channel_mask >> 8))
+            }
+            // All 125 kHz channels on or off, and ChMask for channels 64 - 71
+            6 if wide => Some(mask_below(NARROW_CHANNELS) | wide_mask(channel_mask)),
+            7 if wide => Some(wide_mask(channel_mask)),
+            6 => Some(self.defined_mask()),
+            _ => None,
+        }
+    }
+
+    /** Apply the CFList of a Join-Accept: additional channels following the default channels (CFListType
+     * 0) or the channel mask (CFListType 1). Unknown types are ignored. */
+    pub(crate) fn apply_cf_list(&mut self, cf_list: &[u8; 16]) {
+        match cf_list[15] {
+            CF_LIST_TYPE_FREQUENCIES if self.band.parameters().sub_bands == 0 => {
+                let parameters = self.band.parameters();
+                let first = parameters.uplink_channel_count();
+                let data_rates = parameters.uplink_data_rates(0).unwrap();
+                for (i, bytes) in cf_list
+                    .chunks(CF_LIST_FREQUENCY_LENGTH)
+                    .take(CF_LIST_FREQUENCIES)
+                    .enumerate()
+                {
+                    let frequency = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) * 100;
+                    // A frequency of 0 leaves the channel undefined
+                    let channel = if frequency == 0 {
+                        None
+                    } else {
+                        Some(UplinkChannel {
+                            frequency,
+                            min_data_rate: *data_rates.start(),
+                            max_data_rate: *data_rates.end(),
+                        })
+                    };
+                    // Only fails beyond the capacity of the plan
+                    let _ = self.set_channel(first + i as u8, channel);
+                }
+            }
+            CF_LIST_TYPE_CHANNEL_MASK if self.band.parameters().sub_bands > 0 => {
+                let mask = cf_list.chunks(2).take(CF_LIST_MASKS).enumerate().fold(
+                    0u128,
+                    |mask, (i, bytes)| {
+                        mask | (u16::from_le_bytes([bytes[0], bytes[1]]) as u128) << (16 * i)
+                    },
+                );
+                self.set_mask(mask);
+            }
+            _ => {}
+        }
+    }
+}
+
+/** Mask with the lowest `count` bits set */
+fn mask_below(count: usize) -> u128 {
+    if count >= 128 {
+        u128::MAX
+    } else {
+        (1 << count) - 1
+    }
+}
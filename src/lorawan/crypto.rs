@@ -0,0 +1,184 @@
+//! LoRaWAN 1.0.x security: message integrity codes, FRMPayload encryption, Join-Accept decryption and
+//! session key derivation (LoRaWAN specification 1.0.4, sections 4.3.3, 4.4 and 6.2).
+use crate::error::*;
+use crate::lorawan::frame::{Direction, Frame, PhyPayload};
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::Aes128;
+use cmac::{Cmac, Mac};
+
+/** AES-128 key */
+pub type Key = [u8; 16];
+
+const BLOCK_LENGTH: usize = 16;
+const MIC_LENGTH: usize = 4;
+
+/** First byte of the B0 block for data frame MICs and of the Ai blocks for FRMPayload encryption */
+const B0_BLOCK: u8 = 0x49;
+const A_BLOCK: u8 = 0x01;
+
+/** Session keys of an activated device */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SessionKeys {
+    /** Network session key, for MICs and FPort 0 payloads */
+    pub nwk_s_key: Key,
+    /** Application session key, for the payloads of all other ports */
+    pub app_s_key: Key,
+}
+
+impl SessionKeys {
+    /** Derive the session keys after an over-the-air activation from the AppKey, the JoinNonce (AppNonce)
+     * and NetID of the Join-Accept and the DevNonce of the Join-Request */
+    pub fn derive(app_key: &Key, join_nonce: u32, net_id: u32, dev_nonce: u16) -> SessionKeys {
+        let derive = |prefix: u8| {
+            let mut block = [0u8; BLOCK_LENGTH];
+            block[0] = prefix;
+            block[1..4].copy_from_slice(&join_nonce.to_le_bytes()[..3]);
+            block[4..7].copy_from_slice(&net_id.to_le_bytes()[..3]);
+            block[7..9].copy_from_slice(&dev_nonce.to_le_bytes());
+            aes_encrypt(app_key, &mut block);
+            block
+        };
+        SessionKeys {
+            nwk_s_key: derive(0x01),
+            app_s_key: derive(0x02),
+        }
+    }
+}
+
+/** MIC of a data frame, computed with the NwkSKey. `fcnt` is the full 32 bit frame counter, of which the
+ * frame only carries the lower 16 bits. */
+pub fn data_mic(nwk_s_key: &Key, phy: &PhyPayload, fcnt: u32) -> Result<[u8; 4]> {
+    let data = match &phy.frame {
+        Frame::Data(data) => data,
+        _ => return Err(Error::InvalidFrame("not a data frame")),
+    };
+    let message = message(phy)?;
+    let mut b0 = [0u8; BLOCK_LENGTH];
+    b0[0] = B0_BLOCK;
+    b0[5] = direction_byte(data.direction);
+    b0[6..10].copy_from_slice(&data.dev_addr.to_le_bytes());
+    b0[10..14].copy_from_slice(&fcnt.to_le_bytes());
+    b0[15] = message.len() as u8;
+    Ok(cmac(nwk_s_key, &[&b0, &message]))
+}
+
+/** MIC of a Join-Request or decrypted Join-Accept, computed with the AppKey */
+pub fn join_mic(app_key: &Key, phy: &PhyPayload) -> Result<[u8; 4]> {
+    match &phy.frame {
+        Frame::JoinRequest(_) | Frame::JoinAccept(_) => Ok(cmac(app_key, &[&message(phy)?])),
+        Frame::Data(_) => Err(Error::InvalidFrame("not a join frame")),
+    }
+}
+
+/** Check the MIC of a data frame, failing with `Error::InvalidMic` when it does not match */
+pub fn verify_data_mic(nwk_s_key: &Key, phy: &PhyPayload, fcnt: u32) -> Result<()> {
+    check_mic(phy, data_mic(nwk_s_key, phy, fcnt)?)
+}
+
+/** Check the MIC of a Join-Request or decrypted Join-Accept, failing with `Error::InvalidMic` when it
+ * does not match */
+pub fn verify_join_mic(app_key: &Key, phy: &PhyPayload) -> Result<()> {
+    check_mic(phy, join_mic(app_key, phy)?)
+}
+
+/** Encrypt an FRMPayload. The key is the NwkSKey for FPort 0 and the AppSKey for all other ports. */
+pub fn encrypt_frm_payload(
+    key: &Key,
+    direction: Direction,
+    dev_addr: u32,
+    fcnt: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut a = [0u8; BLOCK_LENGTH];
+    a[0] = A_BLOCK;
+    a[5] = direction_byte(direction);
+    a[6..10].copy_from_slice(&dev_addr.to_le_bytes());
+    a[10..14].copy_from_slice(&fcnt.to_le_bytes());
+
+    let mut encrypted = Vec::with_capacity(payload.len());
+    for (i, chunk) in payload.chunks(BLOCK_LENGTH).enumerate() {
+        a[15] = i as u8 + 1;
+        let mut s = a;
+        aes_encrypt(key, &mut s);
+        encrypted.extend(chunk.iter().zip(s.iter()).map(|(byte, s)| byte ^ s));
+    }
+    encrypted
+}
+
+/** Decrypt an FRMPayload; the encryption is its own inverse */
+pub fn decrypt_frm_payload(
+    key: &Key,
+    direction: Direction,
+    dev_addr: u32,
+    fcnt: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    encrypt_frm_payload(key, direction, dev_addr, fcnt, payload)
+}
+
+/** Decrypt a received Join-Accept PHYPayload with the AppKey, so that it can be parsed with
+ * `PhyPayload::parse`. The network server encrypts it with an AES decrypt operation, so the device
+ * decrypts it by encrypting. */
+pub fn decrypt_join_accept(app_key: &Key, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut decrypted = join_accept_blocks(bytes)?;
+    for block in decrypted[1..].chunks_mut(BLOCK_LENGTH) {
+        aes_encrypt(app_key, block);
+    }
+    Ok(decrypted)
+}
+
+/** Encrypt a Join-Accept PHYPayload, including its MIC, as the network server does */
+pub fn encrypt_join_accept(app_key: &Key, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encrypted = join_accept_blocks(bytes)?;
+    let cipher = Aes128::new(GenericArray::from_slice(app_key));
+    for block in encrypted[1..].chunks_mut(BLOCK_LENGTH) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+    Ok(encrypted)
+}
+
+/** Everything after MHDR has to be one or two AES blocks */
+fn join_accept_blocks(bytes: &[u8]) -> Result<Vec<u8>> {
+    match bytes.len() {
+        17 | 33 => Ok(bytes.to_vec()),
+        _ => Err(Error::InvalidFrame("invalid Join-Accept length")),
+    }
+}
+
+/** The encoded frame without its MIC */
+fn message(phy: &PhyPayload) -> Result<Vec<u8>> {
+    let mut bytes = phy.to_bytes()?;
+    bytes.truncate(bytes.len() - MIC_LENGTH);
+    Ok(bytes)
+}
+
+fn check_mic(phy: &PhyPayload, mic: [u8; 4]) -> Result<()> {
+    if phy.mic == mic {
+        Ok(())
+    } else {
+        Err(Error::InvalidMic)
+    }
+}
+
+fn direction_byte(direction: Direction) -> u8 {
+    match direction {
+        Direction::Uplink => 0,
+        Direction::Downlink => 1,
+    }
+}
+
+fn aes_encrypt(key: &Key, block: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    cipher.encrypt_block(GenericArray::from_mut_slice(block));
+}
+
+/** First four bytes of the AES-CMAC over the concatenated parts */
+fn cmac(key: &Key, parts: &[&[u8]]) -> [u8; 4] {
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(key).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+    let tag = mac.finalize().into_bytes();
+    [tag[0], tag[1], tag[2], tag[3]]
+}
@@ -0,0 +1,921 @@
+use crate::airtime::PREAMBLE_LENGTH;
+use crate::constants::{HF_FREQUENCY_RANGE, LF_FREQUENCY_RANGE};
+use crate::duty_cycle::{Clock, SystemClock};
+use crate::error::*;
+use crate::lorawan::channel_plan::{ChannelPlan, UplinkChannel};
+use crate::lorawan::crypto::*;
+use crate::lorawan::frame::*;
+use crate::lorawan::mac::*;
+use crate::lorawan::session_store::{DeviceState, SessionStore};
+use crate::modulation::LoRaModulation;
+use crate::packet::ReceivedPacket;
+use crate::power::PaOutput;
+use crate::rfm95::{Band, Channel, MAX_PAYLOAD_LENGTH, MAX_SYMBOL_TIMEOUT, RFM95};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiDevice;
+use std::time::{Duration, Instant};
+
+/** Delay between the end of a Join-Request and the RX1 window (JOIN_ACCEPT_DELAY1) */
+const JOIN_ACCEPT_DELAY1: Duration = Duration::from_secs(5);
+/** Delay between the RX1 and RX2 windows; RECEIVE_DELAY2 and JOIN_ACCEPT_DELAY2 are one second after
+ * RECEIVE_DELAY1 and JOIN_ACCEPT_DELAY1 */
+const RX2_DELAY: Duration = Duration::from_secs(1);
+/** RECEIVE_DELAY1 in seconds, used until the network configures another delay */
+const DEFAULT_RX_DELAY: u8 = 1;
+
+/** The receiver is started this much before a receive window opens, and keeps listening for a preamble
+ * until this much after the window opened, to allow for timing errors on either side */
+const RX_WINDOW_MARGIN: Duration = Duration::from_millis(20);
+
+/** Largest gap between two downlink frame counters that is accepted (MAX_FCNT_GAP) */
+const MAX_FCNT_GAP: u32 = 16_384;
+
+/** FPorts 1 - 223 carry application data */
+const MAX_APPLICATION_PORT: u8 = 223;
+/** DevAddr, FCtrl and FCnt of the frame header, and FPort */
+const FHDR_FPORT_LENGTH: usize = 8;
+/** MHDR and MIC */
+const MHDR_MIC_LENGTH: usize = 5;
+
+/** DataRate or TXPower of a LinkADRReq that keeps the current value */
+const LINK_ADR_KEEP: u8 = 0x0F;
+/** Output power range (dBm) of PA_BOOST without high power mode, which TXPower indices are clamped to */
+const MIN_TX_POWER: f32 = 2.0;
+const MAX_TX_POWER: f32 = 17.0;
+/** Battery level of DevStatusAns when the device cannot measure it */
+const BATTERY_LEVEL_UNKNOWN: u8 = 255;
+
+/** Root keys and identifiers of a device for over-the-air activation (OTAA) */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OtaaCredentials {
+    pub dev_eui: u64,
+    /** JoinEUI (AppEUI in LoRaWAN 1.0.2) */
+    pub join_eui: u64,
+    pub app_key: Key,
+}
+
+/** State of an activated device that has to be kept for as long as the session lasts */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub dev_addr: u32,
+    pub keys: SessionKeys,
+    /** Frame counter of the next uplink */
+    pub fcnt_up: u32,
+    /** Lowest frame counter accepted for the next downlink */
+    pub fcnt_down: u32,
+    /** Offset between the uplink and RX1 data rate */
+    pub rx1_dr_offset: u8,
+    /** Frequency (Hz) of the RX2 window */
+    pub rx2_frequency: u32,
+    /** Data rate of the RX2 window */
+    pub rx2_data_rate: u8,
+    /** Delay between the end of an uplink and the RX1 window in seconds (1 - 15) */
+    pub rx_delay: u8,
+    /** TXPower index set by the network (LinkADRReq); `None` keeps the output power of the driver */
+    pub tx_power: Option<u8>,
+    /** Number of transmissions of every unconfirmed uplink (NbTrans, 1 - 15) */
+    pub nb_trans: u8,
+    /** Aggregated duty cycle limit 1 / 2^`max_duty_cycle` set by the network (DutyCycleReq); 0 means no
+     * limit beyond that of the band */
+    pub max_duty_cycle: u8,
+}
+
+impl Session {
+    /** Session with both frame counters at 0 and the default parameters of the band. For a device activated
+     * by personalization (ABP), those have to match what the network has been told about the device. */
+    pub fn new(band: Band, dev_addr: u32, keys: SessionKeys) -> Session {
+        let parameters = band.parameters();
+        Session {
+            dev_addr,
+            keys,
+            fcnt_up: 0,
+            fcnt_down: 0,
+            rx1_dr_offset: 0,
+            rx2_frequency: parameters.rx2_frequency,
+            rx2_data_rate: parameters.rx2_data_rate,
+            rx_delay: DEFAULT_RX_DELAY,
+            tx_power: None,
+            nb_trans: 1,
+            max_duty_cycle: 0,
+        }
+    }
+
+    /** Full frame counter of a downlink from the 16 bits in the frame, or `None` when the frame was
+     * received before or the counter lies too far ahead */
+    fn downlink_fcnt(&self, fcnt: u16) -> Option<u32> {
+        let mut full = (self.fcnt_down & !0xFFFF) | fcnt as u32;
+        if full < self.fcnt_down {
+            full = full.wrapping_add(0x1_0000);
+        }
+        Some(full).filter(|full| full.wrapping_sub(self.fcnt_down) < MAX_FCNT_GAP)
+    }
+}
+
+/** Receive window after an uplink */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RxWindow {
+    Rx1,
+    Rx2,
+}
+
+/** Downlink received in response to an uplink */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Downlink {
+    /** `None` when the frame carries no FRMPayload, e.g. when it only acknowledges the uplink */
+    pub port: Option<u8>,
+    /** Decrypted FRMPayload; empty for FPort 0, whose payload holds MAC commands */
+    pub data: Vec<u8>,
+    /** MAC commands of the frame, from FOpts or the FRMPayload of FPort 0. The device has already
+     * processed them. */
+    pub commands: Vec<DownlinkCommand>,
+    /** The network acknowledged the (confirmed) uplink */
+    pub ack: bool,
+    /** The network has more downlinks queued, which it sends after the next uplink */
+    pub pending: bool,
+    /** The downlink is confirmed; the device acknowledges it with the next uplink */
+    pub confirmed: bool,
+    pub fcnt: u32,
+    pub window: RxWindow,
+    /** Packet RSSI (dBm) */
+    pub rssi: i16,
+    /** Signal-to-noise ratio (dB) */
+    pub snr: f32,
+}
+
+/** LoRaWAN 1.0.x Class A end device on top of an RFM95 driver.
+ *
+ * Every uplink is sent on an enabled channel of the channel plan that supports the data rate, picked by
+ * the channel selector of the driver, and is followed by the two receive windows: RX1 after the RX delay
+ * on the downlink channel of the uplink at the RX1 data rate, and RX2 a second later on the frequency and
+ * data rate of the session. The receiver inverts IQ for both windows. The duty-cycle tracker and
+ * listen-before-talk policy of the driver apply to every uplink.
+ *
+ * MAC commands in downlinks are applied to the channel plan, data rate and session as they arrive, and
+ * their answers are sent in the FOpts of the following uplinks. With a session store (see
+ * `set_session_store`), the device keeps its session across restarts. */
+pub struct Device<SPI, IRQ, CS, RESET> {
+    radio: RFM95<SPI, IRQ, CS, RESET>,
+    clock: Box<dyn Clock + Send>,
+    channels: ChannelPlan,
+    data_rate: u8,
+    dev_nonce: u16,
+    session: Option<Session>,
+    /** A confirmed downlink is acknowledged with the next uplink */
+    ack_pending: bool,
+    adr: bool,
+    /** MAC commands for the next uplink */
+    uplink_commands: Vec<UplinkCommand>,
+    battery_level: Box<dyn FnMut() -> u8 + Send>,
+    /** GPS time of the last DeviceTimeAns and the end of the uplink it refers to */
+    device_time: Option<(Duration, Instant)>,
+    last_tx_end: Option<Instant>,
+    /** Earliest next uplink under the aggregated duty cycle of the session */
+    next_uplink_at: Option<Instant>,
+    store: Option<Box<dyn SessionStore + Send>>,
+}
+
+impl<SPI, IRQ, CS, RESET> Device<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    /** Create a device on top of a driver that has been reset, sending uplinks at the data rate (DR number
+     * of the band of the driver). The device is not activated yet; see `join` and `activate_abp`. */
+    pub fn new(
+        radio: RFM95<SPI, IRQ, CS, RESET>,
+        data_rate: u8,
+    ) -> Result<Device<SPI, IRQ, CS, RESET>> {
+        Device::with_clock(radio, data_rate, SystemClock)
+    }
+
+    /** Create a device that times its receive windows with the given clock */
+    pub fn with_clock<C: Clock + Send + 'static>(
+        radio: RFM95<SPI, IRQ, CS, RESET>,
+        data_rate: u8,
+        clock: C,
+    ) -> Result<Device<SPI, IRQ, CS, RESET>> {
+        let band = radio.band();
+        band.parameters().data_rate(data_rate)?;
+        Ok(Device {
+            radio,
+            clock: Box::new(clock),
+            channels: ChannelPlan::new(band),
+            data_rate,
+            dev_nonce: 0,
+            session: None,
+            ack_pending: false,
+            adr: false,
+            uplink_commands: Vec::new(),
+            battery_level: Box::new(|| BATTERY_LEVEL_UNKNOWN),
+            device_time: None,
+            last_tx_end: None,
+            next_uplink_at: None,
+            store: None,
+        })
+    }
+
+    /** The underlying driver. The device sets the frequency, modulation and IQ inversion for every uplink
+     * and receive window. */
+    pub fn radio(&mut self) -> &mut RFM95<SPI, IRQ, CS, RESET> {
+        &mut self.radio
+    }
+
+    pub fn release(self) -> RFM95<SPI, IRQ, CS, RESET> {
+        self.radio
+    }
+
+    pub fn channel_plan(&self) -> &ChannelPlan {
+        &self.channels
+    }
+
+    pub fn channel_plan_mut(&mut self) -> &mut ChannelPlan {
+        &mut self.channels
+    }
+
+    /** Data rate (DR number) of uplinks */
+    pub fn data_rate(&self) -> u8 {
+        self.data_rate
+    }
+
+    pub fn set_data_rate(&mut self, data_rate: u8) -> Result<()> {
+        self.radio.band().parameters().data_rate(data_rate)?;
+        self.data_rate = data_rate;
+        Ok(())
+    }
+
+    /** DevNonce of the next Join-Request. It counts up from 0 with every Join-Request and must never be
+     * reused with the same JoinEUI, so it has to be kept across restarts. */
+    pub fn dev_nonce(&self) -> u16 {
+        self.dev_nonce
+    }
+
+    pub fn set_dev_nonce(&mut self, dev_nonce: u16) {
+        self.dev_nonce = dev_nonce;
+    }
+
+    /** Current session; `None` until the device is activated */
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    /** The state that has to be kept across restarts to continue the session */
+    pub fn state(&self) -> DeviceState {
+        DeviceState {
+            dev_nonce: self.dev_nonce,
+            data_rate: self.data_rate,
+            channels: self.channels.clone(),
+            session: self.session.clone(),
+        }
+    }
+
+    /** Continue with a state saved by an earlier run, replacing the current session. Fails with
+     * `Error::InvalidSessionState` when the state belongs to another band. */
+    pub fn restore(&mut self, state: DeviceState) -> Result<()> {
+        let band = self.radio.band();
+        if state.channels.band() != band {
+            return Err(Error::InvalidSessionState("saved for another band"));
+        }
+        band.parameters()
+            .data_rate(state.data_rate)
+            .map_err(|_| Error::InvalidSessionState("invalid data rate"))?;
+        self.dev_nonce = state.dev_nonce;
+        self.data_rate = state.data_rate;
+        self.channels = state.channels;
+        match state.session {
+            Some(session) => self.start_session(session),
+            None => self.session = None,
+        }
+        Ok(())
+    }
+
+    /** Keep the state of the device in the store. A state saved there earlier is restored (see `restore`);
+     * returns true when that includes a session, so that the device can send uplinks without joining
+     * again. Otherwise the current state is saved right away.
+     *
+     * From then on, the state is saved before every Join-Request and uplink, so that neither a DevNonce nor
+     * an uplink frame counter is ever reused, and after every successful join and received downlink. When
+     * saving fails, nothing is transmitted and the error is returned. */
+    pub fn set_session_store<S: SessionStore + Send + 'static>(
+        &mut self,
+        store: S,
+    ) -> Result<bool> {
+        let mut store = Box::new(store);
+        let restored = match store.load()? {
+            Some(state) => {
+                self.restore(state)?;
+                self.session.is_some()
+            }
+            None => {
+                store.save(&self.state())?;
+                false
+            }
+        };
+        self.store = Some(store);
+        Ok(restored)
+    }
+
+    /** Whether uplinks set the ADR bit, allowing the network to control the data rate, TX power and
+     * NbTrans with LinkADRReq. The device accepts LinkADRReq either way. */
+    pub fn adr(&self) -> bool {
+        self.adr
+    }
+
+    pub fn set_adr(&mut self, adr: bool) {
+        self.adr = adr;
+    }
+
+    /** Set the function that measures the battery level for DevStatusAns: 0 when the device is on external
+     * power, 1 (empty) to 254 (full), or 255 when it cannot measure it. Without one the level is reported
+     * as unknown. */
+    pub fn set_battery_level<F: FnMut() -> u8 + Send + 'static>(&mut self, battery_level: F) {
+        self.battery_level = Box::new(battery_level);
+    }
+
+    /** MAC commands waiting for the next uplink */
+    pub fn uplink_commands(&self) -> &[UplinkCommand] {
+        &self.uplink_commands
+    }
+
+    /** Ask the network for the current time with the next uplink (DeviceTimeReq); see `gps_time` */
+    pub fn request_device_time(&mut self) {
+        if !self.uplink_commands.contains(&UplinkCommand::DeviceTimeReq) {
+            self.uplink_commands.push(UplinkCommand::DeviceTimeReq);
+        }
+    }
+
+    /** Time since the GPS epoch (1980-01-06 00:00:00 UTC, without leap seconds) from the last DeviceTimeAns,
+     * advanced by the clock of the device since; `None` until the network answered a DeviceTimeReq */
+    pub fn gps_time(&self) -> Option<Duration> {
+        self.device_time
+            .map(|(gps_time, at)| gps_time + self.clock.now().saturating_duration_since(at))
+    }
+
+    /** Activate the device by personalization (ABP), starting a session with both frame counters at 0. The
+     * session is saved to the session store with the first uplink. */
+    pub fn activate_abp(&mut self, dev_addr: u32, keys: SessionKeys) {
+        self.start_session(Session::new(self.radio.band(), dev_addr, keys));
+    }
+
+    /** Join a network by over-the-air activation (OTAA): send a Join-Request and wait for the Join-Accept
+     * in the two receive windows that follow it. Replaces the current session on success. Returns
+     * `Error::ReceiveTimedOut` when no valid Join-Accept arrived; the caller should then retry later, as
+     * required by the duty cycle. */
+    pub fn join(&mut self, credentials: &OtaaCredentials) -> Result<()> {
+        let dev_nonce = self.dev_nonce;
+        self.dev_nonce = dev_nonce.wrapping_add(1);
+        self.save_state()?;
+
+        let mut request = PhyPayload::new(Frame::JoinRequest(JoinRequest {
+            join_eui: credentials.join_eui,
+            dev_eui: credentials.dev_eui,
+            dev_nonce,
+        }));
+        request.mic = join_mic(&credentials.app_key, &request)?;
+        let (channel, tx_end) = self.transmit(&request.to_bytes()?)?;
+
+        let parameters = self.radio.band().parameters();
+        let rx1 = (
+            self.rx1_frequency(channel)?,
+            parameters.rx1_data_rate(self.data_rate, 0)?,
+        );
+        let rx2 = (parameters.rx2_frequency, parameters.rx2_data_rate);
+        let accept = self
+            .receive_windows(tx_end, JOIN_ACCEPT_DELAY1, rx1, rx2, |_, packet, _| {
+                accept_join(&credentials.app_key, packet)
+            })?
+            .ok_or(Error::ReceiveTimedOut)?;
+
+        if let Some(cf_list) = &accept.cf_list {
+            self.channels.apply_cf_list(cf_list);
+        }
+        let keys = SessionKeys::derive(
+            &credentials.app_key,
+            accept.join_nonce,
+            accept.net_id,
+            dev_nonce,
+        );
+        self.start_session(Session {
+            rx1_dr_offset: accept.rx1_dr_offset,
+            rx2_data_rate: accept.rx2_data_rate,
+            rx_delay: (accept.rx_delay & 0x0F).max(DEFAULT_RX_DELAY),
+            ..Session::new(parameters.band, accept.dev_addr, keys)
+        });
+        self.save_state()
+    }
+
+    /** Save the state to the session store, if there is one */
+    fn save_state(&mut self) -> Result<()> {
+        if self.store.is_none() {
+            return Ok(());
+        }
+        let state = self.state();
+        self.store.as_mut().unwrap().save(&state)
+    }
+
+    /** Replace the session. Answers meant for the previous session are dropped, a DeviceTimeReq is kept. */
+    fn start_session(&mut self, session: Session) {
+        self.session = Some(session);
+        self.ack_pending = false;
+        self.uplink_commands
+            .retain(|command| *command == UplinkCommand::DeviceTimeReq);
+        self.next_uplink_at = None;
+    }
+
+    /** Send application data on an FPort (1 - 223) and listen for a downlink in the receive windows that
+     * follow. Returns `None` when nothing was received for this device. Queued MAC command answers are sent
+     * along in FOpts as far as they fit next to the data.
+     *
+     * An unconfirmed uplink is transmitted up to NbTrans times (see `Session::nb_trans`) until a downlink
+     * arrives. A confirmed uplink is not repeated automatically; check `Downlink::ack` and send again when
+     * it is missing. Fails with `Error::DutyCycleExceeded` while the aggregated duty cycle set by the
+     * network does not allow another uplink yet. */
+    pub fn send_uplink(
+        &mut self,
+        port: u8,
+        data: &[u8],
+        confirmed: bool,
+    ) -> Result<Option<Downlink>> {
+        if port == 0 || port > MAX_APPLICATION_PORT {
+            return Err(Error::InvalidPort(port));
+        }
+        let parameters = self.radio.band().parameters();
+        let max_mac_payload =
+            parameters.max_mac_payload(self.data_rate, parameters.max_dwell_time.is_some())?;
+        let max_length = (max_mac_payload as usize).min(MAX_PAYLOAD_LENGTH - MHDR_MIC_LENGTH)
+            - FHDR_FPORT_LENGTH;
+        if data.len() > max_length {
+            return Err(Error::PayloadTooLong(data.len(), max_length));
+        }
+
+        let session = self.session.as_mut().ok_or(Error::NotActivated)?;
+        let now = self.clock.now();
+        if let Some(next) = self.next_uplink_at.filter(|next| *next > now) {
+            return Err(Error::DutyCycleExceeded(Some(next)));
+        }
+
+        let room = (max_length - data.len()).min(MAX_FOPTS_LENGTH);
+        let mut fopts = Vec::new();
+        let mut sent_commands = 0;
+        for command in &self.uplink_commands {
+            let bytes = command.to_bytes();
+            if fopts.len() + bytes.len() > room {
+                break;
+            }
+            fopts.extend(bytes);
+            sent_commands += 1;
+        }
+
+        // Counted even when the transmission fails: skipping a counter is harmless, reusing one is not
+        let fcnt = session.fcnt_up;
+        session.fcnt_up = fcnt.wrapping_add(1);
+        let mut uplink = PhyPayload::new(Frame::Data(DataFrame {
+            direction: Direction::Uplink,
+            confirmed,
+            dev_addr: session.dev_addr,
+            fctrl: FCtrl {
+                adr: self.adr,
+                ack: self.ack_pending,
+                ..FCtrl::default()
+            },
+            fcnt: fcnt as u16,
+            fopts,
+            fport: Some(port),
+            frm_payload: encrypt_frm_payload(
+                &session.keys.app_s_key,
+                Direction::Uplink,
+                session.dev_addr,
+                fcnt,
+                data,
+            ),
+        }));
+        uplink.mic = data_mic(&session.keys.nwk_s_key, &uplink, fcnt)?;
+        let session = session.clone();
+        let packet = uplink.to_bytes()?;
+        self.save_state()?;
+
+        let transmissions = if confirmed {
+            1
+        } else {
+            session.nb_trans.max(1)
+        };
+        for transmission in 0..transmissions {
+            // Repetitions are skipped rather than delayed when the aggregated duty cycle is used up
+            if transmission > 0
+                && self
+                    .next_uplink_at
+                    .is_some_and(|next| next > self.clock.now())
+            {
+                break;
+            }
+            let (channel, tx_end) = self.transmit(&packet)?;
+            if transmission == 0 {
+                self.ack_pending = false;
+                // Sticky answers stay queued until a downlink shows that the network received them
+                let mut index = 0;
+                self.uplink_commands.retain(|command| {
+                    index += 1;
+                    index > sent_commands || command.is_sticky()
+                });
+            }
+
+            let rx1 = (
+                self.rx1_frequency(channel)?,
+                parameters.rx1_data_rate(self.data_rate, session.rx1_dr_offset)?,
+            );
+            let rx2 = (session.rx2_frequency, session.rx2_data_rate);
+            let rx1_delay = Duration::from_secs(session.rx_delay as u64);
+            let downlink =
+                self.receive_windows(tx_end, rx1_delay, rx1, rx2, |device, packet, window| {
+                    device.accept_downlink(packet, window)
+                })?;
+            if downlink.is_some() {
+                self.save_state()?;
+                return Ok(downlink);
+            }
+        }
+        Ok(None)
+    }
+
+    /** Transmit a packet at the current data rate on an enabled channel, returning the number of the
+     * channel and the time the transmission ended */
+    fn transmit(&mut self, packet: &[u8]) -> Result<(u8, Instant)> {
+        let band = self.radio.band();
+        let channels = self.channels.enabled_channels(self.data_rate);
+        if channels.is_empty() {
+            return Err(Error::InvalidDataRate(self.data_rate, band));
+        }
+        let number = channels[self.radio.select_channel(channels.len())?];
+        let frequency = self.channels.channel(number).unwrap().frequency;
+
+        let parameters = band.parameters();
+        let modulation = parameters.data_rate(self.data_rate)?.modulation();
+        self.radio.set_modulation(modulation);
+        self.radio.set_frequency_hz(frequency)?;
+        let session = self.session.as_ref();
+        if let Some(tx_power) = session.and_then(|session| session.tx_power) {
+            let dbm =
+                (parameters.max_eirp - 2.0 * tx_power as f32).clamp(MIN_TX_POWER, MAX_TX_POWER);
+            self.radio
+                .set_tx_power(dbm.round() as i8, PaOutput::PaBoost)?;
+        }
+        let max_duty_cycle = session.map_or(0, |session| session.max_duty_cycle);
+        self.radio.send_packet(packet)?;
+
+        let tx_end = self.clock.now();
+        self.last_tx_end = Some(tx_end);
+        self.next_uplink_at = if max_duty_cycle > 0 {
+            // Off time after a transmission of airtime t at a duty cycle of 1 / 2^n: t * (2^n - 1)
+            let off_time = (1u32 << max_duty_cycle.min(15)) - 1;
+            Some(tx_end + self.radio.time_on_air(packet.len()) * off_time)
+        } else {
+            None
+        };
+        Ok((number, tx_end))
+    }
+
+    /** Frequency (Hz) of the RX1 window after an uplink on the channel */
+    fn rx1_frequency(&self, channel: u8) -> Result<u32> {
+        let parameters = self.radio.band().parameters();
+        if channel < parameters.uplink_channel_count() {
+            parameters.rx1_frequency(channel)
+        } else {
+            // Channels added by the network receive on their uplink frequency
+            self.channels
+                .channel(channel)
+                .map(|channel| channel.frequency)
+                .ok_or(Error::InvalidChannel(
+                    Channel::Uplink(channel),
+                    parameters.band,
+                ))
+        }
+    }
+
+    /** Open RX1 `rx1_delay` after `tx_end` and, unless `accept` takes what RX1 received, RX2 a second
+     * later. Both windows are given as frequency (Hz) and data rate. */
+    fn receive_windows<T, F>(
+        &mut self,
+        tx_end: Instant,
+        rx1_delay: Duration,
+        rx1: (u32, u8),
+        rx2: (u32, u8),
+        accept: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnMut(&mut Self, &ReceivedPacket, RxWindow) -> Option<T>,
+    {
+        self.radio.set_invert_iq_rx(true)?;
+        let result = self.open_windows(tx_end, rx1_delay, rx1, rx2, accept);
+        let restored = self.radio.set_invert_iq_rx(false);
+        let received = result?;
+        restored?;
+        Ok(received)
+    }
+
+    fn open_windows<T, F>(
+        &mut self,
+        tx_end: Instant,
+        rx1_delay: Duration,
+        rx1: (u32, u8),
+        rx2: (u32, u8),
+        mut accept: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnMut(&mut Self, &ReceivedPacket, RxWindow) -> Option<T>,
+    {
+        let windows = [
+            (RxWindow::Rx1, tx_end + rx1_delay, rx1),
+            (RxWindow::Rx2, tx_end + rx1_delay + RX2_DELAY, rx2),
+        ];
+        for (window, opens_at, (frequency, data_rate)) in windows.iter() {
+            if let Some(packet) = self.receive_window(*opens_at, *frequency, *data_rate)? {
+                if let Some(accepted) = accept(self, &packet, *window) {
+                    return Ok(Some(accepted));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /** Wait for the window to open and receive a single packet in it */
+    fn receive_window(
+        &mut self,
+        opens_at: Instant,
+        frequency: u32,
+        data_rate: u8,
+    ) -> Result<Option<ReceivedPacket>> {
+        let band = self.radio.band();
+        let modulation = band.parameters().data_rate(data_rate)?.modulation();
+        let start = opens_at.checked_sub(RX_WINDOW_MARGIN).unwrap_or(opens_at);
+        let now = self.clock.now();
+        if start > now {
+            self.clock.sleep(start - now);
+        }
+
+        // Downlinks carry no payload CRC
+        match self.radio.receive_single(
+            Channel::Frequency(frequency),
+            modulation,
+            false,
+            rx_symbol_timeout(modulation),
+        ) {
+            Ok(packet) => Ok(Some(packet)),
+            Err(Error::SymbolTimeout) | Err(Error::ReceiveTimedOut) | Err(Error::CrcError(_)) => {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /** The packet decrypted, if it is a downlink for this session with a valid MIC and a new frame
+     * counter. Advances the downlink frame counter and processes the MAC commands of the frame. */
+    fn accept_downlink(&mut self, packet: &ReceivedPacket, window: RxWindow) -> Option<Downlink> {
+        let phy = PhyPayload::parse(&packet.payload).ok()?;
+        let session = self.session.as_mut()?;
+        let data = match &phy.frame {
+            Frame::Data(data)
+                if data.direction == Direction::Downlink && data.dev_addr == session.dev_addr =>
+            {
+                data
+            }
+            _ => return None,
+        };
+        let fcnt = session.downlink_fcnt(data.fcnt)?;
+        verify_data_mic(&session.keys.nwk_s_key, &phy, fcnt).ok()?;
+        session.fcnt_down = fcnt.wrapping_add(1);
+        if data.confirmed {
+            self.ack_pending = true;
+        }
+
+        let key = if data.fport == Some(0) {
+            &session.keys.nwk_s_key
+        } else {
+            &session.keys.app_s_key
+        };
+        let payload = decrypt_frm_payload(
+            key,
+            Direction::Downlink,
+            data.dev_addr,
+            fcnt,
+            &data.frm_payload,
+        );
+        // A frame carries MAC commands either in FOpts or as the payload of FPort 0
+        let (payload, commands) = if data.fport == Some(0) {
+            (Vec::new(), DownlinkCommand::parse_all(&payload))
+        } else {
+            (payload, DownlinkCommand::parse_all(&data.fopts))
+        };
+
+        // Any downlink shows that the network received the sticky answers
+        self.uplink_commands.retain(|command| !command.is_sticky());
+        self.process_commands(&commands, packet.snr);
+        Some(Downlink {
+            port: data.fport,
+            data: payload,
+            commands,
+            ack: data.fctrl.ack,
+            pending: data.fctrl.pending,
+            confirmed: data.confirmed,
+            fcnt,
+            window,
+            rssi: packet.rssi,
+            snr: packet.snr,
+        })
+    }
+
+    /** Apply the MAC commands of a downlink and queue their answers */
+    fn process_commands(&mut self, commands: &[DownlinkCommand], snr: f32) {
+        let mut i = 0;
+        while i < commands.len() {
+            let answer = match commands[i] {
+                DownlinkCommand::LinkAdrReq { .. } => {
+                    // Consecutive LinkADRReqs form a block that is applied or rejected as a whole, and
+                    // each of them is answered with the status of the block
+                    let count = commands[i..]
+                        .iter()
+                        .take_while(|command| matches!(command, DownlinkCommand::LinkAdrReq { .. }))
+                        .count();
+                    let answer = self.link_adr(&commands[i..i + count]);
+                    self.uplink_commands
+                        .extend(std::iter::repeat_n(answer, count));
+                    i += count;
+                    continue;
+                }
+                DownlinkCommand::DutyCycleReq { max_duty_cycle } => {
+                    self.session_mut().max_duty_cycle = max_duty_cycle;
+                    Some(UplinkCommand::DutyCycleAns)
+                }
+                DownlinkCommand::RxParamSetupReq {
+                    rx1_dr_offset,
+                    rx2_data_rate,
+                    frequency,
+                } => Some(self.rx_param_setup(rx1_dr_offset, rx2_data_rate, frequency)),
+                DownlinkCommand::DevStatusReq => Some(UplinkCommand::DevStatusAns {
+                    battery: (self.battery_level)(),
+                    margin: snr.round().clamp(-32.0, 31.0) as i8,
+                }),
+                DownlinkCommand::NewChannelReq {
+                    index,
+                    frequency,
+                    min_data_rate,
+                    max_data_rate,
+                } => self.new_channel(index, frequency, min_data_rate, max_data_rate),
+                DownlinkCommand::RxTimingSetupReq { delay } => {
+                    self.session_mut().rx_delay = delay.max(DEFAULT_RX_DELAY);
+                    Some(UplinkCommand::RxTimingSetupAns)
+                }
+                DownlinkCommand::DeviceTimeAns { gps_time } => {
+                    self.device_time = self.last_tx_end.map(|tx_end| (gps_time, tx_end));
+                    None
+                }
+                DownlinkCommand::LinkCheckAns { .. } => None,
+            };
+            self.uplink_commands.extend(answer);
+            i += 1;
+        }
+    }
+
+    /** Apply a block of LinkADRReqs if the channel mask, data rate and TX power they result in are all
+     * acceptable, and return the answer to each of them */
+    fn link_adr(&mut self, requests: &[DownlinkCommand]) -> UplinkCommand {
+        let parameters = self.radio.band().parameters();
+        let mut mask = Some(self.channels.mask());
+        let (mut data_rate, mut tx_power, mut nb_trans) = (LINK_ADR_KEEP, LINK_ADR_KEEP, 0);
+        for request in requests {
+            if let DownlinkCommand::LinkAdrReq {
+                data_rate: request_data_rate,
+                tx_power: request_tx_power,
+                channel_mask,
+                channel_mask_control,
+                nb_trans: request_nb_trans,
+            } = *request
+            {
+                mask = mask.and_then(|mask| {
+                    self.channels
+                        .link_adr_mask(mask, channel_mask_control, channel_mask)
+                });
+                // The last request of the block sets the data rate, TX power and NbTrans
+                data_rate = request_data_rate;
+                tx_power = request_tx_power;
+                nb_trans = request_nb_trans;
+            }
+        }
+
+        // The mask has to enable at least one channel, and only defined ones
+        let defined = self.channels.defined_mask();
+        let mask = mask.filter(|mask| *mask != 0 && mask & !defined == 0);
+        let data_rate_ack = data_rate == LINK_ADR_KEEP
+            || (parameters.data_rate(data_rate).is_ok()
+                && !self
+                    .channels
+                    .channels_in_mask(mask.unwrap_or_else(|| self.channels.mask()), data_rate)
+                    .is_empty());
+        let power_ack = tx_power == LINK_ADR_KEEP || tx_power <= parameters.max_tx_power;
+
+        if let (Some(mask), true, true) = (mask, data_rate_ack, power_ack) {
+            self.channels.set_mask(mask);
+            if data_rate != LINK_ADR_KEEP {
+                self.data_rate = data_rate;
+            }
+            let session = self.session_mut();
+            if tx_power != LINK_ADR_KEEP {
+                session.tx_power = Some(tx_power);
+            }
+            session.nb_trans = nb_trans.max(1);
+        }
+        UplinkCommand::LinkAdrAns {
+            power_ack,
+            data_rate_ack,
+            channel_mask_ack: mask.is_some(),
+        }
+    }
+
+    /** Apply an RXParamSetupReq if all its parameters are acceptable */
+    fn rx_param_setup(
+        &mut self,
+        rx1_dr_offset: u8,
+        rx2_data_rate: u8,
+        frequency: u32,
+    ) -> UplinkCommand {
+        let parameters = self.radio.band().parameters();
+        let rx1_dr_offset_ack = parameters.rx1_data_rate(0, rx1_dr_offset).is_ok();
+        let rx2_data_rate_ack = parameters.data_rate(rx2_data_rate).is_ok();
+        let channel_ack = is_usable_frequency(frequency);
+        if rx1_dr_offset_ack && rx2_data_rate_ack && channel_ack {
+            let session = self.session_mut();
+            session.rx1_dr_offset = rx1_dr_offset;
+            session.rx2_data_rate = rx2_data_rate;
+            session.rx2_frequency = frequency;
+        }
+        UplinkCommand::RxParamSetupAns {
+            rx1_dr_offset_ack,
+            rx2_data_rate_ack,
+            channel_ack,
+        }
+    }
+
+    /** Define or remove a channel of a dynamic channel plan. Bands with a fixed channel plan do not answer
+     * NewChannelReq. */
+    fn new_channel(
+        &mut self,
+        index: u8,
+        frequency: u32,
+        min_data_rate: u8,
+        max_data_rate: u8,
+    ) -> Option<UplinkCommand> {
+        let parameters = self.radio.band().parameters();
+        if parameters.sub_bands > 0 {
+            return None;
+        }
+        let data_rate_range_ok = min_data_rate <= max_data_rate
+            && parameters.data_rate(min_data_rate).is_ok()
+            && parameters.data_rate(max_data_rate).is_ok();
+        // The default channels cannot be changed
+        let channel_frequency_ok = index >= parameters.uplink_channel_count()
+            && (index as usize) < self.channels.capacity()
+            && (frequency == 0 || is_usable_frequency(frequency));
+        if data_rate_range_ok && channel_frequency_ok {
+            let channel = Some(UplinkChannel {
+                frequency,
+                min_data_rate,
+                max_data_rate,
+            })
+            .filter(|_| frequency != 0);
+            // Cannot fail, the index was checked
+            let _ = self.channels.set_channel(index, channel);
+        }
+        Some(UplinkCommand::NewChannelAns {
+            data_rate_range_ok,
+            channel_frequency_ok,
+        })
+    }
+
+    /** MAC commands are only processed for downlinks of the current session */
+    fn session_mut(&mut self) -> &mut Session {
+        self.session.as_mut().unwrap()
+    }
+}
+
+/** The module can tune to the frequency (Hz) */
+fn is_usable_frequency(frequency: u32) -> bool {
+    HF_FREQUENCY_RANGE.contains(&frequency) || LF_FREQUENCY_RANGE.contains(&frequency)
+}
+
+/** The Join-Accept in the packet, if it decrypts to one with a valid MIC */
+fn accept_join(app_key: &Key, packet: &ReceivedPacket) -> Option<JoinAccept> {
+    let decrypted = decrypt_join_accept(app_key, &packet.payload).ok()?;
+    let phy = PhyPayload::parse(&decrypted).ok()?;
+    verify_join_mic(app_key, &phy).ok()?;
+    match phy.frame {
+        Frame::JoinAccept(accept) => Some(accept),
+        _ => None,
+    }
+}
+
+/** Symbol timeout of a receive window: the preamble plus the margin on either side of the window */
+fn rx_symbol_timeout(modulation: LoRaModulation) -> u16 {
+    let symbol_time = modulation.symbol_time().as_micros();
+    let margin = (2 * RX_WINDOW_MARGIN.as_micros()).div_ceil(symbol_time);
+    (PREAMBLE_LENGTH as u128 + margin).min(MAX_SYMBOL_TIMEOUT as u128) as u16
+}
@@ -0,0 +1,325 @@
+//! Encoding and decoding of LoRaWAN 1.0.x PHYPayloads (LoRaWAN specification 1.0.4, chapter 4).
+//!
+//! Multi-byte fields are little endian on the air; EUIs, addresses and nonces are represented as integers
+//! in the usual (most significant byte first) notation. The codec does not encrypt or check MICs; the MIC
+//! is carried as is. A Join-Accept has to be decrypted before it can be parsed.
+use crate::error::*;
+use std::convert::TryInto;
+
+/** Length of MHDR plus MIC */
+const MHDR_MIC_LENGTH: usize = 5;
+const JOIN_REQUEST_LENGTH: usize = 23;
+const JOIN_ACCEPT_LENGTH: usize = 17;
+const CF_LIST_LENGTH: usize = 16;
+/** MHDR, DevAddr, FCtrl, FCnt and MIC */
+const MIN_DATA_FRAME_LENGTH: usize = 12;
+pub(crate) const MAX_FOPTS_LENGTH: usize = 15;
+
+/** LoRaWAN major version R1, in bits 1-0 of MHDR */
+const MAJOR_R1: u8 = 0;
+
+/** Message type, bits 7-5 of MHDR */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MType {
+    JoinRequest,
+    JoinAccept,
+    UnconfirmedDataUp,
+    UnconfirmedDataDown,
+    ConfirmedDataUp,
+    ConfirmedDataDown,
+    RejoinRequest, // LoRaWAN 1.1
+    Proprietary,
+}
+
+impl MType {
+    fn from_mhdr(mhdr: u8) -> MType {
+        match mhdr >> 5 {
+            0 => MType::JoinRequest,
+            1 => MType::JoinAccept,
+            2 => MType::UnconfirmedDataUp,
+            3 => MType::UnconfirmedDataDown,
+            4 => MType::ConfirmedDataUp,
+            5 => MType::ConfirmedDataDown,
+            6 => MType::RejoinRequest,
+            _ => MType::Proprietary,
+        }
+    }
+
+    fn mhdr(&self) -> u8 {
+        let mtype = match self {
+            MType::JoinRequest => 0,
+            MType::JoinAccept => 1,
+            MType::UnconfirmedDataUp => 2,
+            MType::UnconfirmedDataDown => 3,
+            MType::ConfirmedDataUp => 4,
+            MType::ConfirmedDataDown => 5,
+            MType::RejoinRequest => 6,
+            MType::Proprietary => 7,
+        };
+        (mtype << 5) | MAJOR_R1
+    }
+}
+
+/** Direction of a data frame */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Uplink,
+    Downlink,
+}
+
+/** Join-Request sent by the end device to start an over-the-air activation */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JoinRequest {
+    /** JoinEUI (AppEUI in LoRaWAN 1.0.2) */
+    pub join_eui: u64,
+    pub dev_eui: u64,
+    pub dev_nonce: u16,
+}
+
+/** Decrypted Join-Accept sent by the network server in response to a Join-Request */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JoinAccept {
+    /** JoinNonce (AppNonce in LoRaWAN 1.0.2), 24 bits */
+    pub join_nonce: u32,
+    /** NetID, 24 bits */
+    pub net_id: u32,
+    pub dev_addr: u32,
+    /** Offset between the uplink and RX1 data rate, bits 6-4 of DLSettings */
+    pub rx1_dr_offset: u8,
+    /** Data rate of the RX2 window, bits 3-0 of DLSettings */
+    pub rx2_data_rate: u8,
+    /** Delay between the end of an uplink and the RX1 window in seconds; 0 means 1 s */
+    pub rx_delay: u8,
+    /** Optional list of additional channels or channel mask, depending on the region */
+    pub cf_list: Option<[u8; CF_LIST_LENGTH]>,
+}
+
+/** Frame control octet of a data frame */
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FCtrl {
+    /** Adaptive data rate enabled */
+    pub adr: bool,
+    /** ADRACKReq; only in uplinks (RFU in downlinks) */
+    pub adr_ack_req: bool,
+    /** Acknowledges the last confirmed frame of the other side */
+    pub ack: bool,
+    /** FPending in downlinks (more data is waiting), ClassB in uplinks */
+    pub pending: bool,
+}
+
+impl FCtrl {
+    fn from_byte(byte: u8) -> FCtrl {
+        FCtrl {
+            adr: byte & 0x80 != 0,
+            adr_ack_req: byte & 0x40 != 0,
+            ack: byte & 0x20 != 0,
+            pending: byte & 0x10 != 0,
+        }
+    }
+
+    fn to_byte(self, fopts_length: usize) -> u8 {
+        (self.adr as u8) << 7
+            | (self.adr_ack_req as u8) << 6
+            | (self.ack as u8) << 5
+            | (self.pending as u8) << 4
+            | fopts_length as u8
+    }
+}
+
+/** Data frame (MACPayload) carrying MAC commands and/or application data */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataFrame {
+    pub direction: Direction,
+    pub confirmed: bool,
+    pub dev_addr: u32,
+    pub fctrl: FCtrl,
+    /** Lower 16 bits of the frame counter */
+    pub fcnt: u16,
+    /** MAC commands piggybacked in the frame header, at most 15 bytes */
+    pub fopts: Vec<u8>,
+    /** `None` when the frame carries no FRMPayload */
+    pub fport: Option<u8>,
+    /** FRMPayload as sent on the air, i.e. encrypted */
+    pub frm_payload: Vec<u8>,
+}
+
+impl DataFrame {
+    pub fn mtype(&self) -> MType {
+        match (self.direction, self.confirmed) {
+            (Direction::Uplink, false) => MType::UnconfirmedDataUp,
+            (Direction::Uplink, true) => MType::ConfirmedDataUp,
+            (Direction::Downlink, false) => MType::UnconfirmedDataDown,
+            (Direction::Downlink, true) => MType::ConfirmedDataDown,
+        }
+    }
+}
+
+/** Contents of a PHYPayload */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    JoinRequest(JoinRequest),
+    JoinAccept(JoinAccept),
+    Data(DataFrame),
+}
+
+/** A complete LoRaWAN frame as passed to `RFM95::send_packet` or returned in a `ReceivedPacket` */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhyPayload {
+    pub frame: Frame,
+    pub mic: [u8; 4],
+}
+
+impl PhyPayload {
+    pub fn new(frame: Frame) -> PhyPayload {
+        PhyPayload { frame, mic: [0; 4] }
+    }
+
+    pub fn mtype(&self) -> MType {
+        match &self.frame {
+            Frame::JoinRequest(_) => MType::JoinRequest,
+            Frame::JoinAccept(_) => MType::JoinAccept,
+            Frame::Data(data) => data.mtype(),
+        }
+    }
+
+    /** Decode a PHYPayload. Join-Accepts must already be decrypted. */
+    pub fn parse(bytes: &[u8]) -> Result<PhyPayload> {
+        if bytes.len() < MHDR_MIC_LENGTH {
+            return Err(Error::InvalidFrame("frame too short"));
+        }
+        let mhdr = bytes[0];
+        if mhdr & 0x03 != MAJOR_R1 {
+            return Err(Error::InvalidFrame("unsupported major version"));
+        }
+        let (payload, mic) = bytes[1..].split_at(bytes.len() - MHDR_MIC_LENGTH);
+        let frame = match MType::from_mhdr(mhdr) {
+            MType::JoinRequest => Frame::JoinRequest(parse_join_request(bytes.len(), payload)?),
+            MType::JoinAccept => Frame::JoinAccept(parse_join_accept(bytes.len(), payload)?),
+            MType::UnconfirmedDataUp => Frame::Data(parse_data(Direction::Uplink, false, payload)?),
+            MType::ConfirmedDataUp => Frame::Data(parse_data(Direction::Uplink, true, payload)?),
+            MType::UnconfirmedDataDown => {
+                Frame::Data(parse_data(Direction::Downlink, false, payload)?)
+            }
+            MType::ConfirmedDataDown => {
+                Frame::Data(parse_data(Direction::Downlink, true, payload)?)
+            }
+            MType::RejoinRequest | MType::Proprietary => {
+                return Err(Error::InvalidFrame("unsupported message type"));
+            }
+        };
+        Ok(PhyPayload {
+            frame,
+            mic: [mic[0], mic[1], mic[2], mic[3]],
+        })
+    }
+
+    /** Encode the PHYPayload, including the MIC */
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![self.mtype().mhdr()];
+        match &self.frame {
+            Frame::JoinRequest(request) => {
+                bytes.extend_from_slice(&request.join_eui.to_le_bytes());
+                bytes.extend_from_slice(&request.dev_eui.to_le_bytes());
+                bytes.extend_from_slice(&request.dev_nonce.to_le_bytes());
+            }
+            Frame::JoinAccept(accept) => {
+                bytes.extend_from_slice(&accept.join_nonce.to_le_bytes()[..3]);
+                bytes.extend_from_slice(&accept.net_id.to_le_bytes()[..3]);
+                bytes.extend_from_slice(&accept.dev_addr.to_le_bytes());
+                bytes.push((accept.rx1_dr_offset & 0x07) << 4 | (accept.rx2_data_rate & 0x0F));
+                bytes.push(accept.rx_delay);
+                if let Some(cf_list) = &accept.cf_list {
+                    bytes.extend_from_slice(cf_list);
+                }
+            }
+            Frame::Data(data) => {
+                if data.fopts.len() > MAX_FOPTS_LENGTH {
+                    return Err(Error::InvalidFrame("FOpts longer than 15 bytes"));
+                }
+                if data.fport.is_none() && !data.frm_payload.is_empty() {
+                    return Err(Error::InvalidFrame("FRMPayload without FPort"));
+                }
+                if data.fport == Some(0) && !data.fopts.is_empty() {
+                    return Err(Error::InvalidFrame(
+                        "MAC commands in both FOpts and FPort 0",
+                    ));
+                }
+                bytes.extend_from_slice(&data.dev_addr.to_le_bytes());
+                bytes.push(data.fctrl.to_byte(data.fopts.len()));
+                bytes.extend_from_slice(&data.fcnt.to_le_bytes());
+                bytes.extend_from_slice(&data.fopts);
+                if let Some(fport) = data.fport {
+                    bytes.push(fport);
+                    bytes.extend_from_slice(&data.frm_payload);
+                }
+            }
+        }
+        bytes.extend_from_slice(&self.mic);
+        Ok(bytes)
+    }
+}
+
+fn parse_join_request(length: usize, payload: &[u8]) -> Result<JoinRequest> {
+    if length != JOIN_REQUEST_LENGTH {
+        return Err(Error::InvalidFrame("invalid Join-Request length"));
+    }
+    Ok(JoinRequest {
+        join_eui: u64::from_le_bytes(payload[0..8].try_into().unwrap()),
+        dev_eui: u64::from_le_bytes(payload[8..16].try_into().unwrap()),
+        dev_nonce: u16::from_le_bytes([payload[16], payload[17]]),
+    })
+}
+
+fn parse_join_accept(length: usize, payload: &[u8]) -> Result<JoinAccept> {
+    let cf_list = if length == JOIN_ACCEPT_LENGTH {
+        None
+    } else if length == JOIN_ACCEPT_LENGTH + CF_LIST_LENGTH {
+        Some(payload[12..28].try_into().unwrap())
+    } else {
+        return Err(Error::InvalidFrame("invalid Join-Accept length"));
+    };
+    Ok(JoinAccept {
+        join_nonce: u24_from_le_bytes(&payload[0..3]),
+        net_id: u24_from_le_bytes(&payload[3..6]),
+        dev_addr: u32::from_le_bytes(payload[6..10].try_into().unwrap()),
+        rx1_dr_offset: (payload[10] >> 4) & 0x07,
+        rx2_data_rate: payload[10] & 0x0F,
+        rx_delay: payload[11],
+        cf_list,
+    })
+}
+
+fn parse_data(direction: Direction, confirmed: bool, payload: &[u8]) -> Result<DataFrame> {
+    if payload.len() + MHDR_MIC_LENGTH < MIN_DATA_FRAME_LENGTH {
+        return Err(Error::InvalidFrame("data frame too short"));
+    }
+    let fctrl = payload[4];
+    let fopts_end = 7 + (fctrl & 0x0F) as usize;
+    if payload.len() < fopts_end {
+        return Err(Error::InvalidFrame("FOpts exceed the frame"));
+    }
+    let (fport, frm_payload) = match payload[fopts_end..].split_first() {
+        Some((fport, frm_payload)) => (Some(*fport), frm_payload.to_vec()),
+        None => (None, Vec::new()),
+    };
+    let fopts = payload[7..fopts_end].to_vec();
+    if fport == Some(0) && !fopts.is_empty() {
+        return Err(Error::InvalidFrame(
+            "MAC commands in both FOpts and FPort 0",
+        ));
+    }
+    Ok(DataFrame {
+        direction,
+        confirmed,
+        dev_addr: u32::from_le_bytes(payload[0..4].try_into().unwrap()),
+        fctrl: FCtrl::from_byte(fctrl),
+        fcnt: u16::from_le_bytes([payload[5], payload[6]]),
+        fopts,
+        fport,
+        frm_payload,
+    })
+}
+
+fn u24_from_le_bytes(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
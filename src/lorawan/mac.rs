@@ -0,0 +1,214 @@
+//! LoRaWAN 1.0.x MAC commands (LoRaWAN specification 1.0.4, chapter 5), carried in FOpts or in the
+//! FRMPayload of FPort 0.
+//!
+//! Each command is a command identifier (CID) followed by a payload whose length depends on the CID and the
+//! direction. Downlink commands are decoded, uplink commands encoded; `Device` processes the commands it
+//! receives and queues the answers.
+use std::time::Duration;
+
+const LINK_CHECK: u8 = 0x02;
+const LINK_ADR: u8 = 0x03;
+const DUTY_CYCLE: u8 = 0x04;
+const RX_PARAM_SETUP: u8 = 0x05;
+const DEV_STATUS: u8 = 0x06;
+const NEW_CHANNEL: u8 = 0x07;
+const RX_TIMING_SETUP: u8 = 0x08;
+const DEVICE_TIME: u8 = 0x0D;
+
+/** MAC command sent by the network server */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DownlinkCommand {
+    /** Answer to a LinkCheckReq: demodulation margin (dB) of the uplink and number of gateways that received
+     * it */
+    LinkCheckAns { margin: u8, gateway_count: u8 },
+    /** Change the data rate, TX power, channel mask and number of transmissions. A data rate or TX power
+     * of 15 keeps the current value. */
+    LinkAdrReq {
+        data_rate: u8,
+        tx_power: u8,
+        channel_mask: u16,
+        /** ChMaskCntl: which channels `channel_mask` applies to; the meaning depends on the region */
+        channel_mask_control: u8,
+        /** NbTrans: how often every unconfirmed uplink is transmitted; 0 means once */
+        nb_trans: u8,
+    },
+    /** Limit the aggregated duty cycle of the device to 1 / 2^`max_duty_cycle`; 0 removes the limit */
+    DutyCycleReq { max_duty_cycle: u8 },
+    /** Change the RX1 data rate offset and the RX2 data rate and frequency (Hz) */
+    RxParamSetupReq {
+        rx1_dr_offset: u8,
+        rx2_data_rate: u8,
+        frequency: u32,
+    },
+    /** Ask for the battery level and the demodulation margin */
+    DevStatusReq,
+    /** Define (or with frequency 0 remove) an uplink channel */
+    NewChannelReq {
+        index: u8,
+        frequency: u32,
+        min_data_rate: u8,
+        max_data_rate: u8,
+    },
+    /** Change the delay of RX1 (seconds; 0 means 1) */
+    RxTimingSetupReq { delay: u8 },
+    /** Answer to a DeviceTimeReq: time since the GPS epoch at the end of the uplink that carried the
+     * request */
+    DeviceTimeAns { gps_time: Duration },
+}
+
+impl DownlinkCommand {
+    /** Decode the MAC commands of a downlink. Decoding stops at the first unknown or truncated command, as
+     * the commands that follow cannot be located. */
+    pub fn parse_all(bytes: &[u8]) -> Vec<DownlinkCommand> {
+        let mut commands = Vec::new();
+        let mut rest = bytes;
+        while let Some((&cid, payload)) = rest.split_first() {
+            let length = match cid {
+                LINK_CHECK => 2,
+                LINK_ADR => 4,
+                DUTY_CYCLE => 1,
+                RX_PARAM_SETUP => 4,
+                DEV_STATUS => 0,
+                NEW_CHANNEL => 5,
+                RX_TIMING_SETUP => 1,
+                DEVICE_TIME => 5,
+                _ => break,
+            };
+            if payload.len() < length {
+                break;
+            }
+            let (payload, next) = payload.split_at(length);
+            commands.push(DownlinkCommand::decode(cid, payload));
+            rest = next;
+        }
+        commands
+    }
+
+    fn decode(cid: u8, payload: &[u8]) -> DownlinkCommand {
+        match cid {
+            LINK_CHECK => DownlinkCommand::LinkCheckAns {
+                margin: payload[0],
+                gateway_count: payload[1],
+            },
+            LINK_ADR => DownlinkCommand::LinkAdrReq {
+                data_rate: payload[0] >> 4,
+                tx_power: payload[0] & 0x0F,
+                channel_mask: u16::from_le_bytes([payload[1], payload[2]]),
+                channel_mask_control: (payload[3] >> 4) & 0x07,
+                nb_trans: payload[3] & 0x0F,
+            },
+            DUTY_CYCLE => DownlinkCommand::DutyCycleReq {
+                max_duty_cycle: payload[0] & 0x0F,
+            },
+            RX_PARAM_SETUP => DownlinkCommand::RxParamSetupReq {
+                rx1_dr_offset: (payload[0] >> 4) & 0x07,
+                rx2_data_rate: payload[0] & 0x0F,
+                frequency: frequency(&payload[1..4]),
+            },
+            DEV_STATUS => DownlinkCommand::DevStatusReq,
+            NEW_CHANNEL => DownlinkCommand::NewChannelReq {
+                index: payload[0],
+                frequency: frequency(&payload[1..4]),
+                min_data_rate: payload[4] & 0x0F,
+                max_data_rate: payload[4] >> 4,
+            },
+            RX_TIMING_SETUP => DownlinkCommand::RxTimingSetupReq {
+                delay: payload[0] & 0x0F,
+            },
+            DEVICE_TIME => {
+                let seconds = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                // The fractional second is given in 1/256 s
+                DownlinkCommand::DeviceTimeAns {
+                    gps_time: Duration::from_secs(seconds as u64)
+                        + Duration::from_nanos(payload[4] as u64 * 1_000_000_000 / 256),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/** MAC command sent by the end device */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UplinkCommand {
+    LinkAdrAns {
+        power_ack: bool,
+        data_rate_ack: bool,
+        channel_mask_ack: bool,
+    },
+    DutyCycleAns,
+    RxParamSetupAns {
+        rx1_dr_offset_ack: bool,
+        rx2_data_rate_ack: bool,
+        channel_ack: bool,
+    },
+    /** Battery level (0: external power, 1 - 254: level, 255: unknown) and the SNR (dB, -32 to 31) of the
+     * DevStatusReq */
+    DevStatusAns {
+        battery: u8,
+        margin: i8,
+    },
+    NewChannelAns {
+        data_rate_range_ok: bool,
+        channel_frequency_ok: bool,
+    },
+    RxTimingSetupAns,
+    /** Ask the network for the current time */
+    DeviceTimeReq,
+}
+
+impl UplinkCommand {
+    /** CID and payload */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            UplinkCommand::LinkAdrAns {
+                power_ack,
+                data_rate_ack,
+                channel_mask_ack,
+            } => vec![
+                LINK_ADR,
+                status(&[power_ack, data_rate_ack, channel_mask_ack]),
+            ],
+            UplinkCommand::DutyCycleAns => vec![DUTY_CYCLE],
+            UplinkCommand::RxParamSetupAns {
+                rx1_dr_offset_ack,
+                rx2_data_rate_ack,
+                channel_ack,
+            } => vec![
+                RX_PARAM_SETUP,
+                status(&[rx1_dr_offset_ack, rx2_data_rate_ack, channel_ack]),
+            ],
+            UplinkCommand::DevStatusAns { battery, margin } => {
+                vec![DEV_STATUS, battery, margin.clamp(-32, 31) as u8 & 0x3F]
+            }
+            UplinkCommand::NewChannelAns {
+                data_rate_range_ok,
+                channel_frequency_ok,
+            } => vec![
+                NEW_CHANNEL,
+                status(&[data_rate_range_ok, channel_frequency_ok]),
+            ],
+            UplinkCommand::RxTimingSetupAns => vec![RX_TIMING_SETUP],
+            UplinkCommand::DeviceTimeReq => vec![DEVICE_TIME],
+        }
+    }
+
+    /** RXParamSetupAns and RXTimingSetupAns are repeated in every uplink until a downlink is received, so
+     * that the network knows the device applied the new receive window settings */
+    pub fn is_sticky(&self) -> bool {
+        matches!(
+            self,
+            UplinkCommand::RxParamSetupAns { .. } | UplinkCommand::RxTimingSetupAns
+        )
+    }
+}
+
+/** Frequency (Hz) from three bytes in units of 100 Hz */
+fn frequency(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) * 100
+}
+
+/** Status byte with the most significant of the given bits first, ending at bit 0 */
+fn status(bits: &[bool]) -> u8 {
+    bits.iter().fold(0, |status, bit| status << 1 | *bit as u8)
+}
@@ -0,0 +1,17 @@
+//! LoRaWAN 1.0.x end device support on top of the RFM95 driver.
+//!
+//! [`Device`] implements a Class A end device with over-the-air activation and activation by
+//! personalization, which processes the MAC commands of the network and can keep its session in a
+//! [`SessionStore`] across restarts. [`frame`] encodes and decodes PHYPayloads, [`crypto`] computes
+//! MICs and encrypts payloads, and [`mac`] encodes and decodes MAC commands.
+
+mod channel_plan;
+pub mod crypto;
+mod device;
+pub mod frame;
+pub mod mac;
+mod session_store;
+
+pub use channel_plan::*;
+pub use device::*;
+pub use session_store::*;
@@ -0,0 +1,248 @@
+use crate::error::*;
+use crate::lorawan::channel_plan::{ChannelPlan, UplinkChannel};
+use crate::lorawan::crypto::{Key, SessionKeys};
+use crate::lorawan::device::Session;
+use crate::rfm95::Band;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/** First byte of an encoded `DeviceState`, changed whenever the encoding changes */
+const FORMAT_VERSION: u8 = 1;
+
+/** Bands by their number in the encoding */
+const BANDS: [Band; 8] = [
+    Band::EU863,
+    Band::US901,
+    Band::AS920,
+    Band::AU915,
+    Band::KR920,
+    Band::IN865,
+    Band::CN470,
+    Band::EU433,
+];
+
+/** Encoded `Session::tx_power` when the network has not set one */
+const NO_TX_POWER: u8 = 0xFF;
+
+/** Everything a LoRaWAN device has to keep across restarts to continue its session: the network server
+ * drops uplinks whose frame counter it has seen before, so a device that starts counting at 0 again is
+ * not heard until it joins anew. */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceState {
+    /** DevNonce of the next Join-Request */
+    pub dev_nonce: u16,
+    /** Data rate (DR number) of uplinks */
+    pub data_rate: u8,
+    pub channels: ChannelPlan,
+    /** `None` while the device is not activated */
+    pub session: Option<Session>,
+}
+
+impl DeviceState {
+    /** Compact binary encoding of the state, as `FileSessionStore` writes it */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let band = self.channels.band();
+        let mut bytes = vec![
+            FORMAT_VERSION,
+            BANDS.iter().position(|b| *b == band).unwrap() as u8,
+        ];
+        bytes.extend(&self.dev_nonce.to_le_bytes());
+        bytes.push(self.data_rate);
+
+        // Only the channels added to the default ones of the band, followed by the mask
+        let first = band.parameters().uplink_channel_count();
+        let added: Vec<(u8, UplinkChannel)> = (first..self.channels.capacity() as u8)
+            .filter_map(|n| self.channels.channel(n).map(|channel| (n, channel)))
+            .collect();
+        bytes.push(added.len() as u8);
+        for (n, channel) in added {
+            bytes.push(n);
+            bytes.extend(&channel.frequency.to_le_bytes());
+            bytes.push(channel.min_data_rate);
+            bytes.push(channel.max_data_rate);
+        }
+        bytes.extend(&self.channels.mask().to_le_bytes());
+
+        match &self.session {
+            None => bytes.push(0),
+            Some(session) => {
+                bytes.push(1);
+                bytes.extend(&session.dev_addr.to_le_bytes());
+                bytes.extend(&session.keys.nwk_s_key);
+                bytes.extend(&session.keys.app_s_key);
+                bytes.extend(&session.fcnt_up.to_le_bytes());
+                bytes.extend(&session.fcnt_down.to_le_bytes());
+                bytes.push(session.rx1_dr_offset);
+                bytes.extend(&session.rx2_frequency.to_le_bytes());
+                bytes.push(session.rx2_data_rate);
+                bytes.push(session.rx_delay);
+                bytes.push(session.tx_power.unwrap_or(NO_TX_POWER));
+                bytes.push(session.nb_trans);
+                bytes.push(session.max_duty_cycle);
+            }
+        }
+        bytes
+    }
+
+    /** Decode a state encoded by `to_bytes` */
+    pub fn parse(bytes: &[u8]) -> Result<DeviceState> {
+        let mut reader = Reader { bytes };
+        if reader.u8()? != FORMAT_VERSION {
+            return Err(Error::InvalidSessionState("unsupported format version"));
+        }
+        let band = *BANDS
+            .get(reader.u8()? as usize)
+            .ok_or(Error::InvalidSessionState("unknown band"))?;
+        let dev_nonce = reader.u16()?;
+        let data_rate = reader.u8()?;
+
+        let mut channels = ChannelPlan::new(band);
+        for _ in 0..reader.u8()? {
+            let n = reader.u8()?;
+            let channel = UplinkChannel {
+                frequency: reader.u32()?,
+                min_data_rate: reader.u8()?,
+                max_data_rate: reader.u8()?,
+            };
+            channels
+                .set_channel(n, Some(channel))
+                .map_err(|_| Error::InvalidSessionState("invalid channel"))?;
+        }
+        channels.set_mask(reader.u128()?);
+
+        let session = match reader.u8()? {
+            0 => None,
+            1 => Some(Session {
+                dev_addr: reader.u32()?,
+                keys: SessionKeys {
+                    nwk_s_key: reader.key()?,
+                    app_s_key: reader.key()?,
+                },
+                fcnt_up: reader.u32()?,
+                fcnt_down: reader.u32()?,
+                rx1_dr_offset: reader.u8()?,
+                rx2_frequency: reader.u32()?,
+                rx2_data_rate: reader.u8()?,
+                rx_delay: reader.u8()?,
+                tx_power: Some(reader.u8()?).filter(|tx_power| *tx_power != NO_TX_POWER),
+                nb_trans: reader.u8()?,
+                max_duty_cycle: reader.u8()?,
+            }),
+            _ => return Err(Error::InvalidSessionState("invalid session marker")),
+        };
+        if !reader.bytes.is_empty() {
+            return Err(Error::InvalidSessionState("trailing bytes"));
+        }
+        Ok(DeviceState {
+            dev_nonce,
+            data_rate,
+            channels,
+            session,
+        })
+    }
+}
+
+/** Storage for the state of a LoRaWAN device (see `Device::set_session_store`) */
+pub trait SessionStore {
+    /** The state saved last, or `None` when nothing has been saved yet */
+    fn load(&mut self) -> Result<Option<DeviceState>>;
+
+    /** Replace the saved state. When this is interrupted, e.g. by a power failure, either the previous or
+     * the new state has to be loaded afterwards. */
+    fn save(&mut self, state: &DeviceState) -> Result<()>;
+}
+
+/** Session store that keeps the state in a file. Saving writes the state to a temporary file next to it
+ * (the path with `.tmp` appended), syncs that to disk and renames it over the file, so that the file
+ * always holds a complete state. */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    /** Store the state at the path; the directory has to exist */
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileSessionStore {
+        FileSessionStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn temporary_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".tmp");
+        PathBuf::from(path)
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&mut self) -> Result<Option<DeviceState>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => DeviceState::parse(&bytes).map(Some),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(io_error(error)),
+        }
+    }
+
+    fn save(&mut self, state: &DeviceState) -> Result<()> {
+        let temporary = self.temporary_path();
+        let mut file = File::create(&temporary).map_err(io_error)?;
+        file.write_all(&state.to_bytes()).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        fs::rename(&temporary, &self.path).map_err(io_error)?;
+
+        // The rename only survives a power failure once the directory has been synced as well
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(directory)
+            .and_then(|directory| directory.sync_all())
+            .map_err(io_error)
+    }
+}
+
+/** Reads the little-endian fields of an encoded `DeviceState` in order */
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(Error::InvalidSessionState("truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u128(&mut self) -> Result<u128> {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(self.take(16)?);
+        Ok(u128::from_le_bytes(bytes))
+    }
+
+    fn key(&mut self) -> Result<Key> {
+        let mut key = [0u8; 16];
+        key.copy_from_slice(self.take(16)?);
+        Ok(key)
+    }
+}
@@ -0,0 +1,197 @@
+use crate::registers::{ModemConfig1Flags, ModemConfig2Flags, ModemConfig3Flags};
+use std::time::Duration;
+
+/** Symbol time above which low data rate optimisation must be enabled (see SX1276 datasheet, 4.1.1.6) */
+const LOW_DATA_RATE_OPTIMIZE_SYMBOL_TIME: Duration = Duration::from_millis(16);
+
+/** LoRa spreading factor (chips per symbol = 2^SF) */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpreadingFactor {
+    SF6, // Only supported in implicit header mode
+    SF7,
+    SF8,
+    SF9,
+    SF10,
+    SF11,
+    SF12,
+}
+
+impl SpreadingFactor {
+    /** Numeric spreading factor, e.g. 7 for SF7 */
+    pub fn value(&self) -> u8 {
+        match self {
+            SpreadingFactor::SF6 => 6,
+            SpreadingFactor::SF7 => 7,
+            SpreadingFactor::SF8 => 8,
+            SpreadingFactor::SF9 => 9,
+            SpreadingFactor::SF10 => 10,
+            SpreadingFactor::SF11 => 11,
+            SpreadingFactor::SF12 => 12,
+        }
+    }
+
+    fn modem_config_2(&self) -> ModemConfig2Flags {
+        match self {
+            SpreadingFactor::SF6 => ModemConfig2Flags::SF6,
+            SpreadingFactor::SF7 => ModemConfig2Flags::SF7,
+            SpreadingFactor::SF8 => ModemConfig2Flags::SF8,
+            SpreadingFactor::SF9 => ModemConfig2Flags::SF9,
+            SpreadingFactor::SF10 => ModemConfig2Flags::SF10,
+            SpreadingFactor::SF11 => ModemConfig2Flags::SF11,
+            SpreadingFactor::SF12 => ModemConfig2Flags::SF12,
+        }
+    }
+}
+
+/** LoRa signal bandwidth */
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Bandwidth {
+    BW7_8,
+    BW10_4,
+    BW15_6,
+    BW20_8,
+    BW31_25,
+    BW41_7,
+    BW62_5,
+    BW125,
+    BW250,
+    BW500,
+}
+
+impl Bandwidth {
+    /** Bandwidth in Hz */
+    pub fn hz(&self) -> u32 {
+        match self {
+            Bandwidth::BW7_8 => 7_800,
+            Bandwidth::BW10_4 => 10_400,
+            Bandwidth::BW15_6 => 15_600,
+            Bandwidth::BW20_8 => 20_800,
+            Bandwidth::BW31_25 => 31_250,
+            Bandwidth::BW41_7 => 41_700,
+            Bandwidth::BW62_5 => 62_500,
+            Bandwidth::BW125 => 125_000,
+            Bandwidth::BW250 => 250_000,
+            Bandwidth::BW500 => 500_000,
+        }
+    }
+
+    /** Bandwidth configured in bits 7-4 of RegModemConfig1 */
+    pub(crate) fn from_modem_config_1(modem_config_1: u8) -> Bandwidth {
+        match modem_config_1 >> 4 {
+            0 => Bandwidth::BW7_8,
+            1 => Bandwidth::BW10_4,
+            2 => Bandwidth::BW15_6,
+            3 => Bandwidth::BW20_8,
+            4 => Bandwidth::BW31_25,
+            5 => Bandwidth::BW41_7,
+            6 => Bandwidth::BW62_5,
+            7 => Bandwidth::BW125,
+            8 => Bandwidth::BW250,
+            _ => Bandwidth::BW500,
+        }
+    }
+
+    fn modem_config_1(&self) -> ModemConfig1Flags {
+        match self {
+            Bandwidth::BW7_8 => ModemConfig1Flags::BW7_8,
+            Bandwidth::BW10_4 => ModemConfig1Flags::BW10_4,
+            Bandwidth::BW15_6 => ModemConfig1Flags::BW15_6,
+            Bandwidth::BW20_8 => ModemConfig1Flags::BW20_8,
+            Bandwidth::BW31_25 => ModemConfig1Flags::BW31_25,
+            Bandwidth::BW41_7 => ModemConfig1Flags::BW41_7,
+            Bandwidth::BW62_5 => ModemConfig1Flags::BW62_5,
+            Bandwidth::BW125 => ModemConfig1Flags::BW125,
+            Bandwidth::BW250 => ModemConfig1Flags::BW250,
+            Bandwidth::BW500 => ModemConfig1Flags::BW500,
+        }
+    }
+}
+
+/** LoRa forward error correction coding rate */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CodingRate {
+    CR4_5,
+    CR4_6,
+    CR4_7,
+    CR4_8,
+}
+
+impl CodingRate {
+    /** Coding rate as reported in bits 7-5 of RegModemStat for the last received header */
+    pub(crate) fn from_modem_status(modem_status: u8) -> Option<CodingRate> {
+        match modem_status >> 5 {
+            1 => Some(CodingRate::CR4_5),
+            2 => Some(CodingRate::CR4_6),
+            3 => Some(CodingRate::CR4_7),
+            4 => Some(CodingRate::CR4_8),
+            _ => None,
+        }
+    }
+
+    fn modem_config_1(&self) -> ModemConfig1Flags {
+        match self {
+            CodingRate::CR4_5 => ModemConfig1Flags::CODING_RATE_4_5,
+            CodingRate::CR4_6 => ModemConfig1Flags::CODING_RATE_4_6,
+            CodingRate::CR4_7 => ModemConfig1Flags::CODING_RATE_4_7,
+            CodingRate::CR4_8 => ModemConfig1Flags::CODING_RATE_4_8,
+        }
+    }
+}
+
+/** LoRa modulation parameters. Any combination of spreading factor, bandwidth and coding rate the chip
+ * supports can be used; `DataRate` converts into the corresponding `LoRaModulation`. */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LoRaModulation {
+    pub sf: SpreadingFactor,
+    pub bw: Bandwidth,
+    pub cr: CodingRate,
+}
+
+impl LoRaModulation {
+    pub fn new(sf: SpreadingFactor, bw: Bandwidth, cr: CodingRate) -> LoRaModulation {
+        LoRaModulation { sf, bw, cr }
+    }
+
+    /** Duration of a single symbol, 2^SF / BW */
+    pub fn symbol_time(&self) -> Duration {
+        Duration::from_nanos((1_000_000_000u64 << self.sf.value()) / self.bw.hz() as u64)
+    }
+
+    /** Expected duration of a channel activity detection, (2^SF + 32) / BW */
+    pub fn cad_duration(&self) -> Duration {
+        Duration::from_nanos(
+            (1_000_000_000u64 * ((1u64 << self.sf.value()) + 32)) / self.bw.hz() as u64,
+        )
+    }
+
+    /** Whether low data rate optimisation is required, i.e. the symbol time exceeds 16 ms */
+    pub fn low_data_rate_optimize(&self) -> bool {
+        self.symbol_time() > LOW_DATA_RATE_OPTIMIZE_SYMBOL_TIME
+    }
+
+    /** Whether the modulation requires implicit header mode (SF6) */
+    pub fn implicit_header(&self) -> bool {
+        self.sf == SpreadingFactor::SF6
+    }
+
+    pub(crate) fn modem_config_1(&self) -> ModemConfig1Flags {
+        let mut flags = self.bw.modem_config_1() | self.cr.modem_config_1();
+        if self.implicit_header() {
+            flags |= ModemConfig1Flags::IMPLICIT_HEADER_MODE_ON;
+        }
+        flags
+    }
+
+    pub(crate) fn modem_config_2(&self) -> ModemConfig2Flags {
+        self.sf.modem_config_2()
+    }
+
+    pub(crate) fn modem_config_3(&self) -> ModemConfig3Flags {
+        if self.low_data_rate_optimize() {
+            ModemConfig3Flags::AUTO_AGC_ON | ModemConfig3Flags::IS_MOBILE_NODE
+        } else {
+            ModemConfig3Flags::AUTO_AGC_ON
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::modulation::CodingRate;
+use crate::rfm95::Channel;
+use std::time::Instant;
+
+/** Packet received by the modem, together with the link quality measured while receiving it */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedPacket {
+    pub payload: Vec<u8>,
+    /** Packet RSSI (dBm) */
+    pub rssi: i16,
+    /** Signal-to-noise ratio (dB) */
+    pub snr: f32,
+    /** Frequency error between transmitter and receiver as estimated by the modem (Hz) */
+    pub frequency_error: i32,
+    /** Coding rate announced in the packet header */
+    pub coding_rate: Option<CodingRate>,
+    /** Whether the packet header announced a payload CRC */
+    pub crc_on: bool,
+    /** Channel the packet was received on; the concrete channel in case of `Channel::Multi` */
+    pub channel: Channel,
+    /** Time at which the driver noticed the RxDone interrupt */
+    pub received_at: Instant,
+}
+
+impl ReceivedPacket {
+    pub fn len(&self) -> usize {
+        self.payload.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+}
+
+/** RSSI offset (dBm) for the HF (RFM95/96/97 above 779 MHz) and LF port respectively, see section 5.5.5 */
+const RSSI_OFFSET_HF: i16 = -157;
+const RSSI_OFFSET_LF: i16 = -164;
+
+pub(crate) fn rssi_offset(low_frequency_mode: bool) -> i16 {
+    if low_frequency_mode {
+        RSSI_OFFSET_LF
+    } else {
+        RSSI_OFFSET_HF
+    }
+}
+
+/** SNR in dB from RegPktSnrValue (two's complement, multiplied by four) */
+pub(crate) fn snr_db(raw: u8) -> f32 {
+    (raw as i8) as f32 / 4.0
+}
+
+/** Packet RSSI in dBm from RegPktRssiValue. Below the noise floor the SNR is added to the RSSI; above it
+ * the data sheet prescribes a 16/15 correction of the raw value. */
+pub(crate) fn packet_rssi_dbm(raw: u8, snr: f32, low_frequency_mode: bool) -> i16 {
+    let offset = rssi_offset(low_frequency_mode);
+    if snr < 0.0 {
+        offset + raw as i16 + snr.floor() as i16
+    } else {
+        offset + (raw as i16 * 16) / 15
+    }
+}
+
+/** Frequency error in Hz from the 20 bit two's complement value in RegFeiMsb/Mid/Lsb (section 4.1.5) */
+pub(crate) fn frequency_error_hz(fei: [u8; 3], bandwidth_hz: u32) -> i32 {
+    let raw = ((fei[0] as u32 & 0x0F) << 16) | ((fei[1] as u32) << 8) | fei[2] as u32;
+    // Sign extend from bit 19
+    let raw = ((raw << 12) as i32) >> 12;
+    let error = raw as f64 * (1u64 << 24) as f64 / crate::constants::FXOSC as f64
+        * (bandwidth_hz as f64 / 500_000.0);
+    error.round() as i32
+}
@@ -0,0 +1,61 @@
+use core::convert::Infallible;
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use std::time::{Duration, Instant};
+
+/** Placeholder for a pin that is not connected. Use it as the type of the chip select or reset pin
+ * when passing `None` to `RFM95::new`. Reading it always returns low. */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoPin;
+
+impl ErrorType for NoPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl InputPin for NoPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+/** Timing of a software driven chip select line. The SX1276 requires NSS to be low for at least 30 ns
+ * before the first clock edge (setup) and to stay low for at least 100 ns after the last one (hold);
+ * GPIO writes are usually slow enough to meet these without additional delays, which is the default. */
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChipSelectTiming {
+    /** Delay between asserting chip select and starting the SPI transfer */
+    pub setup: Duration,
+    /** Delay between the end of the SPI transfer and releasing chip select */
+    pub hold: Duration,
+}
+
+impl ChipSelectTiming {
+    pub fn new(setup: Duration, hold: Duration) -> ChipSelectTiming {
+        ChipSelectTiming { setup, hold }
+    }
+}
+
+/** Busy-wait for a (short) delay. `thread::sleep` has a granularity far above the nanosecond delays needed
+ * for chip select timing. */
+pub(crate) fn delay(duration: Duration) {
+    if duration.is_zero() {
+        return;
+    }
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,57 @@
+use crate::error::{Error, Result};
+use crate::registers::{OverCurrentProtectionFlags, PAConfigFlags, PADACFlags};
+
+/** Power amplifier output pin. Which of the two is connected to the antenna depends on the module; the
+ * RFM95/96/97/98 modules use PA_BOOST. */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PaOutput {
+    Rfo,     // -4 to +14 dBm
+    PaBoost, // +2 to +17 dBm, or up to +20 dBm in high power mode
+}
+
+/** Register values for a given output power */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PowerSettings {
+    pub pa_config: u8,
+    pub pa_dac: u8,
+    pub ocp: u8,
+}
+
+impl PowerSettings {
+    /** Compute RegPaConfig, RegPaDac and RegOcp for an output power (dBm) on the given output
+     * (see SX1276 datasheet, 5.4.2 and 5.4.4) */
+    pub fn new(dbm: i8, output: PaOutput) -> Result<PowerSettings> {
+        let (pa_select, max_power, output_power, high_power, ocp_ma) = match (output, dbm) {
+            // Pmax = 10.8 dBm, Pout = Pmax - (15 - OutputPower)
+            (PaOutput::Rfo, -4..=-1) => (PAConfigFlags::empty(), 0, dbm + 4, false, 100),
+            // Pmax = 15 dBm, Pout = OutputPower
+            (PaOutput::Rfo, 0..=14) => (PAConfigFlags::empty(), 7, dbm, false, 100),
+            // Pout = 2 + OutputPower
+            (PaOutput::PaBoost, 2..=17) => (PAConfigFlags::PA_SELECT_BOOST, 7, dbm - 2, false, 140),
+            // Pout = 5 + OutputPower with the +20 dBm option enabled in RegPaDac
+            (PaOutput::PaBoost, 18..=20) => (PAConfigFlags::PA_SELECT_BOOST, 7, dbm - 5, true, 240),
+            _ => return Err(Error::InvalidTxPower(dbm, output)),
+        };
+
+        let pa_dac = if high_power {
+            PADACFlags::RESERVED | PADACFlags::HIGH_POWER
+        } else {
+            PADACFlags::RESERVED | PADACFlags::DEFAULT
+        };
+
+        Ok(PowerSettings {
+            pa_config: pa_select.bits() | (max_power << 4) | output_power as u8,
+            pa_dac: pa_dac.bits(),
+            ocp: OverCurrentProtectionFlags::OCP_ON.bits() | ocp_trim(ocp_ma),
+        })
+    }
+}
+
+/** OcpTrim for a current limit (mA): Imax = 45 + 5 * OcpTrim up to 120 mA, -30 + 10 * OcpTrim up to 240 mA */
+fn ocp_trim(milliamps: u16) -> u8 {
+    if milliamps <= 120 {
+        ((milliamps - 45) / 5) as u8
+    } else {
+        ((milliamps + 30) / 10) as u8
+    }
+}
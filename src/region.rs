@@ -0,0 +1,552 @@
+use crate::error::*;
+use crate::modulation::{Bandwidth, CodingRate, LoRaModulation, SpreadingFactor};
+use crate::rfm95::{Band, Channel};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/** Channels at a fixed spacing that share the same uplink data rates */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ChannelBlock {
+    first: u32,
+    spacing: u32,
+    count: u8,
+    min_data_rate: u8,
+    max_data_rate: u8,
+}
+
+const fn block(first: u32, spacing: u32, count: u8, data_rates: (u8, u8)) -> ChannelBlock {
+    ChannelBlock {
+        first,
+        spacing,
+        count,
+        min_data_rate: data_rates.0,
+        max_data_rate: data_rates.1,
+    }
+}
+
+/** LoRa data rate of a band */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataRateParameters {
+    pub sf: SpreadingFactor,
+    pub bw: Bandwidth,
+    /** Maximum MACPayload length (M); the application payload is limited to M - 8 bytes without FOpts */
+    pub max_mac_payload: u8,
+    /** Maximum MACPayload length while the uplink dwell time is limited, `None` when the data rate cannot
+     * be used then */
+    pub max_mac_payload_dwell_time: Option<u8>,
+}
+
+impl DataRateParameters {
+    /** Modulation of the data rate; LoRaWAN always uses coding rate 4/5 */
+    pub fn modulation(&self) -> LoRaModulation {
+        LoRaModulation::new(self.sf, self.bw, CodingRate::CR4_5)
+    }
+}
+
+const fn dr(
+    sf: SpreadingFactor,
+    bw: Bandwidth,
+    max_mac_payload: u8,
+    max_mac_payload_dwell_time: Option<u8>,
+) -> Option<DataRateParameters> {
+    Some(DataRateParameters {
+        sf,
+        bw,
+        max_mac_payload,
+        max_mac_payload_dwell_time,
+    })
+}
+
+/** Mapping from the uplink data rate and RX1DROffset to the RX1 data rate:
+ * `uplink + base - offset`, limited to `min..=max`. With `negative_offsets`, offsets 6 and 7 raise the
+ * data rate by one and two steps (AS923, IN865). */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Rx1DataRates {
+    base: u8,
+    min: u8,
+    max: u8,
+    max_offset: u8,
+    negative_offsets: bool,
+}
+
+/** LoRaWAN regional parameters of a band (LoRaWAN Regional Parameters RP002-1.0), see
+ * `Band::parameters` */
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionalParameters {
+    pub band: Band,
+    /** Default channels used by `Channel::Ch0` to `Channel::Ch7` and `Channel::Multi` */
+    pub default_channels: &'static [u32],
+    uplink: &'static [ChannelBlock],
+    /** Downlink channels; empty when downlinks use the uplink frequency */
+    downlink: &'static [ChannelBlock],
+    /** Data rates indexed by DR number; unused numbers are `None` */
+    pub data_rates: &'static [Option<DataRateParameters>],
+    max_uplink_data_rate: u8,
+    rx1_data_rates: Rx1DataRates,
+    /** Number of sub-bands of eight 125 kHz channels, for bands with a fixed channel plan */
+    pub sub_bands: u8,
+    pub rx2_frequency: u32,
+    pub rx2_data_rate: u8,
+    /** Default maximum EIRP (dBm) */
+    pub max_eirp: f32,
+    /** Highest TXPower index of LinkADRReq; TXPower n stands for `max_eirp` - 2n dBm */
+    pub max_tx_power: u8,
+    /** Maximum time on air of a single uplink where the dwell time is limited */
+    pub max_dwell_time: Option<Duration>,
+}
+
+const DWELL_TIME_400_MS: Option<Duration> = Some(Duration::from_millis(400));
+
+/** DR0 - DR5 at SF12 - SF7 and 125 kHz and DR6 at SF7 and 250 kHz, as used in most bands. The limits
+ * while the dwell time is limited are those of AS923. */
+const EU_DATA_RATES: [Option<DataRateParameters>; 7] = [
+    dr(SpreadingFactor::SF12, Bandwidth::BW125, 59, None),
+    dr(SpreadingFactor::SF11, Bandwidth::BW125, 59, None),
+    dr(SpreadingFactor::SF10, Bandwidth::BW125, 59, Some(19)),
+    dr(SpreadingFactor::SF9, Bandwidth::BW125, 123, Some(61)),
+    dr(SpreadingFactor::SF8, Bandwidth::BW125, 250, Some(133)),
+    dr(SpreadingFactor::SF7, Bandwidth::BW125, 250, Some(250)),
+    dr(SpreadingFactor::SF7, Bandwidth::BW250, 250, Some(250)),
+];
+
+/** DR8 - DR13 at SF12 - SF7 and 500 kHz, the downlink data rates of US915 and AU915 */
+const DOWNLINK_500_KHZ_DATA_RATES: [Option<DataRateParameters>; 6] = [
+    dr(SpreadingFactor::SF12, Bandwidth::BW500, 61, Some(61)),
+    dr(SpreadingFactor::SF11, Bandwidth::BW500, 137, Some(137)),
+    dr(SpreadingFactor::SF10, Bandwidth::BW500, 250, Some(250)),
+    dr(SpreadingFactor::SF9, Bandwidth::BW500, 250, Some(250)),
+    dr(SpreadingFactor::SF8, Bandwidth::BW500, 250, Some(250)),
+    dr(SpreadingFactor::SF7, Bandwidth::BW500, 250, Some(250)),
+];
+
+const US_DATA_RATES: [Option<DataRateParameters>; 14] = [
+    dr(SpreadingFactor::SF10, Bandwidth::BW125, 19, Some(19)),
+    dr(SpreadingFactor::SF9, Bandwidth::BW125, 61, Some(61)),
+    dr(SpreadingFactor::SF8, Bandwidth::BW125, 133, Some(133)),
+    dr(SpreadingFactor::SF7, Bandwidth::BW125, 250, Some(250)),
+    dr(SpreadingFactor::SF8, Bandwidth::BW500, 250, Some(250)),
+    None,
+    None,
+    None,
+    DOWNLINK_500_KHZ_DATA_RATES[0],
+    DOWNLINK_500_KHZ_DATA_RATES[1],
+    DOWNLINK_500_KHZ_DATA_RATES[2],
+    DOWNLINK_500_KHZ_DATA_RATES[3],
+    DOWNLINK_500_KHZ_DATA_RATES[4],
+    DOWNLINK_500_KHZ_DATA_RATES[5],
+];
+
+const AU_DATA_RATES: [Option<DataRateParameters>; 14] = [
+    EU_DATA_RATES[0],
+    EU_DATA_RATES[1],
+    EU_DATA_RATES[2],
+    EU_DATA_RATES[3],
+    EU_DATA_RATES[4],
+    EU_DATA_RATES[5],
+    dr(SpreadingFactor::SF8, Bandwidth::BW500, 250, Some(250)),
+    None,
+    DOWNLINK_500_KHZ_DATA_RATES[0],
+    DOWNLINK_500_KHZ_DATA_RATES[1],
+    DOWNLINK_500_KHZ_DATA_RATES[2],
+    DOWNLINK_500_KHZ_DATA_RATES[3],
+    DOWNLINK_500_KHZ_DATA_RATES[4],
+    DOWNLINK_500_KHZ_DATA_RATES[5],
+];
+
+/** RX1 data rate is the uplink data rate minus the offset (at least DR0) */
+const fn rx1_minus_offset(max: u8) -> Rx1DataRates {
+    Rx1DataRates {
+        base: 0,
+        min: 0,
+        max,
+        max_offset: 5,
+        negative_offsets: false,
+    }
+}
+
+static EU863: RegionalParameters = RegionalParameters {
+    band: Band::EU863,
+    default_channels: &[
+        868_100_000,
+        868_300_000,
+        868_500_000,
+        867_100_000,
+        867_300_000,
+        867_500_000,
+        867_700_000,
+        867_900_000,
+    ],
+    uplink: &[block(868_100_000, 200_000, 3, (0, 5))],
+    downlink: &[],
+    data_rates: &EU_DATA_RATES,
+    max_uplink_data_rate: 6,
+    rx1_data_rates: rx1_minus_offset(6),
+    sub_bands: 0,
+    rx2_frequency: 869_525_000,
+    rx2_data_rate: 0,
+    max_eirp: 16.0,
+    max_tx_power: 7,
+    max_dwell_time: None,
+};
+
+static US901: RegionalParameters = RegionalParameters {
+    band: Band::US901,
+    // Sub-band 2 (channels 8 - 15)
+    default_channels: &[
+        903_900_000,
+        904_100_000,
+        904_300_000,
+        904_500_000,
+        904_700_000,
+        904_900_000,
+        905_100_000,
+        905_300_000,
+    ],
+    uplink: &[
+        block(902_300_000, 200_000, 64, (0, 3)),
+        block(903_000_000, 1_600_000, 8, (4, 4)),
+    ],
+    downlink: &[block(923_300_000, 600_000, 8, (8, 13))],
+    data_rates: &US_DATA_RATES,
+    max_uplink_data_rate: 4,
+    rx1_data_rates: Rx1DataRates {
+        base: 10,
+        min: 8,
+        max: 13,
+        max_offset: 3,
+        negative_offsets: false,
+    },
+    sub_bands: 8,
+    rx2_frequency: 923_300_000,
+    rx2_data_rate: 8,
+    max_eirp: 30.0,
+    max_tx_power: 14,
+    max_dwell_time: DWELL_TIME_400_MS,
+};
+
+static AS920: RegionalParameters = RegionalParameters {
+    band: Band::AS920,
+    default_channels: &[
+        923_200_000,
+        923_400_000,
+        922_200_000,
+        922_400_000,
+        922_600_000,
+        922_800_000,
+        923_000_000,
+        922_000_000,
+    ],
+    uplink: &[block(923_200_000, 200_000, 2, (0, 5))],
+    downlink: &[],
+    data_rates: &EU_DATA_RATES,
+    max_uplink_data_rate: 6,
+    rx1_data_rates: Rx1DataRates {
+        base: 0,
+        min: 0,
+        max: 5,
+        max_offset: 7,
+        negative_offsets: true,
+    },
+    sub_bands: 0,
+    rx2_frequency: 923_200_000,
+    rx2_data_rate: 2,
+    max_eirp: 16.0,
+    max_tx_power: 7,
+    max_dwell_time: DWELL_TIME_400_MS,
+};
+
+static AU915: RegionalParameters = RegionalParameters {
+    band: Band::AU915,
+    // Sub-band 2 (channels 8 - 15)
+    default_channels: &[
+        916_800_000,
+        917_000_000,
+        917_200_000,
+        917_400_000,
+        917_600_000,
+        917_800_000,
+        918_000_000,
+        918_200_000,
+    ],
+    uplink: &[
+        block(915_200_000, 200_000, 64, (0, 5)),
+        block(915_900_000, 1_600_000, 8, (6, 6)),
+    ],
+    downlink: &[block(923_300_000, 600_000, 8, (8, 13))],
+    data_rates: &AU_DATA_RATES,
+    max_uplink_data_rate: 6,
+    rx1_data_rates: Rx1DataRates {
+        base: 8,
+        min: 8,
+        max: 13,
+        max_offset: 5,
+        negative_offsets: false,
+    },
+    sub_bands: 8,
+    rx2_frequency: 923_300_000,
+    rx2_data_rate: 8,
+    max_eirp: 30.0,
+    max_tx_power: 14,
+    max_dwell_time: DWELL_TIME_400_MS,
+};
+
+static KR920: RegionalParameters = RegionalParameters {
+    band: Band::KR920,
+    default_channels: &[922_100_000, 922_300_000, 922_500_000],
+    uplink: &[block(922_100_000, 200_000, 3, (0, 5))],
+    downlink: &[],
+    data_rates: &[
+        EU_DATA_RATES[0],
+        EU_DATA_RATES[1],
+        EU_DATA_RATES[2],
+        EU_DATA_RATES[3],
+        EU_DATA_RATES[4],
+        EU_DATA_RATES[5],
+    ],
+    max_uplink_data_rate: 5,
+    rx1_data_rates: rx1_minus_offset(5),
+    sub_bands: 0,
+    rx2_frequency: 921_900_000,
+    rx2_data_rate: 0,
+    max_eirp: 14.0,
+    max_tx_power: 7,
+    max_dwell_time: None,
+};
+
+static IN865: RegionalParameters = RegionalParameters {
+    band: Band::IN865,
+    default_channels: &[865_062_500, 865_402_500, 865_985_000],
+    uplink: &[
+        block(865_062_500, 0, 1, (0, 5)),
+        block(865_402_500, 0, 1, (0, 5)),
+        block(865_985_000, 0, 1, (0, 5)),
+    ],
+    downlink: &[],
+    data_rates: &[
+        EU_DATA_RATES[0],
+        EU_DATA_RATES[1],
+        EU_DATA_RATES[2],
+        EU_DATA_RATES[3],
+        EU_DATA_RATES[4],
+        EU_DATA_RATES[5],
+    ],
+    max_uplink_data_rate: 5,
+    rx1_data_rates: Rx1DataRates {
+        base: 0,
+        min: 0,
+        max: 5,
+        max_offset: 7,
+        negative_offsets: true,
+    },
+    sub_bands: 0,
+    rx2_frequency: 866_550_000,
+    rx2_data_rate: 2,
+    max_eirp: 30.0,
+    max_tx_power: 10,
+    max_dwell_time: None,
+};
+
+static CN470: RegionalParameters = RegionalParameters {
+    band: Band::CN470,
+    default_channels: &[
+        470_300_000,
+        470_500_000,
+        470_700_000,
+        470_900_000,
+        471_100_000,
+        471_300_000,
+        471_500_000,
+        471_700_000,
+    ],
+    uplink: &[block(470_300_000, 200_000, 96, (0, 5))],
+    downlink: &[block(500_300_000, 200_000, 48, (0, 5))],
+    data_rates: &[
+        EU_DATA_RATES[0],
+        EU_DATA_RATES[1],
+        EU_DATA_RATES[2],
+        EU_DATA_RATES[3],
+        EU_DATA_RATES[4],
+        EU_DATA_RATES[5],
+    ],
+    max_uplink_data_rate: 5,
+    rx1_data_rates: rx1_minus_offset(5),
+    sub_bands: 12,
+    rx2_frequency: 505_300_000,
+    rx2_data_rate: 0,
+    max_eirp: 19.15,
+    max_tx_power: 7,
+    max_dwell_time: None,
+};
+
+static EU433: RegionalParameters = RegionalParameters {
+    band: Band::EU433,
+    default_channels: &[433_175_000, 433_375_000, 433_575_000],
+    uplink: &[block(433_175_000, 200_000, 3, (0, 5))],
+    downlink: &[],
+    data_rates: &EU_DATA_RATES,
+    max_uplink_data_rate: 6,
+    rx1_data_rates: rx1_minus_offset(6),
+    sub_bands: 0,
+    rx2_frequency: 434_665_000,
+    rx2_data_rate: 0,
+    max_eirp: 12.15,
+    max_tx_power: 5,
+    max_dwell_time: None,
+};
+
+impl Band {
+    /** Regional parameters of the band */
+    pub fn parameters(&self) -> &'static RegionalParameters {
+        match self {
+            Band::EU863 => &EU863,
+            Band::US901 => &US901,
+            Band::AS920 => &AS920,
+            Band::AU915 => &AU915,
+            Band::KR920 => &KR920,
+            Band::IN865 => &IN865,
+            Band::CN470 => &CN470,
+            Band::EU433 => &EU433,
+        }
+    }
+}
+
+impl RegionalParameters {
+    /** Number of uplink channels defined by the band; bands with a dynamic channel plan can add further
+     * channels with NewChannelReq */
+    pub fn uplink_channel_count(&self) -> u8 {
+        self.uplink.iter().map(|block| block.count).sum()
+    }
+
+    /** Frequency (Hz) of an uplink channel, numbered as in the regional parameters */
+    pub fn uplink_frequency(&self, channel: u8) -> Result<u32> {
+        let (block, index) = self.uplink_block(channel)?;
+        Ok(block.first + block.spacing * index as u32)
+    }
+
+    /** Data rates allowed on an uplink channel */
+    pub fn uplink_data_rates(&self, channel: u8) -> Result<RangeInclusive<u8>> {
+        let (block, _) = self.uplink_block(channel)?;
+        Ok(block.min_data_rate..=block.max_data_rate)
+    }
+
+    /** Frequencies (Hz) of all uplink channels */
+    pub fn uplink_channels(&self) -> Vec<u32> {
+        channel_frequencies(self.uplink)
+    }
+
+    /** Frequencies (Hz) of the downlink channels, which equal the uplink channels in bands without
+     * dedicated downlink channels */
+    pub fn downlink_channels(&self) -> Vec<u32> {
+        if self.downlink.is_empty() {
+            self.uplink_channels()
+        } else {
+            channel_frequencies(self.downlink)
+        }
+    }
+
+    /** Frequency (Hz) of the RX1 window after an uplink on the channel */
+    pub fn rx1_frequency(&self, uplink_channel: u8) -> Result<u32> {
+        let uplink = self.uplink_frequency(uplink_channel)?;
+        if self.downlink.is_empty() {
+            return Ok(uplink);
+        }
+        let downlink = self.downlink_channels();
+        Ok(downlink[uplink_channel as usize % downlink.len()])
+    }
+
+    /** Data rate of the RX1 window after an uplink at `uplink_data_rate` with the given RX1DROffset */
+    pub fn rx1_data_rate(&self, uplink_data_rate: u8, offset: u8) -> Result<u8> {
+        let rates = self.rx1_data_rates;
+        if uplink_data_rate > self.max_uplink_data_rate {
+            return Err(Error::InvalidDataRate(uplink_data_rate, self.band));
+        }
+        if offset > rates.max_offset {
+            return Err(Error::InvalidDataRateOffset(offset, self.band));
+        }
+        let offset = if rates.negative_offsets && offset > 5 {
+            5 - offset as i16
+        } else {
+            offset as i16
+        };
+        let data_rate = uplink_data_rate as i16 + rates.base as i16 - offset;
+        Ok(data_rate.clamp(rates.min as i16, rates.max as i16) as u8)
+    }
+
+    pub fn data_rate(&self, data_rate: u8) -> Result<DataRateParameters> {
+        self.data_rates
+            .get(data_rate as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::InvalidDataRate(data_rate, self.band))
+    }
+
+    /** Maximum MACPayload length at the data rate, with or without the dwell time limit. The limit is
+     * ignored in bands without dwell time rules. */
+    pub fn max_mac_payload(&self, data_rate: u8, dwell_time_limited: bool) -> Result<u8> {
+        let parameters = self.data_rate(data_rate)?;
+        if !dwell_time_limited || self.max_dwell_time.is_none() {
+            return Ok(parameters.max_mac_payload);
+        }
+        parameters
+            .max_mac_payload_dwell_time
+            .ok_or(Error::InvalidDataRate(data_rate, self.band))
+    }
+
+    /** Uplink channels of a sub-band as a mask with bit n set for channel n. Sub-band k holds the 125 kHz
+     * channels 8k to 8k + 7 and, where the band has them, the 500 kHz channel 64 + k. Sub-bands are
+     * numbered from 0. */
+    pub fn sub_band_mask(&self, sub_band: u8) -> Result<u128> {
+        if sub_band >= self.sub_bands {
+            return Err(Error::InvalidSubBand(sub_band, self.band));
+        }
+        let mut mask = 0xFFu128 << (8 * sub_band as u32);
+        if let Some(wide) = self.uplink.get(1) {
+            if sub_band < wide.count {
+                mask |= 1u128 << (self.uplink[0].count as u32 + sub_band as u32);
+            }
+        }
+        Ok(mask)
+    }
+
+    /** Frequency (Hz) of a channel in the band */
+    pub fn channel_frequency(&self, channel: Channel) -> Result<u32> {
+        let index = match channel {
+            Channel::Frequency(frequency) => return Ok(frequency),
+            Channel::Uplink(number) => return self.uplink_frequency(number),
+            Channel::Ch9 if self.band == Band::EU863 => return Ok(self.rx2_frequency),
+            Channel::Ch9 | Channel::Multi => {
+                return Err(Error::InvalidChannel(channel, self.band));
+            }
+            Channel::Ch0 => 0,
+            Channel::Ch1 => 1,
+            Channel::Ch2 => 2,
+            Channel::Ch3 => 3,
+            Channel::Ch4 => 4,
+            Channel::Ch5 => 5,
+            Channel::Ch6 => 6,
+            Channel::Ch7 => 7,
+        };
+        self.default_channels
+            .get(index)
+            .copied()
+            .ok_or(Error::InvalidChannel(channel, self.band))
+    }
+
+    fn uplink_block(&self, channel: u8) -> Result<(&ChannelBlock, u8)> {
+        let mut index = channel;
+        for block in self.uplink {
+            if index < block.count {
+                return Ok((block, index));
+            }
+            index -= block.count;
+        }
+        Err(Error::InvalidChannel(Channel::Uplink(channel), self.band))
+    }
+}
+
+fn channel_frequencies(blocks: &[ChannelBlock]) -> Vec<u32> {
+    blocks
+        .iter()
+        .flat_map(|block| (0..block.count as u32).map(move |i| block.first + block.spacing * i))
+        .collect()
+}
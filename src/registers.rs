@@ -0,0 +1,578 @@
+/** Register map of the SX1276 (RFM95/96/97/98), see chapter 6 of the data sheet.
+ *
+ * The register file has two pages: registers 0x00 - 0x3F have a different meaning depending on whether
+ * the chip is in LoRa mode (`Register`) or FSK/OOK mode (`FskRegister`). Registers 0x40 and up are
+ * shared by both modes and are listed in `Register`. */
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Register {
+    /** Taken from the LoRA register map (6.4, p. 102) in the RFM data sheet. Only accessible in LoRA mode */
+    FIFO = 0x00,
+    OpMode = 0x01,
+    /* 0x02 - 0x05 RESERVED */
+    FRFMSB = 0x06,
+    FRFMID = 0x07,
+    FRFLSB = 0x08,
+    PAConfig = 0x09,
+    PARamp = 0x0A,
+    OverCurrentProtection = 0x0B,
+    LNA = 0x0C,
+    FIFOAddressPointer = 0x0D,
+    FIFOTXBaseAddress = 0x0E,
+    FIFORXBaseAddress = 0x0F,
+    FIFORXCurrent = 0x10,
+    IRQFlagsMask = 0x11, // Optional flags mask
+    IRQFlags = 0x12,
+    ReceiveNumberOfBytes = 0x13, // Number of payload bytes of latest packet received
+    ReceiveValidHeaderCountMSB = 0x14, // Number of valid headers received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
+    ReceiveValidHeaderCountLSB = 0x15, // Number of valid headers received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
+    ReceiveValidPacketCountMSB = 0x16, // Number of valid packets received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
+    ReceiveValidPacketCountLSB = 0x17, // Number of valid packets received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
+    ModemStatus = 0x18,
+    LastSNRValue = 0x19, // Estimation of SNR on last packet received.In two’s compliment format mutiplied by 4.
+    LastRSSIValue = 0x1A, // RSSI of the latest packet received (dBm)
+    RSSIValue = 0x1B,    // Current RSSI value (dBm)
+    HopChannel = 0x1C,
+    ModemConfig1 = 0x1D,
+    ModemConfig2 = 0x1E,
+    SymbolTimeoutLSB = 0x1F,
+    PreambleLengthMSB = 0x20,
+    PreambleLengthLSB = 0x21,
+    PayloadLength = 0x22, // Payload length in bytes. The register needs to be set in implicit header mode for the expected packet length. A 0 value is not permitted
+    MaxPayloadLength = 0x23, // Maximum payload length; if header payload length exceeds value a header CRC error is generated. Allows filtering of packet with a bad size.
+    HopPeriod = 0x24, // Symbol periods between frequency hops. (0 = disabled). 1st hop always happen after the 1st header symbol
+    FIFOReceiveAddress = 0x25, // Current value of RX databuffer pointer (address of last byte written by Lora receiver)
+    ModemConfig3 = 0x26,
+    PPMCorrection = 0x27, // Data rate offset value, used in conjunction with AFC
+    FrequencyErrorMSB = 0x28, // Estimated frequency error from modem (20 bit two's complement, MSB in bits 3-0)
+    FrequencyErrorMID = 0x29,
+    FrequencyErrorLSB = 0x2A,
+    /* 0x2B RESERVED */
+    RSSIWideband = 0x2C, // Wideband RSSI measurement used to locally generate a random number
+    /* 0x2D - 0x2E RESERVED */
+    IFFrequency2 = 0x2F,
+    IFFrequency1 = 0x30,
+    DetectOptimize = 0x31, // LoRa detection optimize: 0x03 for SF7 to SF12, 0x05 for SF6
+    /* 0x32 RESERVED */
+    InvertIQ = 0x33,
+    /* 0x34 - 0x35 RESERVED */
+    HighBWOptimize1 = 0x36,
+    DetectionThreshold = 0x37, // LoRa detection threshold: 0x0A for SF7 to SF12, 0x0C for SF6
+    /* 0x38 RESERVED */
+    SyncWord = 0x39, // LoRa sync word (0x34 is reserved for LoRaWAN networks)
+    HighBWOptimize2 = 0x3A,
+    InvertIQ2 = 0x3B,
+
+    /** Taken from Table 85, available in either mode */
+    DIOMapping1 = 0x40,
+    DIOMapping2 = 0x41,
+    Version = 0x42,
+    PLLHop = 0x44,
+    TCXO = 0x4B,
+    PADAC = 0x4D, // Higher power settings of the PA (+20 dBm on PA_BOOST)
+    FormerTemperature = 0x5B,
+    BitRateFraction = 0x5D, // Fractional part of the bit rate divider (FSK only)
+    AGCReference = 0x61,
+    AGCThreshold1 = 0x62,
+    AGCThreshold2 = 0x63,
+    AGCThreshold3 = 0x64,
+    PLL = 0x70,
+}
+
+impl Register {
+    /** All registers of the LoRa page, including the shared registers, ordered by address */
+    pub const ALL: [Register; 62] = [
+        Register::FIFO,
+        Register::OpMode,
+        Register::FRFMSB,
+        Register::FRFMID,
+        Register::FRFLSB,
+        Register::PAConfig,
+        Register::PARamp,
+        Register::OverCurrentProtection,
+        Register::LNA,
+        Register::FIFOAddressPointer,
+        Register::FIFOTXBaseAddress,
+        Register::FIFORXBaseAddress,
+        Register::FIFORXCurrent,
+        Register::IRQFlagsMask,
+        Register::IRQFlags,
+        Register::ReceiveNumberOfBytes,
+        Register::ReceiveValidHeaderCountMSB,
+        Register::ReceiveValidHeaderCountLSB,
+        Register::ReceiveValidPacketCountMSB,
+        Register::ReceiveValidPacketCountLSB,
+        Register::ModemStatus,
+        Register::LastSNRValue,
+        Register::LastRSSIValue,
+        Register::RSSIValue,
+        Register::HopChannel,
+        Register::ModemConfig1,
+        Register::ModemConfig2,
+        Register::SymbolTimeoutLSB,
+        Register::PreambleLengthMSB,
+        Register::PreambleLengthLSB,
+        Register::PayloadLength,
+        Register::MaxPayloadLength,
+        Register::HopPeriod,
+        Register::FIFOReceiveAddress,
+        Register::ModemConfig3,
+        Register::PPMCorrection,
+        Register::FrequencyErrorMSB,
+        Register::FrequencyErrorMID,
+        Register::FrequencyErrorLSB,
+        Register::RSSIWideband,
+        Register::IFFrequency2,
+        Register::IFFrequency1,
+        Register::DetectOptimize,
+        Register::InvertIQ,
+        Register::HighBWOptimize1,
+        Register::DetectionThreshold,
+        Register::SyncWord,
+        Register::HighBWOptimize2,
+        Register::InvertIQ2,
+        Register::DIOMapping1,
+        Register::DIOMapping2,
+        Register::Version,
+        Register::PLLHop,
+        Register::TCXO,
+        Register::PADAC,
+        Register::FormerTemperature,
+        Register::BitRateFraction,
+        Register::AGCReference,
+        Register::AGCThreshold1,
+        Register::AGCThreshold2,
+        Register::AGCThreshold3,
+        Register::PLL,
+    ];
+
+    /** Value of the register after power-on reset (Table 41 in the data sheet) */
+    pub fn reset_value(self) -> u8 {
+        match self {
+            Register::OpMode => 0x09,
+            Register::FRFMSB => 0x6C,
+            Register::FRFMID => 0x80,
+            Register::PAConfig => 0x4F,
+            Register::PARamp => 0x09,
+            Register::OverCurrentProtection => 0x2B,
+            Register::LNA => 0x20,
+            Register::FIFOTXBaseAddress => 0x80,
+            Register::ModemConfig1 => 0x72,
+            Register::ModemConfig2 => 0x70,
+            Register::SymbolTimeoutLSB => 0x64,
+            Register::PreambleLengthLSB => 0x08,
+            Register::PayloadLength => 0x01,
+            Register::MaxPayloadLength => 0xFF,
+            Register::DetectOptimize => 0xC3,
+            Register::InvertIQ => 0x27,
+            Register::HighBWOptimize1 => 0x03,
+            Register::DetectionThreshold => 0x0A,
+            Register::SyncWord => 0x12,
+            Register::HighBWOptimize2 => 0x52,
+            Register::InvertIQ2 => 0x1D,
+            Register::Version => 0x12,
+            Register::PLLHop => 0x2D,
+            Register::TCXO => 0x09,
+            Register::PADAC => 0x84,
+            Register::AGCReference => 0x13,
+            Register::AGCThreshold1 => 0x0E,
+            Register::AGCThreshold2 => 0x5B,
+            Register::AGCThreshold3 => 0xDB,
+            Register::PLL => 0xD0,
+            _ => 0x00,
+        }
+    }
+
+    /** Whether writes to this register are ignored by the chip */
+    pub fn is_read_only(self) -> bool {
+        matches!(
+            self,
+            Register::FIFORXCurrent
+                | Register::ReceiveNumberOfBytes
+                | Register::ReceiveValidHeaderCountMSB
+                | Register::ReceiveValidHeaderCountLSB
+                | Register::ReceiveValidPacketCountMSB
+                | Register::ReceiveValidPacketCountLSB
+                | Register::ModemStatus
+                | Register::LastSNRValue
+                | Register::LastRSSIValue
+                | Register::RSSIValue
+                | Register::HopChannel
+                | Register::FIFOReceiveAddress
+                | Register::FrequencyErrorMSB
+                | Register::FrequencyErrorMID
+                | Register::FrequencyErrorLSB
+                | Register::RSSIWideband
+                | Register::Version
+                | Register::FormerTemperature
+        )
+    }
+}
+
+impl From<Register> for u8 {
+    fn from(register: Register) -> u8 {
+        register as u8
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum FskRegister {
+    /** Taken from the FSK/OOK register map (Table 41) in the data sheet. Only accessible in FSK/OOK mode.
+     * The registers from 0x40 up are shared with LoRa mode and can be found in `Register`. */
+    FIFO = 0x00,
+    OpMode = 0x01,
+    BitrateMSB = 0x02,
+    BitrateLSB = 0x03,
+    FrequencyDeviationMSB = 0x04,
+    FrequencyDeviationLSB = 0x05,
+    FRFMSB = 0x06,
+    FRFMID = 0x07,
+    FRFLSB = 0x08,
+    PAConfig = 0x09,
+    PARamp = 0x0A,
+    OverCurrentProtection = 0x0B,
+    LNA = 0x0C,
+    ReceiveConfig = 0x0D,
+    RSSIConfig = 0x0E,
+    RSSICollision = 0x0F,
+    RSSIThreshold = 0x10,
+    RSSIValue = 0x11,
+    ReceiveBandwidth = 0x12,
+    AFCBandwidth = 0x13,
+    OOKPeak = 0x14,
+    OOKFix = 0x15,
+    OOKAverage = 0x16,
+    /* 0x17 - 0x19 RESERVED */
+    AFCFEI = 0x1A,
+    AFCMSB = 0x1B,
+    AFCLSB = 0x1C,
+    FrequencyErrorMSB = 0x1D,
+    FrequencyErrorLSB = 0x1E,
+    PreambleDetect = 0x1F,
+    ReceiveTimeout1 = 0x20,
+    ReceiveTimeout2 = 0x21,
+    ReceiveTimeout3 = 0x22,
+    ReceiveDelay = 0x23,
+    Oscillator = 0x24,
+    PreambleLengthMSB = 0x25,
+    PreambleLengthLSB = 0x26,
+    SyncConfig = 0x27,
+    SyncValue1 = 0x28,
+    SyncValue2 = 0x29,
+    SyncValue3 = 0x2A,
+    SyncValue4 = 0x2B,
+    SyncValue5 = 0x2C,
+    SyncValue6 = 0x2D,
+    SyncValue7 = 0x2E,
+    SyncValue8 = 0x2F,
+    PacketConfig1 = 0x30,
+    PacketConfig2 = 0x31,
+    PayloadLength = 0x32,
+    NodeAddress = 0x33,
+    BroadcastAddress = 0x34,
+    FIFOThreshold = 0x35,
+    SequencerConfig1 = 0x36,
+    SequencerConfig2 = 0x37,
+    TimerResolution = 0x38,
+    Timer1Coefficient = 0x39,
+    Timer2Coefficient = 0x3A,
+    ImageCalibration = 0x3B,
+    Temperature = 0x3C,
+    LowBattery = 0x3D,
+    IRQFlags1 = 0x3E,
+    IRQFlags2 = 0x3F,
+}
+
+impl FskRegister {
+    /** Value of the register after power-on reset (Table 41 in the data sheet) */
+    pub fn reset_value(self) -> u8 {
+        match self {
+            FskRegister::OpMode => 0x09,
+            FskRegister::BitrateMSB => 0x1A,
+            FskRegister::BitrateLSB => 0x0B,
+            FskRegister::FrequencyDeviationLSB => 0x52,
+            FskRegister::FRFMSB => 0x6C,
+            FskRegister::FRFMID => 0x80,
+            FskRegister::PAConfig => 0x4F,
+            FskRegister::PARamp => 0x09,
+            FskRegister::OverCurrentProtection => 0x2B,
+            FskRegister::LNA => 0x20,
+            FskRegister::ReceiveConfig => 0x0E,
+            FskRegister::RSSIConfig => 0x02,
+            FskRegister::RSSICollision => 0x0A,
+            FskRegister::RSSIThreshold => 0xFF,
+            FskRegister::ReceiveBandwidth => 0x15,
+            FskRegister::AFCBandwidth => 0x0B,
+            FskRegister::OOKPeak => 0x28,
+            FskRegister::OOKFix => 0x0C,
+            FskRegister::OOKAverage => 0x12,
+            FskRegister::PreambleDetect => 0x40,
+            FskRegister::Oscillator => 0x05,
+            FskRegister::PreambleLengthLSB => 0x03,
+            FskRegister::SyncConfig => 0x93,
+            FskRegister::SyncValue1
+            | FskRegister::SyncValue2
+            | FskRegister::SyncValue3
+            | FskRegister::SyncValue4
+            | FskRegister::SyncValue5
+            | FskRegister::SyncValue6
+            | FskRegister::SyncValue7
+            | FskRegister::SyncValue8 => 0x55,
+            FskRegister::PacketConfig1 => 0x90,
+            FskRegister::PacketConfig2 => 0x40,
+            FskRegister::PayloadLength => 0x40,
+            FskRegister::FIFOThreshold => 0x0F,
+            FskRegister::Timer1Coefficient => 0xF5,
+            FskRegister::Timer2Coefficient => 0x20,
+            FskRegister::ImageCalibration => 0x82,
+            FskRegister::LowBattery => 0x02,
+            FskRegister::IRQFlags1 => 0x80,
+            FskRegister::IRQFlags2 => 0x40,
+            _ => 0x00,
+        }
+    }
+}
+
+impl From<FskRegister> for u8 {
+    fn from(register: FskRegister) -> u8 {
+        register as u8
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Mode: u8 {
+        // See p. 102, RegOpMode
+        const SLEEP = 0b000;
+        const STANDBY = 0b001;
+        const FREQUENCY_SYNTHESIS_TRANSMIT = 0b010;
+        const TRANSMIT = 0b011;
+        const FREQUENCY_SYNTHESIS_RECEIVE = 0b100;
+        const RECEIVE_CONTINUOUS = 0b101;
+        const RECEIVE_SINGLE = 0b110;
+        const CHANNEL_ACTIVITY_DETECTION = 0b111;
+
+        const LORA = 0b1000_0000;
+        const ACCESS_SHARED_REGISTERS = 0b0100_0000;
+        const RESERVED_5 = 0b0010_0000;
+        const RESERVED_4 = 0b0001_0000;
+        const LOW_FREQUENCY_MODE = 0b0000_1000;
+    }
+}
+
+bitflags! {
+    // RegOpMode in FSK/OOK mode; the mode bits 2-0 are the same as in `Mode`
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FskModeFlags: u8 {
+        const MODULATION_FSK = 0b0000_0000;
+        const MODULATION_OOK = 0b0010_0000;
+        const LOW_FREQUENCY_MODE = 0b0000_1000;
+    }
+}
+
+bitflags! {
+    // See RegPaConfig: OutputPower in bits 3-0, MaxPower in bits 6-4
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct PAConfigFlags: u8 {
+        const PA_SELECT_BOOST = 0b1000_0000; // Output on PA_BOOST instead of RFO
+        const MAX_POWER_MASK = 0b0111_0000;
+        const OUTPUT_POWER_MASK = 0b0000_1111;
+    }
+}
+
+bitflags! {
+    // See RegOcp: trimming of the over current protection in bits 4-0
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct OverCurrentProtectionFlags: u8 {
+        const OCP_ON = 0b0010_0000;
+        const OCP_TRIM_MASK = 0b0001_1111;
+    }
+}
+
+bitflags! {
+    // See RegLna
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct LNAFlags: u8 {
+        const GAIN_G1 = 0b0010_0000; // Maximum gain
+        const GAIN_G2 = 0b0100_0000;
+        const GAIN_G3 = 0b0110_0000;
+        const GAIN_G4 = 0b1000_0000;
+        const GAIN_G5 = 0b1010_0000;
+        const GAIN_G6 = 0b1100_0000; // Minimum gain
+
+        const BOOST_HF_ON = 0b0000_0011; // Boost on, 150% LNA current (HF port only)
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ModemConfig3Flags: u8 {
+        const IS_MOBILE_NODE = 0b0000_1000;
+        const AUTO_AGC_ON = 0b0000_0100;
+    }
+}
+
+bitflags! {
+    // See page 106 of data sheet: RegModemConfig1
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ModemConfig1Flags: u8 {
+        const BW7_8 = 0b0000_0000;
+        const BW10_4 = 0b0001_0000;
+        const BW15_6 = 0b0010_0000;
+        const BW20_8 = 0b0011_0000;
+        const BW31_25 = 0b0100_0000;
+        const BW41_7 = 0b0101_0000;
+        const BW62_5 = 0b0110_0000;
+        const BW125 = 0b0111_0000;
+        const BW250 = 0b1000_0000;
+        const BW500 = 0b1001_0000;
+
+        const CODING_RATE_4_5 = 0b0000_0010;
+        const CODING_RATE_4_6 = 0b0000_0100;
+        const CODING_RATE_4_7 = 0b0000_0110;
+        const CODING_RATE_4_8 = 0b0000_1000;
+
+        const IMPLICIT_HEADER_MODE_ON = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ModemConfig2Flags: u8 {
+        const SF6 = 0x60;
+        const SF7 = 0x70;
+        const SF8 = 0x80;
+        const SF9 = 0x90;
+        const SF10 = 0xA0;
+        const SF11 = 0xB0;
+        const SF12 = 0xC0;
+
+        const TX_CONTINOUS_MODE_ON = 0b0000_1000; // Continuous mode, send multiple packets across the FIFO (used for spectral analysis)
+        const RX_PAYLOAD_CRC_FOUND = 0b0000_0100; // CRC Information extracted from the received packet header
+
+        const SYMBOL_TIMEOUT_MSB_1 = 0b0000_0010;
+        const SYMBOL_TIMEOUT_MSB_0 = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct IRQFlags: u8 {
+        const CHANNEL_ACTIVITY_DETECTED = 0b0000_0001;
+        const FHSS_CHANGE_CHANNEL = 0b0000_0010;
+        const CHANNEL_ACTIVITY_DETECTION_DONE = 0b0000_0100;
+        const TRANSMIT_DONE = 0b0000_1000;
+        const VALID_HEADER_RECEIVED = 0b0001_0000;
+        const PAYLOAD_CRC_ERROR = 0b0010_0000;
+        const RECEIVE_DONE = 0b0100_0000;
+        const RECEIVE_TIMEOUT = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    // See RegModemStat: the coding rate of the last header received is in bits 7-5
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ModemStatusFlags: u8 {
+        const RX_CODING_RATE_MASK = 0b1110_0000;
+        const MODEM_CLEAR = 0b0001_0000;
+        const HEADER_INFO_VALID = 0b0000_1000;
+        const RX_ONGOING = 0b0000_0100;
+        const SIGNAL_SYNCHRONIZED = 0b0000_0010;
+        const SIGNAL_DETECTED = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    // See RegHopChannel
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct HopChannelFlags: u8 {
+        const PLL_TIMEOUT = 0b1000_0000;
+        const CRC_ON_PAYLOAD = 0b0100_0000; // CRC information extracted from the received packet header
+        const FHSS_PRESENT_CHANNEL_MASK = 0b0011_1111;
+    }
+}
+
+bitflags! {
+    // See RegInvertIQ. Note that bit 0 set means the TX path is *not* inverted.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct InvertIQFlags: u8 {
+        const INVERT_IQ_RX = 0b0100_0000;
+        const NORMAL_IQ_TX = 0b0000_0001;
+        const RESERVED = 0b0010_0110;
+    }
+}
+
+bitflags! {
+    // See Table 18 (LoRa mode) for the meaning of each mapping
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DIOMapping1Flags: u8 {
+        const DIO0_RX_DONE = 0b0000_0000;
+        const DIO0_TX_DONE = 0b0100_0000;
+        const DIO0_CAD_DONE = 0b1000_0000;
+
+        const DIO1_RX_TIMEOUT = 0b0000_0000;
+        const DIO1_FHSS_CHANGE_CHANNEL = 0b0001_0000;
+        const DIO1_CAD_DETECTED = 0b0010_0000;
+
+        const DIO2_FHSS_CHANGE_CHANNEL = 0b0000_0000;
+
+        const DIO3_CAD_DONE = 0b0000_0000;
+        const DIO3_VALID_HEADER = 0b0000_0001;
+        const DIO3_PAYLOAD_CRC_ERROR = 0b0000_0010;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DIOMapping2Flags: u8 {
+        const DIO4_CAD_DETECTED = 0b0000_0000;
+        const DIO4_PLL_LOCK = 0b0100_0000;
+
+        const DIO5_MODE_READY = 0b0000_0000;
+        const DIO5_CLK_OUT = 0b0001_0000;
+
+        const MAP_PREAMBLE_DETECT = 0b0000_0001; // FSK only: map PreambleDetect instead of Rssi
+    }
+}
+
+bitflags! {
+    // See RegPaDac: 0x04 is the default, 0x07 enables +20 dBm on PA_BOOST
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct PADACFlags: u8 {
+        const RESERVED = 0b1000_0000;
+        const DEFAULT = 0b0000_0100;
+        const HIGH_POWER = 0b0000_0111;
+    }
+}
+
+bitflags! {
+    // See RegIrqFlags1 (FSK/OOK mode)
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FskIRQFlags1: u8 {
+        const MODE_READY = 0b1000_0000;
+        const RX_READY = 0b0100_0000;
+        const TX_READY = 0b0010_0000;
+        const PLL_LOCK = 0b0001_0000;
+        const RSSI = 0b0000_1000;
+        const TIMEOUT = 0b0000_0100;
+        const PREAMBLE_DETECT = 0b0000_0010;
+        const SYNC_ADDRESS_MATCH = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    // See RegIrqFlags2 (FSK/OOK mode)
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FskIRQFlags2: u8 {
+        const FIFO_FULL = 0b1000_0000;
+        const FIFO_EMPTY = 0b0100_0000;
+        const FIFO_LEVEL = 0b0010_0000;
+        const FIFO_OVERRUN = 0b0001_0000;
+        const PACKET_SENT = 0b0000_1000;
+        const PAYLOAD_READY = 0b0000_0100;
+        const CRC_OK = 0b0000_0010;
+        const LOW_BATTERY = 0b0000_0001;
+    }
+}
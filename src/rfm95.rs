@@ -1,147 +1,18 @@
-use rand::Rng;
-use rppal::gpio::{Gpio, InputPin, Level, OutputPin, Trigger};
-use rppal::spi::{Segment, Spi};
-use std::error::Error;
-use std::fmt::Display;
+use crate::airtime::*;
+use crate::channel_selection::*;
+use crate::constants::*;
+use crate::duty_cycle::*;
+use crate::error::*;
+use crate::lbt::*;
+use crate::modulation::*;
+use crate::packet::*;
+use crate::pins::*;
+use crate::power::*;
+use crate::registers::*;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::{Operation, SpiDevice};
 use std::thread;
-use std::time::Duration;
-
-#[allow(dead_code)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum Register {
-    /** Taken from the LoRA register map (6.4, p. 102) in the RFM data sheet. Only accessible in LoRA mode */
-    FIFO = 0x00,
-    OpMode = 0x01,
-    /* 0x02 - 0x05 RESERVED */
-    FRFMSB = 0x06,
-    FRFMID = 0x07,
-    FRFLSB = 0x08,
-    PAConfig = 0x09,
-    PARamp = 0x0A,
-    OverCurrentProtection = 0x0B,
-    LNA = 0x0C,
-    FIFOAddressPointer = 0x0D,
-    FIFOTXBaseAddress = 0x0E,
-    FIFORXBaseAddress = 0x0F,
-    FIFORXCurrent = 0x10,
-    IRQFlagsMask = 0x11, // Optional flags mask
-    IRQFlags = 0x12,
-    ReceiveNumberOfBytes = 0x13, // Number of payload bytes of latest packet received
-    ReceiveValidHeaderCountMSB = 0x14, // Number of valid headers received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
-    ReceiveValidHeaderCountLSB = 0x15, // Number of valid headers received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
-    ReceiveValidPacketCountMSB = 0x16, // Number of valid packets received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
-    ReceiveValidPacketCountLSB = 0x17, // Number of valid packets received since last transition into Rx mode. Header and packet counters are reseted in Sleep mode.
-    ModemStatus = 0x18,
-    LastSNRValue = 0x19, // Estimation of SNR on last packet received.In two’s compliment format mutiplied by 4.
-    LastRSSIValue = 0x1A, // RSSI of the latest packet received (dBm)
-    RSSIValue = 0x1B,    // Current RSSI value (dBm)
-    HopChannel = 0x1C,
-    ModemConfig1 = 0x1D,
-    ModemConfig2 = 0x1E,
-    SymbolTimeoutLSB = 0x1F,
-    PreambleLengthMSB = 0x20,
-    PreambleLengthLSB = 0x21,
-    PayloadLength = 0x22, // Payload length in bytes. The register needs to be set in implicit header mode for the expected packet length. A 0 value is not permitted
-    MaxPayloadLength = 0x23, // Maximum payload length; if header payload length exceeds value a header CRC error is generated. Allows filtering of packet with a bad size.
-    HopPeriod = 0x24, // Symbol periods between frequency hops. (0 = disabled). 1st hop always happen after the 1st header symbol
-    FIFOReceiveAddress = 0x25, // Current value of RX databuffer pointer (address of last byte written by Lora receiver)
-    ModemConfig3 = 0x26,
-
-    // NodeAddress = 0x33,
-    // ImageCalibration = 0x3B,
-    Timer1Coefficient = 0x39,
-
-    /** Taken from Table 85, available in either mode */
-    DIOMapping1 = 0x40,
-    DIOMapping2 = 0x41,
-    Version = 0x42,
-}
-
-bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct Mode: u8 {
-        // See p. 102, RegOpMode
-        const SLEEP = 0b000;
-        const STANDBY = 0b001;
-        const FREQUENCY_SYNTHESIS_TRANSMIT = 0b010;
-        const TRANSMIT = 0b011;
-        const FREQUENCY_SYNTHESIS_RECEIVE = 0b100;
-        const RECEIVE_CONTINUOUS = 0b101;
-        const RECEIVE_SINGLE = 0b110;
-        const CHANNEL_ACTIVITY_DETECTION = 0b111;
-
-        const LORA = 0b1000_0000;
-        const ACCESS_SHARED_REGISTERS = 0b0100_0000;
-        const RESERVED_5 = 0b0010_0000;
-        const RESERVED_4 = 0b0001_0000;
-        const LOW_FREQUENCY_MODE = 0b0000_1000;
-    }
-}
-
-bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct ModemConfig3Flags: u8 {
-        const IS_MOBILE_NODE = 0b0000_1000;
-        const AUTO_AGC_ON = 0b0000_0100;
-    }
-}
-
-bitflags! {
-    // See page 106 of data sheet: RegModemConfig1
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct ModemConfig1Flags: u8 {
-        const BW7_8 = 0b0000_0000;
-        const BW10_4 = 0b0001_0000;
-        const BW15_6 = 0b0010_0000;
-        const BW20_8 = 0b0011_0000;
-        const BW31_25 = 0b0100_0000;
-        const BW41_7 = 0b0101_0000;
-        const BW62_5 = 0b0110_0000;
-        const BW125 = 0b0111_0000;
-        const BW250 = 0b1000_0000;
-        const BW500 = 0b1001_0000;
-
-        const CODING_RATE_4_5 = 0b0000_0010;
-        const CODING_RATE_4_6 = 0b0000_0100;
-        const CODING_RATE_4_7 = 0b0000_0110;
-        const CODING_RATE_4_8 = 0b0000_1000;
-
-        const IMPLICIT_HEADER_MODE_ON = 0b0000_0001;
-    }
-}
-
-bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct ModemConfig2Flags: u8 {
-        const SF7 = 0x70;
-        const SF8 = 0x80;
-        const SF9 = 0x90;
-        const SF10 = 0xA0;
-        const SF11 = 0xB0;
-        const SF12 = 0xC0;
-
-        const TX_CONTINOUS_MODE_ON = 0b0000_1000; // Continuous mode, send multiple packets across the FIFO (used for spectral analysis)
-        const RX_PAYLOAD_CRC_FOUND = 0b0000_0100; // CRC Information extracted from the received packet header
-
-        const SYMBOL_TIMEOUT_MSB_1 = 0b0000_0010;
-        const SYMBOL_TIMEOUT_MSB_0 = 0b0000_0001;
-    }
-}
-
-bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct IRQFlags: u8 {
-        const CHANNEL_ACTIVITY_DETECTED = 0b0000_0001;
-        const FHSS_CHANGE_CHANNEL = 0b0000_0010;
-        const CHANNEL_ACTIVITY_DETECTION_DONE = 0b0000_0100;
-        const TRANSMIT_DONE = 0b0000_1000;
-        const VALID_HEADER_RECEIVED = 0b0001_0000;
-        const PAYLOAD_CRC_ERROR = 0b0010_0000;
-        const RECEIVE_DONE = 0b0100_0000;
-        const RECEIVE_TIMEOUT = 0b1000_0000;
-    }
-}
+use std::time::{Duration, Instant};
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone)]
@@ -156,7 +27,7 @@ pub enum DataRate {
 }
 
 #[allow(dead_code)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Channel {
     Ch0,
     Ch1,
@@ -168,74 +39,132 @@ pub enum Channel {
     Ch7,
     Ch9,
     Multi,
+    /** Fixed frequency (Hz), independent of the band */
+    Frequency(u32),
+    /** Uplink channel numbered as in the LoRaWAN regional parameters of the band, e.g. 0 - 71 in US901 */
+    Uplink(u8),
 }
 
+/** Frequency band, with the channel plan of the corresponding LoRaWAN region (see `Band::parameters`) */
 #[allow(dead_code)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Band {
-    EU863,
-    US901,
-    AS920,
+    EU863, // EU868
+    US901, // US915
+    AS920, // AS923-1
+    AU915,
+    KR920,
+    IN865,
+    CN470,
+    EU433,
 }
 
-const RFM_VERSION: u8 = 0x12;
+/** Interval at which the IRQ pin is sampled while waiting for an interrupt */
+const IRQ_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/** Largest symbol timeout supported by `receive_single` (10 bits) */
+pub(crate) const MAX_SYMBOL_TIMEOUT: u16 = 0x3FF;
 
-pub struct RFM95 {
-    spi: Spi,
-    irq_pin: InputPin,
-    cs_bcm_pin: Option<u8>,
+/** Bits 1-0 of RegModemConfig2 hold bits 9-8 of the symbol timeout */
+const SYMBOL_TIMEOUT_MSB_MASK: u8 =
+    ModemConfig2Flags::SYMBOL_TIMEOUT_MSB_1.bits() | ModemConfig2Flags::SYMBOL_TIMEOUT_MSB_0.bits();
+
+/** Upper bound for the length of a packet in symbols: preamble and header plus 255 bytes at four symbols
+ * per byte (the worst case, SF7 with low data rate optimisation and coding rate 4/8, needs 3.2) */
+const MAX_PACKET_SYMBOLS: u32 = 1_060;
+
+/** Time allowed for a channel activity detection to complete in addition to its expected duration */
+const CAD_TIMEOUT_MARGIN: Duration = Duration::from_millis(10);
+
+/** Time allowed for TxDone in addition to the time on air of the packet */
+const TRANSMIT_TIMEOUT_MARGIN: Duration = Duration::from_millis(100);
+
+/** Longest payload the modem transmits (RegPayloadLength is a single byte) */
+pub(crate) const MAX_PAYLOAD_LENGTH: usize = 255;
+
+/** Driver for an RFM9x module, generic over the `embedded-hal` SPI device and the GPIO pins used
+ * for the DIO0 interrupt line, an optional (software) chip select and the optional reset line. */
+pub struct RFM95<SPI, IRQ, CS, RESET> {
+    spi: SPI,
+    irq_pin: IRQ,
+    cs_pin: Option<CS>,
+    cs_timing: ChipSelectTiming,
     tx_random_number: u8,
     tx_packets: u32,
-    data_rate: DataRate,
+    modulation: LoRaModulation,
     channel: Channel,
     band: Band,
-    reset_pin: Option<InputPin>,
-}
-
-pub struct ChipSelected {
-    cs_pin: Option<OutputPin>,
+    reset_pin: Option<RESET>,
+    keep_corrupted_packets: bool,
+    implicit_payload_length: u8,
+    listen_before_talk: Option<ListenBeforeTalk>,
+    duty_cycle: Option<DutyCycleTracker>,
+    channel_selector: Box<dyn ChannelSelector + Send>,
+    last_tx_channel: Option<Channel>,
+    /** Pick of the channel selector for `Channel::Multi` made ahead of the next operation */
+    next_channel: Option<Channel>,
+    rx_channel: Channel,
+    low_frequency_mode: bool,
 }
 
-#[derive(Debug)]
-pub struct ModeChangeFailedErrorInfo {
-    old_mode: u8,
-    new_mode: Mode,
-    set_mode: u8,
-}
-
-#[derive(Debug)]
-pub enum RFMError {
-    InvalidVersion,
-    ModeChangeFailed(ModeChangeFailedErrorInfo),
-    TransmissionTimedOut,
-}
-
-impl RFM95 {
-    pub fn new(
-        spi: Spi,
-        irq_bcm_pin: u8,
-        cs_bcm_pin: Option<u8>,
-        data_rate: DataRate,
+impl<SPI, IRQ, CS, RESET> RFM95<SPI, IRQ, CS, RESET>
+where
+    SPI: SpiDevice,
+    IRQ: InputPin,
+    CS: OutputPin,
+    RESET: OutputPin,
+{
+    /** Create a driver on top of an SPI device and the DIO0 interrupt pin. The `cs_pin` is driven in
+     * addition to whatever chip select the SPI device already manages; pass `None` (e.g. as
+     * `None::<NoPin>`) when the SPI device takes care of chip select on its own. Without a
+     * `reset_pin`, `reset` skips cycling the reset line. The modulation can be given either as a
+     * `DataRate` or as a `LoRaModulation`. */
+    pub fn new<M: Into<LoRaModulation>>(
+        spi: SPI,
+        irq_pin: IRQ,
+        cs_pin: Option<CS>,
+        reset_pin: Option<RESET>,
+        modulation: M,
         band: Band,
         channel: Channel,
-    ) -> Result<RFM95, Box<dyn Error>> {
-        let mut irq_pin = Gpio::new()?.get(irq_bcm_pin)?.into_input();
-        irq_pin.set_interrupt(Trigger::RisingEdge)?;
-        Ok(RFM95 {
-            spi: spi,
-            irq_pin: irq_pin,
-            cs_bcm_pin: cs_bcm_pin,
+    ) -> RFM95<SPI, IRQ, CS, RESET> {
+        RFM95 {
+            spi,
+            irq_pin,
+            cs_pin,
+            cs_timing: ChipSelectTiming::default(),
             tx_random_number: 0,
             tx_packets: 0,
-            data_rate: data_rate,
-            band: band,
-            channel: channel,
-            reset_pin: None,
-        })
+            modulation: modulation.into(),
+            band,
+            channel,
+            reset_pin,
+            keep_corrupted_packets: false,
+            implicit_payload_length: u8::MAX,
+            listen_before_talk: None,
+            duty_cycle: None,
+            channel_selector: Box::new(RandomChannel),
+            last_tx_channel: None,
+            next_channel: None,
+            rx_channel: channel,
+            low_frequency_mode: false,
+        }
+    }
+
+    /** Release the SPI device and pins owned by the driver */
+    pub fn release(self) -> (SPI, IRQ, Option<CS>, Option<RESET>) {
+        (self.spi, self.irq_pin, self.cs_pin, self.reset_pin)
+    }
+
+    /** Set mode of the RFM9x chip without verifying it, for modes the chip leaves by itself */
+    fn write_mode(&mut self, mode: Mode) -> Result<()> {
+        let mode = self.port_mode(mode);
+        self.write_register(Register::OpMode, mode.bits())
     }
 
     /** Set mode of the RFM9x chip and verify it was set correctly */
-    fn set_mode(&mut self, mode: Mode) -> Result<(), Box<dyn Error>> {
+    fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        let mode = self.port_mode(mode);
         let old_mode_raw = self.read_register(Register::OpMode)?;
         let old_mode = Mode::from_bits_truncate(old_mode_raw);
 
@@ -250,33 +179,28 @@ impl RFM95 {
         // Check the correct mode was set
         let set_mode_raw = self.read_register(Register::OpMode)?;
         if set_mode_raw != mode.bits() {
-            return Err(Box::new(RFMError::ModeChangeFailed(
-                ModeChangeFailedErrorInfo {
-                    old_mode: old_mode_raw,
-                    new_mode: mode,
-                    set_mode: set_mode_raw,
-                },
-            )));
+            return Err(Error::ModeChangeFailed(ModeChangeFailedErrorInfo {
+                old_mode: old_mode_raw,
+                new_mode: mode,
+                set_mode: set_mode_raw,
+            }));
         }
         Ok(())
     }
 
-    pub fn reset(&mut self, bcm_pin: u8) -> Result<(), Box<dyn Error>> {
-        /* Cycle reset pin. This is a bit weird: first, we set it to output low, then the pin is changed to be a pull-up input.
-        According to the Python version of the RFM95 driver, this is the only way it will actually work. */
-        self.reset_pin = None;
-        {
-            let mut pin = Gpio::new()?.get(bcm_pin)?.into_output();
-            pin.set_low();
+    pub fn reset(&mut self) -> Result<()> {
+        // Cycle the reset pin: pull it low briefly, then release it and give the chip time to start up.
+        if let Some(pin) = self.reset_pin.as_mut() {
+            pin.set_low().map_err(gpio_error)?;
             thread::sleep(Duration::from_millis(1));
+            pin.set_high().map_err(gpio_error)?;
+            thread::sleep(Duration::from_millis(500));
         }
-        let in_pin = Gpio::new()?.get(bcm_pin)?.into_input_pullup();
-        thread::sleep(Duration::from_millis(500));
-        self.reset_pin = Some(in_pin);
 
         // Check version
-        if self.get_version()? != RFM_VERSION {
-            return Err(Box::new(RFMError::InvalidVersion));
+        let version = self.get_version()?;
+        if version != RFM_VERSION {
+            return Err(Error::InvalidVersion(version));
         }
 
         // Set modes
@@ -286,22 +210,23 @@ impl RFM95 {
         self.set_mode(Mode::SLEEP | Mode::LORA)?;
 
         // PA pin (maximum power, 17 dBm)
-        self.write_register(Register::PAConfig, 0xFF)?;
+        self.set_tx_power(17, PaOutput::PaBoost)?;
 
         // Rx Timeout set to 37 symbols
         self.write_register(Register::SymbolTimeoutLSB, 0x25)?;
 
         // Preamble length set to 8 symbols
         // 0x0008 + 4 = 12
-        self.write_register(Register::PreambleLengthMSB, 0x00)?;
-        self.write_register(Register::PreambleLengthLSB, 0x08)?;
+        let [preamble_msb, preamble_lsb] = PREAMBLE_LENGTH.to_be_bytes();
+        self.write_register(Register::PreambleLengthMSB, preamble_msb)?;
+        self.write_register(Register::PreambleLengthLSB, preamble_lsb)?;
 
         // LoRA sync word
-        self.write_register(Register::Timer1Coefficient, 0x34)?;
+        self.write_register(Register::SyncWord, SYNC_WORD_LORAWAN)?;
 
-        // IQ
-        // self.write_register(Register::NodeAddress, 0x27)?;
-        // self.write_register(Register::ImageCalibration, 0x1D)?;
+        // IQ (not inverted)
+        self.write_register(Register::InvertIQ, Register::InvertIQ.reset_value())?;
+        self.write_register(Register::InvertIQ2, INVERT_IQ2_OFF)?;
 
         // FIFO pointers
         self.write_register(Register::FIFOTXBaseAddress, 0x80)?;
@@ -311,38 +236,80 @@ impl RFM95 {
         Ok(())
     }
 
-    fn read_register(&mut self, register: Register) -> Result<u8, Box<dyn Error>> {
-        let s = self.select();
-        let cmd = (register as u8) & 0x7F;
-        let mut buffer = [42u8; 1];
-        self.spi
-            .transfer_segments(&[Segment::with_write(&[cmd]), Segment::with_read(&mut buffer)])?;
-        let r = Ok(buffer[0]);
-        drop(s);
-        r
-    }
-
-    fn write_register(&mut self, register: Register, value: u8) -> Result<(), Box<dyn Error>> {
-        //println!("REG {:02x} = {:8b} ({:02x}) {:?}", register as u8, value, value, register);
-        let s = self.select();
-        let cmd = (register as u8) | 0x80;
-        self.spi
-            .transfer_segments(&[Segment::with_write(&[cmd, value])])?;
-        drop(s);
+    /** Set the transmit power (dBm) and the power amplifier output to use: -4 to +14 dBm on RFO, +2 to
+     * +17 dBm on PA_BOOST, or +18 to +20 dBm on PA_BOOST in high power mode. The over-current protection
+     * limit is raised to match. Note that high power mode is only allowed at a duty cycle of up to 1%. */
+    pub fn set_tx_power(&mut self, dbm: i8, output: PaOutput) -> Result<()> {
+        let settings = PowerSettings::new(dbm, output)?;
+        self.write_register(Register::PAConfig, settings.pa_config)?;
+        self.write_register(Register::PADAC, settings.pa_dac)?;
+        self.write_register(Register::OverCurrentProtection, settings.ocp)?;
         Ok(())
     }
 
-    fn wait_for_interrupt(&mut self, timeout: Duration) -> Result<bool, Box<dyn Error>> {
-        self.write_register(Register::IRQFlags, 0xFF)?; // Clear IRQ flags
-        assert_eq!(self.irq_pin.is_high(), false);
-        let result = match self.irq_pin.poll_interrupt(true, Some(timeout))? {
-            Some(Level::Low) => false, // Should not happen?
-            Some(Level::High) => true,
-            None => false,
+    /** Read a single register. Accepts both `Register` and `FskRegister`, allowing access to features the
+     * driver does not (yet) cover. */
+    pub fn read_register<R: Into<u8>>(&mut self, register: R) -> Result<u8> {
+        let cmd = register.into() & 0x7F;
+        let mut buffer = [42u8; 1];
+        self.transaction(&mut [Operation::Write(&[cmd]), Operation::Read(&mut buffer)])?;
+        Ok(buffer[0])
+    }
+
+    /** Write a single register. Note that the driver assumes it is in control of the registers it
+     * configures itself; changing those may result in unexpected behaviour. */
+    pub fn write_register<R: Into<u8>>(&mut self, register: R, value: u8) -> Result<()> {
+        let cmd = register.into() | 0x80;
+        self.transaction(&mut [Operation::Write(&[cmd, value])])
+    }
+
+    /** Read consecutive registers in a single SPI transaction, starting at `register`. The chip increments
+     * the address after every byte, except for the FIFO register, so reading `Register::FIFO` reads
+     * `buffer.len()` bytes from the FIFO. */
+    pub fn read_registers<R: Into<u8>>(&mut self, register: R, buffer: &mut [u8]) -> Result<()> {
+        let cmd = register.into() & 0x7F;
+        self.transaction(&mut [Operation::Write(&[cmd]), Operation::Read(buffer)])
+    }
+
+    /** Write consecutive registers in a single SPI transaction, starting at `register`. As with
+     * `read_registers`, writing `Register::FIFO` writes all of `data` to the FIFO. */
+    pub fn write_registers<R: Into<u8>>(&mut self, register: R, data: &[u8]) -> Result<()> {
+        let cmd = register.into() | 0x80;
+        self.transaction(&mut [Operation::Write(&[cmd]), Operation::Write(data)])
+    }
+
+    /** Run an SPI transaction with the chip selected. The chip is deselected again even when the
+     * transaction fails. */
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<()> {
+        self.select()?;
+        let result = self.spi.transaction(operations).map_err(spi_error);
+        self.deselect()?;
+        result
+    }
+
+    fn clear_interrupts(&mut self) -> Result<()> {
+        self.write_register(Register::IRQFlags, IRQFlags::all().bits())
+    }
+
+    /** Put the transceiver in standby, aborting any transmission or reception in progress */
+    pub(crate) fn standby(&mut self) -> Result<()> {
+        self.set_mode(Mode::LORA | Mode::STANDBY)
+    }
+
+    pub(crate) fn wait_for_interrupt(&mut self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            if self.irq_pin.is_high().map_err(gpio_error)? {
+                break true;
+            }
+            if Instant::now() >= deadline {
+                break false;
+            }
+            thread::sleep(IRQ_POLL_INTERVAL);
         };
 
         // let irq_flags = IRQFlags::from_bits_truncate(self.read_register(Register::IRQFlags)?);
-        // println!("IRQ status: fired {}, pin {}, flags={:?}", result, self.irq_pin.is_high(), irq_flags);
+        // println!("IRQ status: fired {}, flags={:?}", result, irq_flags);
         Ok(result)
     }
 
@@ -354,100 +321,523 @@ impl RFM95 {
      * The data rates used:
      * - RX1 uses the data rate of the uplink, unless an offset has been configured (see LoRAWAN regional spec. 2.2.7)
      * - RX2 again is fixed and configurable; the default is SF12, 125 kHz.
+     *
+     * Returns `Error::ReceiveTimedOut` when no packet arrived within `timeout` and `Error::CrcError` when
+     * the packet that did arrive was corrupted.
      */
-    pub fn receive_packet(
+    pub fn receive_packet<M: Into<LoRaModulation>>(
         &mut self,
         channel: Channel,
-        data_rate: DataRate,
+        modulation: M,
         with_crc: bool,
         timeout: Duration,
-    ) -> Result<([u8; 255], u8), Box<dyn Error>> {
-        //println!("debug 1");
+    ) -> Result<ReceivedPacket> {
+        let modulation = modulation.into();
+        self.start_receive(channel, modulation, with_crc)?;
 
-        let mut buffer = [0 as u8; 255];
+        // Wait for the interrupt pin to become high
+        let received = self.wait_for_interrupt(timeout)?;
+        self.finish_receive(received, modulation)
+    }
 
-        self.set_mode(Mode::LORA | Mode::STANDBY)?;
+    /** Configure the receiver and put it in continuous receive mode, with DIO0 signalling RxDone */
+    pub(crate) fn start_receive(
+        &mut self,
+        channel: Channel,
+        modulation: LoRaModulation,
+        with_crc: bool,
+    ) -> Result<()> {
+        //println!("debug 1");
+
+        self.standby()?;
 
         //println!("debug 2");
 
+        // Configure in standby, so that nothing is received with the previous settings
+        self.configure_receive(channel, modulation, with_crc)?;
+
         // Put receiver in receive mode
-        self.set_mode(Mode::LORA | Mode::RECEIVE_CONTINUOUS)?;
-        self.set_frequency(channel)?;
-        self.set_data_rate(data_rate, with_crc)?;
-        self.write_register(Register::PayloadLength, 0u8)?;
+        self.set_mode(Mode::LORA | Mode::RECEIVE_CONTINUOUS)
+
+        //println!("Before RX: {} bytes, {} pkts, {} headers, last RSSI={}", self.read_register(Register::ReceiveNumberOfBytes)?, self.read_register(Register::ReceiveValidPacketCountLSB)?, self.read_register(Register::ReceiveValidHeaderCountLSB)?, self.read_register(Register::LastRSSIValue)?);
+    }
+
+    /** Configure frequency, modulation and payload length for receiving, map DIO0 to RxDone and DIO1 to
+     * RxTimeout, and clear the IRQ flags */
+    fn configure_receive(
+        &mut self,
+        channel: Channel,
+        modulation: LoRaModulation,
+        with_crc: bool,
+    ) -> Result<()> {
+        self.rx_channel = self.set_frequency(channel)?;
+        self.configure_modulation(modulation, with_crc)?;
+        // In implicit header mode the payload length is not transmitted, so the receiver has to know it
+        let payload_length = if modulation.implicit_header() {
+            self.implicit_payload_length
+        } else {
+            0
+        };
+        self.write_register(Register::PayloadLength, payload_length)?;
 
         //println!("debug 3");
 
         // Set IRQ pin to become high when a message has been received (RxDone)
-        self.write_register(Register::DIOMapping1, 0x00)?;
+        self.write_register(
+            Register::DIOMapping1,
+            (DIOMapping1Flags::DIO0_RX_DONE | DIOMapping1Flags::DIO1_RX_TIMEOUT).bits(),
+        )?;
+        self.clear_interrupts()
+    }
 
-        //println!("Before RX: {} bytes, {} pkts, {} headers, last RSSI={}", self.read_register(Register::ReceiveNumberOfBytes)?, self.read_register(Register::ReceiveValidPacketCountLSB)?, self.read_register(Register::ReceiveValidHeaderCountLSB)?, self.read_register(Register::LastRSSIValue)?);
+    /** Receive a single packet, giving up when no preamble is detected within `symbol_timeout` symbols
+     * (at most 1023). This is timed by the chip itself, which makes it suitable for receive windows that
+     * have to be opened precisely, such as the LoRaWAN RX1 and RX2 windows. The RxTimeout interrupt is
+     * also mapped to DIO1.
+     *
+     * Returns `Error::SymbolTimeout` when no preamble was detected, and `Error::CrcError` when the packet
+     * that did arrive was corrupted. */
+    pub fn receive_single<M: Into<LoRaModulation>>(
+        &mut self,
+        channel: Channel,
+        modulation: M,
+        with_crc: bool,
+        symbol_timeout: u16,
+    ) -> Result<ReceivedPacket> {
+        if symbol_timeout > MAX_SYMBOL_TIMEOUT {
+            return Err(Error::InvalidSymbolTimeout(symbol_timeout));
+        }
+        let modulation = modulation.into();
+
+        self.standby()?;
+        self.configure_receive(channel, modulation, with_crc)?;
+
+        // The symbol timeout is split across bits 1-0 of RegModemConfig2 and RegSymbTimeoutLsb
+        let modem_config_2 = self.read_register(Register::ModemConfig2)?;
+        self.write_register(
+            Register::ModemConfig2,
+            (modem_config_2 & !SYMBOL_TIMEOUT_MSB_MASK) | (symbol_timeout >> 8) as u8,
+        )?;
+        self.write_register(Register::SymbolTimeoutLSB, symbol_timeout as u8)?;
+
+        // The chip returns to standby by itself, so the mode change cannot be verified
+        self.write_mode(Mode::LORA | Mode::RECEIVE_SINGLE)?;
+
+        // Once a preamble has been detected, the chip keeps receiving until the packet is complete
+        let timeout = modulation.symbol_time() * (symbol_timeout as u32 + MAX_PACKET_SYMBOLS);
+        let deadline = Instant::now() + timeout;
+        let received = loop {
+            if self.irq_pin.is_high().map_err(gpio_error)? {
+                break true;
+            }
+            let irq_flags = IRQFlags::from_bits_truncate(self.read_register(Register::IRQFlags)?);
+            if irq_flags.contains(IRQFlags::RECEIVE_TIMEOUT) {
+                self.standby()?;
+                return Err(Error::SymbolTimeout);
+            }
+            if Instant::now() >= deadline {
+                break false;
+            }
+            thread::sleep(IRQ_POLL_INTERVAL);
+        };
+        self.finish_receive(received, modulation)
+    }
+
+    /** Read the packet (if `received`) after `start_receive` and return to standby */
+    pub(crate) fn finish_receive(
+        &mut self,
+        received: bool,
+        modulation: LoRaModulation,
+    ) -> Result<ReceivedPacket> {
+        let received_at = Instant::now();
+        let irq_flags = IRQFlags::from_bits_truncate(self.read_register(Register::IRQFlags)?);
+        if !received || !irq_flags.contains(IRQFlags::RECEIVE_DONE) {
+            self.standby()?;
+            return Err(Error::ReceiveTimedOut);
+        }
 
-        // Wait for the interrupt pin to become high
-        self.wait_for_interrupt(timeout)?;
         //println!("RX: {} bytes, {} pkts, {} headers, last RSSI={}", self.read_register(Register::ReceiveNumberOfBytes)?, self.read_register(Register::ReceiveValidPacketCountLSB)?, self.read_register(Register::ReceiveValidHeaderCountLSB)?, self.read_register(Register::LastRSSIValue)?);
+        let packet = self.read_received_packet(received_at)?;
+
+        // Put transceiver to sleep again
+        self.standby()?;
+        self.check_received_packet(packet, irq_flags, modulation)
+    }
+
+    /** Read the packet signalled by RxDone while staying in continuous receive mode. Returns `None` when
+     * RxDone was not set. */
+    pub(crate) fn take_received_packet(
+        &mut self,
+        modulation: LoRaModulation,
+    ) -> Result<Option<ReceivedPacket>> {
+        let received_at = Instant::now();
+        let irq_flags = IRQFlags::from_bits_truncate(self.read_register(Register::IRQFlags)?);
+        if !irq_flags.contains(IRQFlags::RECEIVE_DONE) {
+            return Ok(None);
+        }
+        let packet = self.read_received_packet(received_at)?;
+        self.clear_interrupts()?;
+        self.check_received_packet(packet, irq_flags, modulation)
+            .map(Some)
+    }
+
+    /** Check the IRQ flags that came with a received packet for CRC and header errors */
+    fn check_received_packet(
+        &self,
+        packet: ReceivedPacket,
+        irq_flags: IRQFlags,
+        modulation: LoRaModulation,
+    ) -> Result<ReceivedPacket> {
+        // In explicit header mode, RxDone without a valid header means the header was corrupted
+        if irq_flags.contains(IRQFlags::PAYLOAD_CRC_ERROR)
+            || (!modulation.implicit_header()
+                && !irq_flags.contains(IRQFlags::VALID_HEADER_RECEIVED))
+        {
+            return Err(Error::CrcError(if self.keep_corrupted_packets {
+                Some(Box::new(packet))
+            } else {
+                None
+            }));
+        }
+        Ok(packet)
+    }
+
+    /** Read the last received packet from the FIFO, along with its signal quality */
+    fn read_received_packet(&mut self, received_at: Instant) -> Result<ReceivedPacket> {
         let size = self.read_register(Register::ReceiveNumberOfBytes)?;
         let fifo_addr = self.read_register(Register::FIFORXCurrent)?;
         self.write_register(Register::FIFOAddressPointer, fifo_addr)?;
-        for i in 0..size {
-            let byte = self.read_register(Register::FIFO)?;
-            buffer[i as usize] = byte;
-        }
+        let mut payload = vec![0u8; size as usize];
+        self.read_registers(Register::FIFO, &mut payload)?;
         self.write_register(Register::FIFOAddressPointer, 0)?;
 
-        // Put transceiver to sleep again
-        self.set_mode(Mode::LORA | Mode::STANDBY)?;
-        Ok((buffer, size))
+        let low_frequency_mode = self.is_low_frequency_mode()?;
+        let snr = snr_db(self.read_register(Register::LastSNRValue)?);
+        let rssi = packet_rssi_dbm(
+            self.read_register(Register::LastRSSIValue)?,
+            snr,
+            low_frequency_mode,
+        );
+        let fei = [
+            self.read_register(Register::FrequencyErrorMSB)?,
+            self.read_register(Register::FrequencyErrorMID)?,
+            self.read_register(Register::FrequencyErrorLSB)?,
+        ];
+        let bandwidth = Bandwidth::from_modem_config_1(self.read_register(Register::ModemConfig1)?);
+        let modem_status = self.read_register(Register::ModemStatus)?;
+        let hop_channel =
+            HopChannelFlags::from_bits_truncate(self.read_register(Register::HopChannel)?);
+
+        Ok(ReceivedPacket {
+            payload,
+            rssi,
+            snr,
+            frequency_error: frequency_error_hz(fei, bandwidth.hz()),
+            coding_rate: CodingRate::from_modem_status(modem_status),
+            crc_on: hop_channel.contains(HopChannelFlags::CRC_ON_PAYLOAD),
+            channel: self.rx_channel,
+            received_at,
+        })
+    }
+
+    fn is_low_frequency_mode(&mut self) -> Result<bool> {
+        let mode = Mode::from_bits_truncate(self.read_register(Register::OpMode)?);
+        Ok(mode.contains(Mode::LOW_FREQUENCY_MODE))
+    }
+
+    /** When enabled, packets that fail the CRC check are not discarded but returned inside
+     * `Error::CrcError`, which can be useful to diagnose a link. Disabled by default. */
+    pub fn set_keep_corrupted_packets(&mut self, keep: bool) {
+        self.keep_corrupted_packets = keep;
+    }
+
+    /** Set the modulation used for transmissions and `receive_packet_on_tx` */
+    pub fn set_modulation<M: Into<LoRaModulation>>(&mut self, modulation: M) {
+        self.modulation = modulation.into();
     }
 
+    pub fn modulation(&self) -> LoRaModulation {
+        self.modulation
+    }
+
+    /** Length of the packets to expect when receiving in implicit header mode (SF6), in which the length is
+     * not transmitted. Defaults to 255. */
+    pub fn set_implicit_payload_length(&mut self, length: u8) {
+        self.implicit_payload_length = length;
+    }
+
+    /** Invert the I and Q signals of the receiver, which is needed to hear LoRaWAN downlinks: gateways
+     * transmit them inverted so that end devices do not hear each other's uplinks. Transmissions are not
+     * affected. `reset` turns inversion off. */
+    pub fn set_invert_iq_rx(&mut self, invert: bool) -> Result<()> {
+        let (invert_iq, invert_iq2) = if invert {
+            (
+                Register::InvertIQ.reset_value() | InvertIQFlags::INVERT_IQ_RX.bits(),
+                INVERT_IQ2_ON,
+            )
+        } else {
+            (Register::InvertIQ.reset_value(), INVERT_IQ2_OFF)
+        };
+        self.write_register(Register::InvertIQ, invert_iq)?;
+        self.write_register(Register::InvertIQ2, invert_iq2)
+    }
+
+    /** Receive on the channel of the last transmission (see `last_tx_channel`) with the configured
+     * modulation */
     pub fn receive_packet_on_tx(
         &mut self,
         with_crc: bool,
         timeout: Duration,
-    ) -> Result<([u8; 255], u8), Box<dyn Error>> {
-        self.receive_packet(self.channel, self.data_rate, with_crc, timeout)
+    ) -> Result<ReceivedPacket> {
+        let channel = self.last_tx_channel.unwrap_or(self.channel);
+        self.receive_packet(channel, self.modulation, with_crc, timeout)
     }
 
-    fn set_frequency(&mut self, channel: Channel) -> Result<(), Box<dyn Error>> {
-        let frequency = channel.frequency(&self.band);
-        self.write_register(Register::FRFMSB, frequency[0])?;
-        self.write_register(Register::FRFMID, frequency[1])?;
-        self.write_register(Register::FRFLSB, frequency[2])?;
-        //println!("Frequency set to {:?} {:02x?}", channel, frequency);
+    /** Strategy for picking the channel whenever `Channel::Multi` is used, among the default channels of
+     * the band. Picks at random by default. */
+    pub fn set_channel_selector<S: ChannelSelector + Send + 'static>(&mut self, selector: S) {
+        self.channel_selector = Box::new(selector);
+        self.next_channel = None;
+    }
+
+    /** Concrete channel used by the last transmission; never `Channel::Multi` */
+    pub fn last_tx_channel(&self) -> Option<Channel> {
+        self.last_tx_channel
+    }
+
+    /** The channel itself, or the default channel the channel selector picks for `Channel::Multi`. A pick
+     * made ahead by `peek_channel` is used first. */
+    pub(crate) fn resolve_channel(&mut self, channel: Channel) -> Result<Channel> {
+        match channel {
+            Channel::Multi => match self.next_channel.take() {
+                Some(next) => Ok(next),
+                None => {
+                    let count = self.band.parameters().default_channels.len();
+                    let index = self.select_channel(count)?;
+                    Channel::default_channel(index).ok_or(Error::InvalidChannel(channel, self.band))
+                }
+            },
+            channel => Ok(channel),
+        }
+    }
+
+    /** Resolve the channel like `resolve_channel`, but keep the pick for `Channel::Multi` for the next
+     * operation, so that it ends up on the same channel and the selector does not move on */
+    fn peek_channel(&mut self, channel: Channel) -> Result<Channel> {
+        let resolved = self.resolve_channel(channel)?;
+        if channel == Channel::Multi {
+            self.next_channel = Some(resolved);
+        }
+        Ok(resolved)
+    }
+
+    /** Let the channel selector pick one of `count` channels. Fails with `Error::InvalidChannelSelection`
+     * when the selector returns an index out of range. */
+    pub(crate) fn select_channel(&mut self, count: usize) -> Result<usize> {
+        let index = self.channel_selector.select(count);
+        if index >= count {
+            return Err(Error::InvalidChannelSelection(index, count));
+        }
+        Ok(index)
+    }
+
+    /** Tune to the channel, returning the concrete channel in case of `Channel::Multi` */
+    fn set_frequency(&mut self, channel: Channel) -> Result<Channel> {
+        let channel = self.resolve_channel(channel)?;
+        let frequency = channel.frequency_hz(&self.band)?;
+        self.write_frequency(frequency)?;
+        Ok(channel)
+    }
+
+    /** Tune to an arbitrary frequency (Hz), which is also used for subsequent transmissions. The RFM95/96/97
+     * HF port covers 779 - 1020 MHz; the LF port of the RFM96/98 covers 137 - 525 MHz. Low frequency mode
+     * is enabled automatically for frequencies on the LF port. */
+    pub fn set_frequency_hz(&mut self, frequency: u32) -> Result<()> {
+        self.write_frequency(frequency)?;
+        self.channel = Channel::Frequency(frequency);
         Ok(())
     }
 
-    fn set_data_rate(
-        &mut self,
-        data_rate: DataRate,
-        enable_crc: bool,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut modem_config_2 = data_rate.modem_config_2();
+    /** Frequency the chip is currently tuned to (Hz) */
+    pub fn get_frequency_hz(&mut self) -> Result<u32> {
+        let frf = ((self.read_register(Register::FRFMSB)? as u64) << 16)
+            | ((self.read_register(Register::FRFMID)? as u64) << 8)
+            | self.read_register(Register::FRFLSB)? as u64;
+        Ok(((frf * FXOSC as u64 + (1 << 18)) >> 19) as u32)
+    }
+
+    fn write_frequency(&mut self, frequency: u32) -> Result<()> {
+        let low_frequency_mode = if HF_FREQUENCY_RANGE.contains(&frequency) {
+            false
+        } else if LF_FREQUENCY_RANGE.contains(&frequency) {
+            true
+        } else {
+            return Err(Error::InvalidFrequency(frequency));
+        };
+
+        if low_frequency_mode != self.low_frequency_mode {
+            self.low_frequency_mode = low_frequency_mode;
+            let mode = Mode::from_bits_truncate(self.read_register(Register::OpMode)?);
+            self.write_register(Register::OpMode, self.port_mode(mode).bits())?;
+        }
+
+        // FRF = frequency / FSTEP, with FSTEP = FXOSC / 2^19
+        let frf = (((frequency as u64) << 19) + FXOSC as u64 / 2) / FXOSC as u64;
+        self.write_register(Register::FRFMSB, (frf >> 16) as u8)?;
+        self.write_register(Register::FRFMID, (frf >> 8) as u8)?;
+        self.write_register(Register::FRFLSB, frf as u8)?;
+        //println!("Frequency set to {} Hz {:06x}", frequency, frf);
+        Ok(())
+    }
+
+    /** Set or clear the LowFrequencyModeOn bit in a mode, depending on the port in use */
+    fn port_mode(&self, mode: Mode) -> Mode {
+        if self.low_frequency_mode {
+            mode | Mode::LOW_FREQUENCY_MODE
+        } else {
+            mode - Mode::LOW_FREQUENCY_MODE
+        }
+    }
+
+    fn configure_modulation(&mut self, modulation: LoRaModulation, enable_crc: bool) -> Result<()> {
+        let mut modem_config_2 = modulation.modem_config_2();
 
         if enable_crc {
             modem_config_2 |= ModemConfig2Flags::RX_PAYLOAD_CRC_FOUND;
         }
 
         self.write_register(Register::ModemConfig2, modem_config_2.bits())?;
-        self.write_register(Register::ModemConfig1, data_rate.modem_config_1().bits())?;
-        self.write_register(Register::ModemConfig3, data_rate.modem_config_3().bits())?;
+        self.write_register(Register::ModemConfig1, modulation.modem_config_1().bits())?;
+        self.write_register(Register::ModemConfig3, modulation.modem_config_3().bits())?;
+
+        // SF6 needs its own detection settings (see SX1276 datasheet, 4.1.1.2)
+        let (detect_optimize, detection_threshold) = if modulation.sf == SpreadingFactor::SF6 {
+            (DETECTION_OPTIMIZE_SF6, DETECTION_THRESHOLD_SF6)
+        } else {
+            (
+                DETECTION_OPTIMIZE_SF7_TO_SF12,
+                DETECTION_THRESHOLD_SF7_TO_SF12,
+            )
+        };
+        let detect_optimize_reg = self.read_register(Register::DetectOptimize)?;
+        self.write_register(
+            Register::DetectOptimize,
+            (detect_optimize_reg & !0x07) | detect_optimize,
+        )?;
+        self.write_register(Register::DetectionThreshold, detection_threshold)?;
+        Ok(())
+    }
+
+    /** Sense the channel before every transmission, backing off while it is busy. `send_packet` returns
+     * `Error::ChannelBusy` when the channel is still busy after the last retry. Disabled by default. */
+    pub fn set_listen_before_talk(&mut self, policy: Option<ListenBeforeTalk>) {
+        self.listen_before_talk = policy;
+    }
+
+    pub fn listen_before_talk(&self) -> Option<ListenBeforeTalk> {
+        self.listen_before_talk
+    }
+
+    /** Track the time on air of every transmission and keep it within the duty-cycle limits of the
+     * sub-bands. Disabled by default. Fails with `Error::BandMismatch` when the tracker was created for
+     * another band than the radio's. */
+    pub fn set_duty_cycle_tracker(&mut self, tracker: Option<DutyCycleTracker>) -> Result<()> {
+        if let Some(tracker) = &tracker {
+            if tracker.band() != self.band {
+                return Err(Error::BandMismatch(tracker.band(), self.band));
+            }
+        }
+        self.duty_cycle = tracker;
         Ok(())
     }
 
-    pub fn send_packet(&mut self, packet: &[u8]) -> Result<(), Box<dyn Error>> {
-        assert!(packet.len() > 0);
-        assert!(packet.len() < 255);
+    pub fn duty_cycle_tracker(&self) -> Option<&DutyCycleTracker> {
+        self.duty_cycle.as_ref()
+    }
+
+    pub(crate) fn band(&self) -> Band {
+        self.band
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn channel(&self) -> Channel {
+        self.channel
+    }
 
-        self.set_mode(Mode::LORA | Mode::STANDBY)?;
+    /** How long a transmission of `airtime` on the frequency (Hz) has to wait for the duty cycle */
+    pub(crate) fn duty_cycle_wait_time(
+        &self,
+        frequency: u32,
+        airtime: Duration,
+    ) -> Result<Duration> {
+        match &self.duty_cycle {
+            Some(tracker) => tracker.wait_time(frequency, airtime),
+            None => Ok(Duration::ZERO),
+        }
+    }
+
+    pub(crate) fn record_transmission(&mut self, frequency: u32, airtime: Duration) {
+        if let Some(tracker) = &mut self.duty_cycle {
+            tracker.record(frequency, airtime);
+        }
+    }
+
+    /** Transmit a packet using the configured channel and modulation. With `Channel::Multi`, the channel
+     * selector picks the channel, which `last_tx_channel` reports afterwards. Fails with
+     * `Error::InvalidPayloadLength` unless the packet has 1 - 255 bytes. */
+    pub fn send_packet(&mut self, packet: &[u8]) -> Result<()> {
+        check_payload_length(packet)?;
+        let channel = self.resolve_channel(self.channel)?;
+        let frequency = channel.frequency_hz(&self.band)?;
+        let airtime = self.time_on_air(packet.len());
+        let wait = self.duty_cycle_wait_time(frequency, airtime)?;
+        if let Some(tracker) = self.duty_cycle.as_ref().filter(|_| !wait.is_zero()) {
+            tracker.sleep(wait);
+        }
+
+        if let Some(policy) = self.listen_before_talk {
+            let mut retries = 0;
+            while !self.channel_clear(&policy, channel)? {
+                if retries == policy.max_retries {
+                    return Err(Error::ChannelBusy);
+                }
+                retries += 1;
+                thread::sleep(policy.backoff());
+            }
+        }
+
+        self.start_transmit(packet, channel)?;
+        self.record_transmission(frequency, airtime);
+
+        // Wait for the interrupt pin to become high
+        let done = self.wait_for_interrupt(self.transmit_timeout(packet.len()))?;
+        self.finish_transmit(done)
+    }
+
+    /** Time on air of a packet of the given length with the current modulation */
+    pub fn time_on_air(&self, payload_length: usize) -> Duration {
+        time_on_air(&PacketParameters::new(self.modulation), payload_length).total
+    }
+
+    /** Time to wait for TxDone after starting a transmission */
+    pub(crate) fn transmit_timeout(&self, payload_length: usize) -> Duration {
+        self.time_on_air(payload_length) + TRANSMIT_TIMEOUT_MARGIN
+    }
+
+    /** Load the packet into the FIFO and start transmitting it on the channel, with DIO0 signalling
+     * TxDone */
+    pub(crate) fn start_transmit(&mut self, packet: &[u8], channel: Channel) -> Result<()> {
+        check_payload_length(packet)?;
+        self.standby()?;
 
         // Configure DIO0 as the IRQ pin to become low when TxDone
-        self.write_register(Register::DIOMapping1, 0x40)?;
+        self.write_register(Register::DIOMapping1, DIOMapping1Flags::DIO0_TX_DONE.bits())?;
 
         // Set channel
-        self.set_frequency(self.channel)?;
+        self.last_tx_channel = Some(self.set_frequency(channel)?);
 
-        // Set data rate
-        self.set_data_rate(self.data_rate, true)?;
+        // Set modulation
+        self.configure_modulation(self.modulation, true)?;
 
         // Set payload length
         self.write_register(Register::PayloadLength, packet.len() as u8)?;
@@ -456,180 +846,161 @@ impl RFM95 {
         self.write_register(Register::FIFOAddressPointer, 0x80)?;
 
         // Write payload to FIFO
-        for i in 0..packet.len() {
-            self.write_register(Register::FIFO, packet[i])?;
-            self.tx_packets += 1;
-        }
+        self.write_registers(Register::FIFO, packet)?;
+        self.tx_packets += packet.len() as u32;
 
         // Switch to transmit mode
-        self.set_mode(Mode::LORA | Mode::TRANSMIT)?;
+        self.clear_interrupts()?;
+        self.set_mode(Mode::LORA | Mode::TRANSMIT)
+    }
 
-        // Wait for the interrupt pin to become high
-        if !self.wait_for_interrupt(Duration::from_millis(1000))? {
-            return Err(Box::new(RFMError::TransmissionTimedOut));
+    /** Return to standby after `start_transmit`, once TxDone was signalled (`done`) */
+    pub(crate) fn finish_transmit(&mut self, done: bool) -> Result<()> {
+        if !done {
+            return Err(Error::TransmissionTimedOut);
         }
 
         // Put transceiver to standby again
-        self.set_mode(Mode::LORA | Mode::STANDBY)?;
+        self.standby()?;
         Ok(())
     }
 
-    fn select(&mut self) -> Result<ChipSelected, Box<dyn Error>> {
-        ChipSelected::new(self.cs_bcm_pin)
+    /** Run a single channel activity detection on the current channel, looking for a LoRa preamble with
+     * the given modulation. Takes about `LoRaModulation::cad_duration`. DIO0 is mapped to CadDone and
+     * DIO1 to CadDetected. With `Channel::Multi`, the channel selector picks the channel, which the next
+     * transmission or reception then uses as well. */
+    pub fn detect_channel_activity<M: Into<LoRaModulation>>(
+        &mut self,
+        modulation: M,
+    ) -> Result<bool> {
+        let channel = self.peek_channel(self.channel)?;
+        self.detect_channel_activity_on(channel, modulation.into())
     }
 
-    pub fn get_version(&mut self) -> Result<u8, Box<dyn Error>> {
-        self.read_register(Register::Version)
+    pub(crate) fn detect_channel_activity_on(
+        &mut self,
+        channel: Channel,
+        modulation: LoRaModulation,
+    ) -> Result<bool> {
+        let timeout = self.start_channel_activity_detection(channel, modulation)?;
+        let done = self.wait_for_interrupt(timeout)?;
+        self.finish_channel_activity_detection(done)
     }
 
-    pub fn get_rssi(&mut self) -> Result<u8, Box<dyn Error>> {
-        self.read_register(Register::RSSIValue)
+    /** Start a channel activity detection, with DIO0 signalling CadDone. Returns how long to wait for it. */
+    pub(crate) fn start_channel_activity_detection(
+        &mut self,
+        channel: Channel,
+        modulation: LoRaModulation,
+    ) -> Result<Duration> {
+        self.standby()?;
+        self.set_frequency(channel)?;
+        self.configure_modulation(modulation, true)?;
+        self.write_register(
+            Register::DIOMapping1,
+            (DIOMapping1Flags::DIO0_CAD_DONE | DIOMapping1Flags::DIO1_CAD_DETECTED).bits(),
+        )?;
+        self.clear_interrupts()?;
+
+        // The chip returns to standby when done, so the mode change cannot be verified
+        self.write_mode(Mode::LORA | Mode::CHANNEL_ACTIVITY_DETECTION)?;
+        Ok(modulation.cad_duration() + CAD_TIMEOUT_MARGIN)
     }
 
-    pub fn get_snr(&mut self) -> Result<u8, Box<dyn Error>> {
-        self.read_register(Register::LastSNRValue)
-    }
-}
+    /** Read the result after `start_channel_activity_detection`, once CadDone was signalled (`done`), and
+     * return to standby. Returns true when activity was detected. */
+    pub(crate) fn finish_channel_activity_detection(&mut self, done: bool) -> Result<bool> {
+        let irq_flags = IRQFlags::from_bits_truncate(self.read_register(Register::IRQFlags)?);
+        self.standby()?;
 
-impl Display for RFMError {
-    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        write!(
-            out,
-            "{}",
-            match self {
-                RFMError::InvalidVersion => String::from("invalid version"),
-                RFMError::ModeChangeFailed(info) => format!("mode change failed: {:?}", info),
-                RFMError::TransmissionTimedOut => String::from("transmission timed out"),
-            }
-        )
+        if !done || !irq_flags.contains(IRQFlags::CHANNEL_ACTIVITY_DETECTION_DONE) {
+            return Err(Error::ChannelActivityDetectionTimedOut);
+        }
+        Ok(irq_flags.contains(IRQFlags::CHANNEL_ACTIVITY_DETECTED))
     }
-}
 
-impl Error for RFMError {}
-
-impl ChipSelected {
-    pub fn new(cs_bcm_pin: Option<u8>) -> Result<ChipSelected, Box<dyn Error>> {
-        Ok(ChipSelected {
-            cs_pin: match cs_bcm_pin {
-                Some(cs_pin) => {
-                    let mut p = Gpio::new()?.get(cs_pin)?.into_output();
-                    p.set_low();
-                    Some(p)
-                }
-                None => None,
-            },
-        })
+    /** Configure the setup and hold delays of the software chip select line. Has no effect without a
+     * `cs_pin`. */
+    pub fn set_chip_select_timing(&mut self, timing: ChipSelectTiming) {
+        self.cs_timing = timing;
     }
-}
 
-impl Drop for ChipSelected {
-    fn drop(&mut self) {
-        match self.cs_pin {
-            Some(ref mut pin) => pin.set_high(),
-            None => {}
+    fn select(&mut self) -> Result<()> {
+        if let Some(pin) = self.cs_pin.as_mut() {
+            pin.set_low().map_err(gpio_error)?;
+            delay(self.cs_timing.setup);
         }
+        Ok(())
     }
-}
 
-impl DataRate {
-    fn modem_config_1(&self) -> ModemConfig1Flags {
-        match self {
-            DataRate::SF7_BW125 => ModemConfig1Flags::BW125 | ModemConfig1Flags::CODING_RATE_4_5,
-            DataRate::SF7_BW250 => ModemConfig1Flags::BW250 | ModemConfig1Flags::CODING_RATE_4_5,
-            DataRate::SF8_BW125 => ModemConfig1Flags::BW125 | ModemConfig1Flags::CODING_RATE_4_5,
-            DataRate::SF9_BW125 => ModemConfig1Flags::BW125 | ModemConfig1Flags::CODING_RATE_4_5,
-            DataRate::SF10_BW125 => ModemConfig1Flags::BW125 | ModemConfig1Flags::CODING_RATE_4_5,
-            DataRate::SF11_BW125 => ModemConfig1Flags::BW125 | ModemConfig1Flags::CODING_RATE_4_8,
-            DataRate::SF12_BW125 => ModemConfig1Flags::BW125 | ModemConfig1Flags::CODING_RATE_4_8,
+    fn deselect(&mut self) -> Result<()> {
+        if let Some(pin) = self.cs_pin.as_mut() {
+            delay(self.cs_timing.hold);
+            pin.set_high().map_err(gpio_error)?;
         }
+        Ok(())
     }
 
-    fn modem_config_2(&self) -> ModemConfig2Flags {
-        match self {
-            DataRate::SF7_BW125 => ModemConfig2Flags::SF7,
-            DataRate::SF7_BW250 => ModemConfig2Flags::SF7,
-            DataRate::SF8_BW125 => ModemConfig2Flags::SF8,
-            DataRate::SF9_BW125 => ModemConfig2Flags::SF9,
-            DataRate::SF10_BW125 => ModemConfig2Flags::SF10,
-            DataRate::SF11_BW125 => ModemConfig2Flags::SF11,
-            DataRate::SF12_BW125 => ModemConfig2Flags::SF12,
-        }
+    pub fn get_version(&mut self) -> Result<u8> {
+        self.read_register(Register::Version)
     }
 
-    fn modem_config_3(&self) -> ModemConfig3Flags {
-        match self {
-            DataRate::SF7_BW125 => ModemConfig3Flags::AUTO_AGC_ON,
-            DataRate::SF7_BW250 => ModemConfig3Flags::AUTO_AGC_ON,
-            DataRate::SF8_BW125 => ModemConfig3Flags::AUTO_AGC_ON,
-            DataRate::SF9_BW125 => ModemConfig3Flags::AUTO_AGC_ON,
-            DataRate::SF10_BW125 => ModemConfig3Flags::AUTO_AGC_ON,
-            DataRate::SF11_BW125 => {
-                ModemConfig3Flags::AUTO_AGC_ON | ModemConfig3Flags::IS_MOBILE_NODE
-            }
-            DataRate::SF12_BW125 => {
-                ModemConfig3Flags::AUTO_AGC_ON | ModemConfig3Flags::IS_MOBILE_NODE
-            }
-        }
+    /** Current RSSI (dBm) */
+    pub fn get_rssi(&mut self) -> Result<i16> {
+        let raw = self.read_register(Register::RSSIValue)?;
+        Ok(rssi_offset(self.is_low_frequency_mode()?) + raw as i16)
+    }
+
+    /** SNR of the last packet received (dB) */
+    pub fn get_snr(&mut self) -> Result<f32> {
+        Ok(snr_db(self.read_register(Register::LastSNRValue)?))
     }
 }
 
-impl Channel {
-    fn frequency(&self, band: &Band) -> [u8; 3] {
-        match band {
-            Band::EU863 => match self {
-                Channel::Ch0 => [0xD9, 0x06, 0x8B], //Channel 0 868.100 MHz / 61.035 Hz = 14222987 = 0xD9068B
-                Channel::Ch1 => [0xD9, 0x13, 0x58], //Channel 1 868.300 MHz / 61.035 Hz = 14226264 = 0xD91358
-                Channel::Ch2 => [0xD9, 0x20, 0x24], //Channel 2 868.500 MHz / 61.035 Hz = 14229540 = 0xD92024
-                Channel::Ch3 => [0xD8, 0xC6, 0x8B], //Channel 3 867.100 MHz / 61.035 Hz = 14206603 = 0xD8C68B
-                Channel::Ch4 => [0xD8, 0xD3, 0x58], //Channel 4 867.300 MHz / 61.035 Hz = 14209880 = 0xD8D358
-                Channel::Ch5 => [0xD8, 0xE0, 0x24], //Channel 5 867.500 MHz / 61.035 Hz = 14213156 = 0xD8E024
-                Channel::Ch6 => [0xD8, 0xEC, 0xF1], //Channel 6 867.700 MHz / 61.035 Hz = 14216433 = 0xD8ECF1
-                Channel::Ch7 => [0xD8, 0xF9, 0xBE], //Channel 7 867.900 MHz / 61.035 Hz = 14219710 = 0xD8F9BE
-                Channel::Multi => Channel::random().frequency(band),
-                Channel::Ch9 => [0xD9, 0x61, 0xBE], // Channel 9 869.525 MHz / 61.035 Hz = 14246334 = 0xD961BE
-            },
+/** Fail with `Error::InvalidPayloadLength` unless the modem can transmit the packet */
+pub(crate) fn check_payload_length(packet: &[u8]) -> Result<()> {
+    if packet.is_empty() || packet.len() > MAX_PAYLOAD_LENGTH {
+        return Err(Error::InvalidPayloadLength(packet.len()));
+    }
+    Ok(())
+}
 
-            Band::US901 => match self {
-                Channel::Ch0 => [0xE1, 0xF9, 0xC0], //Channel 0 903.900 MHz / 61.035 Hz = 14809536 = 0xE1F9C0
-                Channel::Ch1 => [0xE2, 0x06, 0x8C], //Channel 1 904.100 MHz / 61.035 Hz = 14812812 = 0xE2068C
-                Channel::Ch2 => [0xE2, 0x13, 0x59], //Channel 2 904.300 MHz / 61.035 Hz = 14816089 = 0xE21359
-                Channel::Ch3 => [0xE2, 0x20, 0x26], //Channel 3 904.500 MHz / 61.035 Hz = 14819366 = 0xE22026
-                Channel::Ch4 => [0xE2, 0x2C, 0xF3], //Channel 4 904.700 MHz / 61.035 Hz = 14822643 = 0xE22CF3
-                Channel::Ch5 => [0xE2, 0x39, 0xC0], //Channel 5 904.900 MHz / 61.035 Hz = 14825920 = 0xE239C0
-                Channel::Ch6 => [0xE2, 0x46, 0x8C], //Channel 6 905.100 MHz / 61.035 Hz = 14829196 = 0xE2468C
-                Channel::Ch7 => [0xE2, 0x53, 0x59], //Channel 7 905.300 MHz / 61.035 Hz = 14832473 = 0xE25359
-                Channel::Multi => Channel::random().frequency(band),
-                Channel::Ch9 => unreachable!(),
-            },
+impl From<DataRate> for LoRaModulation {
+    fn from(data_rate: DataRate) -> LoRaModulation {
+        let (sf, bw, cr) = match data_rate {
+            DataRate::SF7_BW125 => (SpreadingFactor::SF7, Bandwidth::BW125, CodingRate::CR4_5),
+            DataRate::SF7_BW250 => (SpreadingFactor::SF7, Bandwidth::BW250, CodingRate::CR4_5),
+            DataRate::SF8_BW125 => (SpreadingFactor::SF8, Bandwidth::BW125, CodingRate::CR4_5),
+            DataRate::SF9_BW125 => (SpreadingFactor::SF9, Bandwidth::BW125, CodingRate::CR4_5),
+            DataRate::SF10_BW125 => (SpreadingFactor::SF10, Bandwidth::BW125, CodingRate::CR4_5),
+            DataRate::SF11_BW125 => (SpreadingFactor::SF11, Bandwidth::BW125, CodingRate::CR4_8),
+            DataRate::SF12_BW125 => (SpreadingFactor::SF12, Bandwidth::BW125, CodingRate::CR4_8),
+        };
+        LoRaModulation::new(sf, bw, cr)
+    }
+}
 
-            Band::AS920 => match self {
-                Channel::Ch0 => [0xE6, 0xCC, 0xF4], //Channel 0 868.100 MHz / 61.035 Hz = 15125748 = 0xE6CCF4
-                Channel::Ch1 => [0xE6, 0xD9, 0xC0], //Channel 1 868.300 MHz / 61.035 Hz = 15129024 = 0xE6D9C0
-                Channel::Ch2 => [0xE6, 0x8C, 0xF3], //Channel 2 868.500 MHz / 61.035 Hz = 15109363 = 0xE68CF3
-                Channel::Ch3 => [0xE6, 0x99, 0xC0], //Channel 3 867.100 MHz / 61.035 Hz = 15112640 = 0xE699C0
-                Channel::Ch4 => [0xE6, 0xA6, 0x8D], //Channel 4 867.300 MHz / 61.035 Hz = 15115917 = 0xE6A68D
-                Channel::Ch5 => [0xE6, 0xB3, 0x5A], //Channel 5 867.500 MHz / 61.035 Hz = 15119194 = 0xE6B35A
-                Channel::Ch6 => [0xE6, 0xC0, 0x27], //Channel 6 867.700 MHz / 61.035 Hz = 15122471 = 0xE6C027
-                Channel::Ch7 => [0xE6, 0x80, 0x27], //Channel 7 867.900 MHz / 61.035 Hz = 15106087 = 0xE68027
-                Channel::Multi => Channel::random().frequency(band),
-                Channel::Ch9 => unreachable!(),
-            },
+impl Channel {
+    /** Channel for an index into the default channels of a band, if there is one */
+    fn default_channel(index: usize) -> Option<Channel> {
+        match index {
+            0 => Some(Channel::Ch0),
+            1 => Some(Channel::Ch1),
+            2 => Some(Channel::Ch2),
+            3 => Some(Channel::Ch3),
+            4 => Some(Channel::Ch4),
+            5 => Some(Channel::Ch5),
+            6 => Some(Channel::Ch6),
+            7 => Some(Channel::Ch7),
+            _ => None,
         }
     }
 
-    fn random() -> Channel {
-        let mut rng = rand::thread_rng();
-        // Only use channels 0..=7
-        match rng.gen_range(0..=7) {
-            0 => Channel::Ch0,
-            1 => Channel::Ch1,
-            2 => Channel::Ch2,
-            3 => Channel::Ch3,
-            4 => Channel::Ch4,
-            5 => Channel::Ch5,
-            6 => Channel::Ch6,
-            7 => Channel::Ch7,
-            _ => unreachable!(),
-        }
+    /** Center frequency of the channel in the given band (Hz). `Ch0` to `Ch7` select the default channels
+     * of the band, see `RegionalParameters::default_channels`. `Ch9` (869.525 MHz) only exists in EU863.
+     * `Multi` has no frequency of its own: the radio's channel selector resolves it to a default channel
+     * (see `RFM95::set_channel_selector`), so it fails with `Error::InvalidChannel`. */
+    pub fn frequency_hz(&self, band: &Band) -> Result<u32> {
+        band.parameters().channel_frequency(*self)
     }
 }
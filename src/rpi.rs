@@ -0,0 +1,213 @@
+#[cfg(feature = "async")]
+use crate::AsyncRfm95;
+use crate::{Band, Channel, ChipSelectTiming, LoRaModulation, RFM95};
+use core::convert::Infallible;
+#[cfg(feature = "async")]
+use embedded_hal_async::digital::Wait;
+#[cfg(feature = "async")]
+use rppal::gpio::Trigger;
+use rppal::gpio::{Bias, Error, Gpio, InputPin, IoPin, Mode, OutputPin, Pin};
+use rppal::spi::{SimpleHalSpiDevice, Spi};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use tokio::sync::Notify;
+
+/** RFM9x driver using the Raspberry Pi SPI and GPIO peripherals through rppal */
+pub type RpiRFM95 = RFM95<SimpleHalSpiDevice, InputPin, OutputPin, RpiResetPin>;
+
+/** Reset (NRESET) line of the module. `set_low` drives the line low, but `set_high` does not drive it
+ * high: it turns the pin into an input with pull-up and lets the module release the line itself. This is
+ * a bit weird, but according to the Python version of the RFM95 driver, it is the only way the reset
+ * actually works; the SX1276 datasheet also describes a manual reset as pulling NRESET low and then
+ * releasing it. */
+pub struct RpiResetPin {
+    pin: IoPin,
+}
+
+impl RpiResetPin {
+    /** Take the pin and release the reset line */
+    pub fn new(pin: Pin) -> RpiResetPin {
+        let mut pin = pin.into_io(Mode::Input);
+        pin.set_bias(Bias::PullUp);
+        RpiResetPin { pin }
+    }
+}
+
+impl embedded_hal::digital::ErrorType for RpiResetPin {
+    type Error = Infallible;
+}
+
+impl embedded_hal::digital::OutputPin for RpiResetPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        // Latch the level before switching to output, so that the line is never driven high
+        self.pin.set_low();
+        self.pin.set_bias(Bias::Off);
+        self.pin.set_mode(Mode::Output);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_mode(Mode::Input);
+        self.pin.set_bias(Bias::PullUp);
+        Ok(())
+    }
+}
+
+/** How the chip select (NSS) line of the module is driven */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RpiChipSelect {
+    /** NSS is connected to the CE pin of the `SlaveSelect` the `Spi` bus was opened with, and is driven by
+     * the SPI peripheral */
+    Hardware,
+    /** NSS is connected to an arbitrary GPIO (BCM pin number), which the driver drives itself. The pin is
+     * acquired once and held for the lifetime of the driver. */
+    Software(u8, ChipSelectTiming),
+}
+
+impl RpiRFM95 {
+    /** Create a driver from an rppal SPI bus and BCM pin numbers. With `RpiChipSelect::Software`, the
+     * hardware slave select of `spi` should be one that is not connected to anything. */
+    pub fn from_rppal<M: Into<LoRaModulation>>(
+        spi: Spi,
+        irq_bcm_pin: u8,
+        chip_select: RpiChipSelect,
+        reset_bcm_pin: Option<u8>,
+        modulation: M,
+        band: Band,
+        channel: Channel,
+    ) -> Result<RpiRFM95, Error> {
+        let (irq_pin, cs_pin, cs_timing, reset_pin) =
+            rppal_pins(irq_bcm_pin, chip_select, reset_bcm_pin)?;
+        let mut rfm = RFM95::new(
+            SimpleHalSpiDevice::new(spi),
+            irq_pin,
+            cs_pin,
+            reset_pin,
+            modulation,
+            band,
+            channel,
+        );
+        rfm.set_chip_select_timing(cs_timing);
+        Ok(rfm)
+    }
+}
+
+/** Acquire the DIO0, chip select and reset pins */
+fn rppal_pins(
+    irq_bcm_pin: u8,
+    chip_select: RpiChipSelect,
+    reset_bcm_pin: Option<u8>,
+) -> Result<
+    (
+        InputPin,
+        Option<OutputPin>,
+        ChipSelectTiming,
+        Option<RpiResetPin>,
+    ),
+    Error,
+> {
+    let gpio = Gpio::new()?;
+    let irq_pin = gpio.get(irq_bcm_pin)?.into_input();
+    let (cs_pin, cs_timing) = match chip_select {
+        RpiChipSelect::Hardware => (None, ChipSelectTiming::default()),
+        RpiChipSelect::Software(pin, timing) => (Some(gpio.get(pin)?.into_output_high()), timing),
+    };
+    let reset_pin = match reset_bcm_pin {
+        Some(pin) => Some(RpiResetPin::new(gpio.get(pin)?)),
+        None => None,
+    };
+    Ok((irq_pin, cs_pin, cs_timing, reset_pin))
+}
+
+/** DIO0 line that is woken up by rppal's asynchronous interrupt handler, for use with `AsyncRfm95` */
+#[cfg(feature = "async")]
+pub struct RpiDio0 {
+    pin: InputPin,
+    interrupt: Arc<Notify>,
+}
+
+#[cfg(feature = "async")]
+impl RpiDio0 {
+    /** Install an interrupt handler on the pin, which is triggered on both edges */
+    pub fn new(mut pin: InputPin) -> Result<RpiDio0, Error> {
+        let interrupt = Arc::new(Notify::new());
+        let notify = interrupt.clone();
+        pin.set_async_interrupt(Trigger::Both, None, move |_| notify.notify_one())?;
+        Ok(RpiDio0 { pin, interrupt })
+    }
+
+    async fn wait_for_level(&mut self, high: bool) {
+        // A notification that arrives between checking the level and awaiting is kept by `Notify`
+        while self.pin.is_high() != high {
+            self.interrupt.notified().await;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal::digital::ErrorType for RpiDio0 {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "async")]
+impl Wait for RpiDio0 {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await;
+        self.wait_for_level(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await;
+        self.wait_for_level(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.interrupt.notified().await;
+        Ok(())
+    }
+}
+
+/** Asynchronous RFM9x driver using the Raspberry Pi SPI and GPIO peripherals through rppal */
+#[cfg(feature = "async")]
+pub type RpiAsyncRFM95 = AsyncRfm95<SimpleHalSpiDevice, RpiDio0, OutputPin, RpiResetPin>;
+
+#[cfg(feature = "async")]
+impl RpiAsyncRFM95 {
+    /** Create a driver from an rppal SPI bus and BCM pin numbers; see `RpiRFM95::from_rppal` */
+    pub fn from_rppal<M: Into<LoRaModulation>>(
+        spi: Spi,
+        irq_bcm_pin: u8,
+        chip_select: RpiChipSelect,
+        reset_bcm_pin: Option<u8>,
+        modulation: M,
+        band: Band,
+        channel: Channel,
+    ) -> Result<RpiAsyncRFM95, Error> {
+        let (irq_pin, cs_pin, cs_timing, reset_pin) =
+            rppal_pins(irq_bcm_pin, chip_select, reset_bcm_pin)?;
+        let mut rfm = AsyncRfm95::new(
+            SimpleHalSpiDevice::new(spi),
+            RpiDio0::new(irq_pin)?,
+            cs_pin,
+            reset_pin,
+            modulation,
+            band,
+            channel,
+        );
+        rfm.radio().set_chip_select_timing(cs_timing);
+        Ok(rfm)
+    }
+}
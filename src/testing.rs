@@ -0,0 +1,542 @@
+//! Simulated SX1276 for testing the driver without hardware.
+//!
+//! [`Sx1276`] holds the register file and FIFO of an emulated chip. It hands out a [`MockSpi`]
+//! implementing `SpiDevice` and a [`MockDio0`] implementing `InputPin`, which are passed to
+//! `RFM95::new` in place of the real peripherals. The emulation covers the register map, the
+//! `RegOpMode` state machine, the FIFO pointers and the IRQ flags of the LoRa modem.
+//!
+//! Time does not pass inside the simulation. Transmissions, receptions and timeouts complete the
+//! next time the driver samples DIO0, which is what the driver does while waiting for an interrupt.
+//! With the `async` feature, [`MockDio0`] also implements `embedded_hal_async::digital::Wait` by
+//! sampling the line every millisecond.
+//!
+//! [`ManualClock`] is a [`Clock`] that only advances when told to, for testing the duty-cycle tracker.
+use crate::duty_cycle::Clock;
+use crate::modulation::CodingRate;
+use crate::registers::{
+    HopChannelFlags, IRQFlags, InvertIQFlags, Mode, ModemStatusFlags, Register,
+};
+use core::convert::Infallible;
+use embedded_hal::digital::{self, InputPin};
+use embedded_hal::spi::{self, Operation, SpiDevice};
+#[cfg(feature = "async")]
+use embedded_hal_async::digital::Wait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const REG_FIFO: u8 = Register::FIFO as u8;
+const REG_OP_MODE: u8 = Register::OpMode as u8;
+const REG_FIFO_ADDR_PTR: u8 = Register::FIFOAddressPointer as u8;
+const REG_FIFO_TX_BASE_ADDR: u8 = Register::FIFOTXBaseAddress as u8;
+const REG_FIFO_RX_BASE_ADDR: u8 = Register::FIFORXBaseAddress as u8;
+const REG_FIFO_RX_CURRENT_ADDR: u8 = Register::FIFORXCurrent as u8;
+const REG_IRQ_FLAGS_MASK: u8 = Register::IRQFlagsMask as u8;
+const REG_IRQ_FLAGS: u8 = Register::IRQFlags as u8;
+const REG_RX_NB_BYTES: u8 = Register::ReceiveNumberOfBytes as u8;
+const REG_RX_HEADER_CNT_LSB: u8 = Register::ReceiveValidHeaderCountLSB as u8;
+const REG_RX_PACKET_CNT_LSB: u8 = Register::ReceiveValidPacketCountLSB as u8;
+const REG_MODEM_STATUS: u8 = Register::ModemStatus as u8;
+const REG_PKT_SNR_VALUE: u8 = Register::LastSNRValue as u8;
+const REG_PKT_RSSI_VALUE: u8 = Register::LastRSSIValue as u8;
+const REG_HOP_CHANNEL: u8 = Register::HopChannel as u8;
+const REG_FEI_MSB: u8 = Register::FrequencyErrorMSB as u8;
+const REG_PAYLOAD_LENGTH: u8 = Register::PayloadLength as u8;
+const REG_FIFO_RX_BYTE_ADDR: u8 = Register::FIFOReceiveAddress as u8;
+const REG_DIO_MAPPING_1: u8 = Register::DIOMapping1 as u8;
+const REG_INVERT_IQ: u8 = Register::InvertIQ as u8;
+
+const MODE_MASK: u8 = 0b0000_0111;
+const MODE_SLEEP: u8 = Mode::SLEEP.bits();
+const MODE_STANDBY: u8 = Mode::STANDBY.bits();
+const MODE_TRANSMIT: u8 = Mode::TRANSMIT.bits();
+const MODE_RECEIVE_CONTINUOUS: u8 = Mode::RECEIVE_CONTINUOUS.bits();
+const MODE_RECEIVE_SINGLE: u8 = Mode::RECEIVE_SINGLE.bits();
+const MODE_CHANNEL_ACTIVITY_DETECTION: u8 = Mode::CHANNEL_ACTIVITY_DETECTION.bits();
+const LONG_RANGE_MODE: u8 = Mode::LORA.bits();
+
+const IRQ_CAD_DETECTED: u8 = IRQFlags::CHANNEL_ACTIVITY_DETECTED.bits();
+const IRQ_CAD_DONE: u8 = IRQFlags::CHANNEL_ACTIVITY_DETECTION_DONE.bits();
+const IRQ_TX_DONE: u8 = IRQFlags::TRANSMIT_DONE.bits();
+const IRQ_VALID_HEADER: u8 = IRQFlags::VALID_HEADER_RECEIVED.bits();
+const IRQ_PAYLOAD_CRC_ERROR: u8 = IRQFlags::PAYLOAD_CRC_ERROR.bits();
+const IRQ_RX_DONE: u8 = IRQFlags::RECEIVE_DONE.bits();
+const IRQ_RX_TIMEOUT: u8 = IRQFlags::RECEIVE_TIMEOUT.bits();
+
+/** Packet waiting to be picked up by the emulated receiver */
+#[derive(Clone, Debug)]
+pub struct IncomingPacket {
+    pub payload: Vec<u8>,
+    /** Raise `PayloadCrcError` when the packet is received */
+    pub crc_error: bool,
+    /** Whether the (emulated) packet header announces a payload CRC */
+    pub crc_on: bool,
+    /** Raw value for `RegPktRssiValue` */
+    pub rssi: u8,
+    /** Raw value for `RegPktSnrValue` (two's complement, in quarter dB) */
+    pub snr: i8,
+    /** Raw 20 bit frequency error for `RegFeiMsb/Mid/Lsb` */
+    pub frequency_error: i32,
+    /** Coding rate announced in the header, reported through `RegModemStat` */
+    pub coding_rate: CodingRate,
+    /** Transmitted with inverted I and Q, as LoRaWAN downlinks are. The packet is only heard by a receiver
+     * with the same setting; otherwise it passes unnoticed. */
+    pub inverted_iq: bool,
+}
+
+impl IncomingPacket {
+    pub fn new(payload: &[u8]) -> IncomingPacket {
+        IncomingPacket {
+            payload: payload.to_vec(),
+            crc_error: false,
+            crc_on: true,
+            rssi: 0x40,
+            snr: 0x20,
+            frequency_error: 0,
+            coding_rate: CodingRate::CR4_5,
+            inverted_iq: false,
+        }
+    }
+}
+
+struct State {
+    registers: [u8; 0x80],
+    fifo: [u8; 256],
+    rx_write_address: u8,
+    incoming: VecDeque<IncomingPacket>,
+    transmitted: Vec<Vec<u8>>,
+    channel_activity: bool,
+    transactions: usize,
+}
+
+/** Emulated SX1276 register file and modem */
+#[derive(Clone)]
+pub struct Sx1276 {
+    state: Arc<Mutex<State>>,
+}
+
+/** SPI device connected to an emulated [`Sx1276`] */
+pub struct MockSpi {
+    state: Arc<Mutex<State>>,
+}
+
+/** DIO0 line of an emulated [`Sx1276`] */
+pub struct MockDio0 {
+    state: Arc<Mutex<State>>,
+}
+
+impl Sx1276 {
+    /** Create an emulated chip with its registers at their power-on values */
+    pub fn new() -> Sx1276 {
+        let mut registers = [0u8; 0x80];
+        for register in Register::ALL.iter() {
+            registers[*register as usize] = register.reset_value();
+        }
+
+        Sx1276 {
+            state: Arc::new(Mutex::new(State {
+                registers,
+                fifo: [0u8; 256],
+                rx_write_address: 0,
+                incoming: VecDeque::new(),
+                transmitted: Vec::new(),
+                channel_activity: false,
+                transactions: 0,
+            })),
+        }
+    }
+
+    /** SPI device to hand to the driver */
+    pub fn spi(&self) -> MockSpi {
+        MockSpi {
+            state: self.state.clone(),
+        }
+    }
+
+    /** DIO0 pin to hand to the driver */
+    pub fn dio0(&self) -> MockDio0 {
+        MockDio0 {
+            state: self.state.clone(),
+        }
+    }
+
+    /** Queue a packet on the air. It is received as soon as the modem is in a receive mode. */
+    pub fn receive(&self, packet: IncomingPacket) {
+        self.lock().incoming.push_back(packet);
+    }
+
+    /** Payloads transmitted so far, oldest first */
+    pub fn transmitted(&self) -> Vec<Vec<u8>> {
+        self.lock().transmitted.clone()
+    }
+
+    /** Whether channel activity detection finds a LoRa preamble */
+    pub fn set_channel_activity(&self, active: bool) {
+        self.lock().channel_activity = active;
+    }
+
+    /** Current value of a register */
+    pub fn register(&self, address: u8) -> u8 {
+        self.lock().registers[(address & 0x7F) as usize]
+    }
+
+    /** Overwrite a register, bypassing the emulated side effects of an SPI write */
+    pub fn set_register(&self, address: u8, value: u8) {
+        self.lock().registers[(address & 0x7F) as usize] = value;
+    }
+
+    /** Contents of the 256 byte FIFO */
+    pub fn fifo(&self) -> [u8; 256] {
+        self.lock().fifo
+    }
+
+    /** Number of SPI transactions performed so far */
+    pub fn transactions(&self) -> usize {
+        self.lock().transactions
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Default for Sx1276 {
+    fn default() -> Sx1276 {
+        Sx1276::new()
+    }
+}
+
+impl State {
+    fn mode(&self) -> u8 {
+        self.registers[REG_OP_MODE as usize] & MODE_MASK
+    }
+
+    fn set_mode(&mut self, mode: u8) {
+        let op_mode = &mut self.registers[REG_OP_MODE as usize];
+        *op_mode = (*op_mode & !MODE_MASK) | mode;
+    }
+
+    fn is_lora(&self) -> bool {
+        self.registers[REG_OP_MODE as usize] & LONG_RANGE_MODE != 0
+    }
+
+    fn read(&mut self, address: u8) -> u8 {
+        match address {
+            REG_FIFO => {
+                let pointer = self.registers[REG_FIFO_ADDR_PTR as usize];
+                self.registers[REG_FIFO_ADDR_PTR as usize] = pointer.wrapping_add(1);
+                self.fifo[pointer as usize]
+            }
+            _ => self.registers[address as usize],
+        }
+    }
+
+    fn write(&mut self, address: u8, value: u8) {
+        match address {
+            REG_FIFO => {
+                let pointer = self.registers[REG_FIFO_ADDR_PTR as usize];
+                self.registers[REG_FIFO_ADDR_PTR as usize] = pointer.wrapping_add(1);
+                self.fifo[pointer as usize] = value;
+            }
+            REG_OP_MODE => self.write_op_mode(value),
+            REG_IRQ_FLAGS => self.registers[REG_IRQ_FLAGS as usize] &= !value,
+            _ => {
+                let read_only = Register::ALL
+                    .iter()
+                    .any(|r| *r as u8 == address && r.is_read_only());
+                if !read_only {
+                    self.registers[address as usize] = value;
+                }
+            }
+        }
+    }
+
+    fn write_op_mode(&mut self, value: u8) {
+        let old = self.registers[REG_OP_MODE as usize];
+        // LongRangeMode can only be changed while the chip is asleep
+        let value = if old & MODE_MASK == MODE_SLEEP {
+            value
+        } else {
+            (value & !LONG_RANGE_MODE) | (old & LONG_RANGE_MODE)
+        };
+        self.registers[REG_OP_MODE as usize] = value;
+
+        let mode = value & MODE_MASK;
+        if mode != old & MODE_MASK
+            && (mode == MODE_RECEIVE_CONTINUOUS || mode == MODE_RECEIVE_SINGLE)
+        {
+            self.rx_write_address = self.registers[REG_FIFO_RX_BASE_ADDR as usize];
+        }
+    }
+
+    /** Let the modem finish whatever the current mode is waiting for */
+    fn step(&mut self) {
+        if !self.is_lora() {
+            return;
+        }
+
+        match self.mode() {
+            MODE_TRANSMIT => {
+                let base = self.registers[REG_FIFO_TX_BASE_ADDR as usize];
+                let length = self.registers[REG_PAYLOAD_LENGTH as usize];
+                let payload = (0..length)
+                    .map(|i| self.fifo[base.wrapping_add(i) as usize])
+                    .collect();
+                self.transmitted.push(payload);
+                self.registers[REG_IRQ_FLAGS as usize] |= IRQ_TX_DONE;
+                self.set_mode(MODE_STANDBY);
+            }
+            MODE_RECEIVE_CONTINUOUS
+                if self.registers[REG_IRQ_FLAGS as usize] & IRQ_RX_DONE == 0 =>
+            {
+                if let Some(packet) = self.incoming.pop_front().filter(|p| self.hears(p)) {
+                    self.deliver(packet);
+                }
+            }
+            MODE_RECEIVE_SINGLE => {
+                match self.incoming.pop_front().filter(|p| self.hears(p)) {
+                    Some(packet) => self.deliver(packet),
+                    None => self.registers[REG_IRQ_FLAGS as usize] |= IRQ_RX_TIMEOUT,
+                }
+                self.set_mode(MODE_STANDBY);
+            }
+            MODE_CHANNEL_ACTIVITY_DETECTION => {
+                let mut flags = IRQ_CAD_DONE;
+                if self.channel_activity {
+                    flags |= IRQ_CAD_DETECTED;
+                }
+                self.registers[REG_IRQ_FLAGS as usize] |= flags;
+                self.set_mode(MODE_STANDBY);
+            }
+            _ => {}
+        }
+    }
+
+    /** Whether the IQ setting of the receiver matches that of the packet */
+    fn hears(&self, packet: &IncomingPacket) -> bool {
+        let inverted =
+            self.registers[REG_INVERT_IQ as usize] & InvertIQFlags::INVERT_IQ_RX.bits() != 0;
+        packet.inverted_iq == inverted
+    }
+
+    fn deliver(&mut self, packet: IncomingPacket) {
+        let start = self.rx_write_address;
+        for (i, byte) in packet.payload.iter().enumerate() {
+            self.fifo[start.wrapping_add(i as u8) as usize] = *byte;
+        }
+        let length = packet.payload.len() as u8;
+        self.rx_write_address = start.wrapping_add(length);
+
+        let registers = &mut self.registers;
+        registers[REG_FIFO_RX_CURRENT_ADDR as usize] = start;
+        registers[REG_FIFO_RX_BYTE_ADDR as usize] = start.wrapping_add(length).wrapping_sub(1);
+        registers[REG_RX_NB_BYTES as usize] = length;
+        registers[REG_PKT_RSSI_VALUE as usize] = packet.rssi;
+        registers[REG_PKT_SNR_VALUE as usize] = packet.snr as u8;
+        registers[REG_MODEM_STATUS as usize] = match packet.coding_rate {
+            CodingRate::CR4_5 => 1 << 5,
+            CodingRate::CR4_6 => 2 << 5,
+            CodingRate::CR4_7 => 3 << 5,
+            CodingRate::CR4_8 => 4 << 5,
+        } | ModemStatusFlags::MODEM_CLEAR.bits();
+        let fei = (packet.frequency_error as u32) & 0x000F_FFFF;
+        registers[REG_FEI_MSB as usize] = (fei >> 16) as u8;
+        registers[REG_FEI_MSB as usize + 1] = (fei >> 8) as u8;
+        registers[REG_FEI_MSB as usize + 2] = fei as u8;
+        registers[REG_HOP_CHANNEL as usize] = if packet.crc_on {
+            HopChannelFlags::CRC_ON_PAYLOAD.bits()
+        } else {
+            0
+        };
+        registers[REG_RX_HEADER_CNT_LSB as usize] =
+            registers[REG_RX_HEADER_CNT_LSB as usize].wrapping_add(1);
+        registers[REG_RX_PACKET_CNT_LSB as usize] =
+            registers[REG_RX_PACKET_CNT_LSB as usize].wrapping_add(1);
+
+        let mut flags = IRQ_RX_DONE | IRQ_VALID_HEADER;
+        if packet.crc_error {
+            flags |= IRQ_PAYLOAD_CRC_ERROR;
+        }
+        registers[REG_IRQ_FLAGS as usize] |= flags;
+    }
+
+    fn dio0(&self) -> bool {
+        let flag = match self.registers[REG_DIO_MAPPING_1 as usize] >> 6 {
+            0b00 => IRQ_RX_DONE,
+            0b01 => IRQ_TX_DONE,
+            0b10 => IRQ_CAD_DONE,
+            _ => return false,
+        };
+        let flags =
+            self.registers[REG_IRQ_FLAGS as usize] & !self.registers[REG_IRQ_FLAGS_MASK as usize];
+        flags & flag != 0
+    }
+}
+
+/** Position within an SPI transaction: the first byte is the address, the rest is data */
+struct Access {
+    address: Option<u8>,
+    write: bool,
+}
+
+impl Access {
+    /** Clock one byte in and return the byte clocked out */
+    fn exchange(&mut self, state: &mut State, mosi: u8) -> u8 {
+        match self.address {
+            None => {
+                self.address = Some(mosi & 0x7F);
+                self.write = mosi & 0x80 != 0;
+                0
+            }
+            Some(address) => {
+                // Burst access increments the address, except on the FIFO
+                if address != REG_FIFO {
+                    self.address = Some((address + 1) & 0x7F);
+                }
+                if self.write {
+                    state.write(address, mosi);
+                    0
+                } else {
+                    state.read(address)
+                }
+            }
+        }
+    }
+}
+
+impl spi::ErrorType for MockSpi {
+    type Error = Infallible;
+}
+
+impl SpiDevice for MockSpi {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.transactions += 1;
+        let mut access = Access {
+            address: None,
+            write: false,
+        };
+
+        for operation in operations.iter_mut() {
+            match operation {
+                Operation::Read(buffer) => {
+                    for byte in buffer.iter_mut() {
+                        *byte = access.exchange(&mut state, 0);
+                    }
+                }
+                Operation::Write(buffer) => {
+                    for byte in buffer.iter() {
+                        access.exchange(&mut state, *byte);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    for i in 0..read.len().max(write.len()) {
+                        let miso = access.exchange(&mut state, write.get(i).copied().unwrap_or(0));
+                        if let Some(byte) = read.get_mut(i) {
+                            *byte = miso;
+                        }
+                    }
+                }
+                Operation::TransferInPlace(buffer) => {
+                    for byte in buffer.iter_mut() {
+                        *byte = access.exchange(&mut state, *byte);
+                    }
+                }
+                Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for MockDio0 {
+    type Error = Infallible;
+}
+
+impl InputPin for MockDio0 {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.step();
+        Ok(state.dio0())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+#[cfg(feature = "async")]
+impl MockDio0 {
+    async fn wait_for_level(&mut self, high: bool) {
+        while self.is_high().unwrap() != high {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Wait for MockDio0 {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await;
+        self.wait_for_level(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await;
+        self.wait_for_level(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        let level = self.is_high()?;
+        self.wait_for_level(!level).await;
+        Ok(())
+    }
+}
+
+/** Clock that stands still until advanced. Sleeping on it advances it by the duration slept, so that
+ * waiting for the duty cycle completes immediately. Clones share the same time. */
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    /** Create a clock starting at the current time */
+    pub fn new() -> ManualClock {
+        ManualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "async")]
+
+use rfm9x::testing::{IncomingPacket, MockDio0, MockSpi, Sx1276};
+use rfm9x::{AsyncRfm95, Band, Channel, ChannelSensing, DataRate, Error, ListenBeforeTalk, NoPin};
+use std::time::Duration;
+
+fn radio() -> (Sx1276, AsyncRfm95<MockSpi, MockDio0, NoPin, NoPin>) {
+    let chip = Sx1276::new();
+    let mut rfm = AsyncRfm95::new(
+        chip.spi(),
+        chip.dio0(),
+        None,
+        None,
+        DataRate::SF7_BW125,
+        Band::EU863,
+        Channel::Ch0,
+    );
+    rfm.radio().reset().unwrap();
+    (chip, rfm)
+}
+
+#[tokio::test]
+async fn send_transmits_payload() {
+    let (chip, mut rfm) = radio();
+    rfm.send(b"hello").await.unwrap();
+    assert_eq!(chip.transmitted(), vec![b"hello".to_vec()]);
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+#[tokio::test]
+async fn send_listens_before_talk_with_channel_activity_detection() {
+    let (chip, mut rfm) = radio();
+    rfm.radio().set_listen_before_talk(Some(ListenBeforeTalk {
+        sensing: ChannelSensing::ChannelActivity,
+        listen_time: Duration::from_millis(1),
+        max_extra_listen_time: Duration::ZERO,
+        min_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(2),
+        max_retries: 2,
+    }));
+
+    chip.set_channel_activity(true);
+    assert_eq!(rfm.send(b"busy").await, Err(Error::ChannelBusy));
+    assert!(chip.transmitted().is_empty());
+
+    chip.set_channel_activity(false);
+    rfm.send(b"clear").await.unwrap();
+    assert_eq!(chip.transmitted(), vec![b"clear".to_vec()]);
+}
+
+#[tokio::test]
+async fn receive_reads_fifo() {
+    let (chip, mut rfm) = radio();
+    chip.receive(IncomingPacket::new(b"world"));
+    let packet = rfm
+        .receive(
+            Channel::Ch0,
+            DataRate::SF7_BW125,
+            true,
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(packet.payload, b"world");
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+#[tokio::test]
+async fn receive_times_out() {
+    let (chip, mut rfm) = radio();
+    let result = rfm
+        .receive(
+            Channel::Ch0,
+            DataRate::SF7_BW125,
+            true,
+            Duration::from_millis(20),
+        )
+        .await;
+    assert_eq!(result, Err(Error::ReceiveTimedOut));
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+#[tokio::test]
+async fn dropping_receive_returns_to_standby() {
+    let (chip, mut rfm) = radio();
+    let receive = rfm.receive(
+        Channel::Ch0,
+        DataRate::SF7_BW125,
+        true,
+        Duration::from_secs(10),
+    );
+    assert!(tokio::time::timeout(Duration::from_millis(20), receive)
+        .await
+        .is_err());
+    assert_eq!(chip.register(0x01), 0x81);
+}
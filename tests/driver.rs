@@ -0,0 +1,845 @@
+use embedded_hal::digital::{self, OutputPin};
+use rfm9x::registers::Register;
+use rfm9x::testing::{IncomingPacket, ManualClock, MockDio0, MockSpi, Sx1276};
+use rfm9x::{
+    Band, Bandwidth, Channel, ChannelSelector, ChannelSensing, ChipSelectTiming, Clock, CodingRate,
+    DataRate, DutyCycleEnforcement, DutyCycleTracker, Error, ListenBeforeTalk, LoRaModulation,
+    NoPin, PaOutput, PacketParameters, RandomWithoutRepeat, RoundRobin, SeededRandom,
+    SpreadingFactor, RFM95,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+fn radio() -> (Sx1276, RFM95<MockSpi, MockDio0, NoPin, NoPin>) {
+    let chip = Sx1276::new();
+    let rfm = RFM95::new(
+        chip.spi(),
+        chip.dio0(),
+        None,
+        None,
+        DataRate::SF7_BW125,
+        Band::EU863,
+        Channel::Ch0,
+    );
+    (chip, rfm)
+}
+
+#[test]
+fn reset_enters_lora_sleep() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    assert_eq!(chip.register(0x01), 0x80);
+    assert_eq!(chip.register(0x0E), 0x80);
+    assert_eq!(chip.register(0x0F), 0x00);
+}
+
+#[test]
+fn send_packet_transmits_payload() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    rfm.send_packet(b"hello").unwrap();
+    assert_eq!(chip.transmitted(), vec![b"hello".to_vec()]);
+    // Back in LoRa standby after TxDone
+    assert_eq!(chip.register(0x01), 0x81);
+    // 868.1 MHz
+    assert_eq!(
+        [
+            chip.register(0x06),
+            chip.register(0x07),
+            chip.register(0x08)
+        ],
+        [0xD9, 0x06, 0x66]
+    );
+}
+
+#[test]
+fn send_packet_checks_payload_length() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    assert_eq!(rfm.send_packet(&[]), Err(Error::InvalidPayloadLength(0)));
+    assert_eq!(
+        rfm.send_packet(&[0xA5; 256]),
+        Err(Error::InvalidPayloadLength(256))
+    );
+    assert!(chip.transmitted().is_empty());
+
+    let longest: Vec<u8> = (0..255).map(|i| i as u8).collect();
+    rfm.send_packet(&longest).unwrap();
+    assert_eq!(chip.transmitted(), vec![longest]);
+}
+
+#[test]
+fn low_data_rate_optimize_follows_symbol_time() {
+    // 2^11 / 125 kHz = 16.4 ms
+    assert!(LoRaModulation::from(DataRate::SF11_BW125).low_data_rate_optimize());
+    assert!(!LoRaModulation::from(DataRate::SF10_BW125).low_data_rate_optimize());
+    assert!(
+        !LoRaModulation::new(SpreadingFactor::SF12, Bandwidth::BW500, CodingRate::CR4_5)
+            .low_data_rate_optimize()
+    );
+    assert!(
+        LoRaModulation::new(SpreadingFactor::SF7, Bandwidth::BW7_8, CodingRate::CR4_5)
+            .low_data_rate_optimize()
+    );
+}
+
+#[test]
+fn cad_duration_follows_spreading_factor() {
+    // (128 + 32) / 125 kHz
+    assert_eq!(
+        LoRaModulation::from(DataRate::SF7_BW125).cad_duration(),
+        Duration::from_micros(1280)
+    );
+    // (4096 + 32) / 125 kHz
+    assert_eq!(
+        LoRaModulation::from(DataRate::SF12_BW125).cad_duration(),
+        Duration::from_micros(33_024)
+    );
+}
+
+#[test]
+fn send_packet_applies_modulation() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+
+    rfm.set_modulation(LoRaModulation::new(
+        SpreadingFactor::SF12,
+        Bandwidth::BW500,
+        CodingRate::CR4_6,
+    ));
+    rfm.send_packet(b"fast").unwrap();
+    assert_eq!(chip.register(0x1D), 0x94);
+    assert_eq!(chip.register(0x1E), 0xC4);
+    assert_eq!(chip.register(0x26), 0x04);
+
+    rfm.set_modulation(LoRaModulation::new(
+        SpreadingFactor::SF10,
+        Bandwidth::BW62_5,
+        CodingRate::CR4_7,
+    ));
+    rfm.send_packet(b"slow").unwrap();
+    assert_eq!(chip.register(0x1D), 0x66);
+    assert_eq!(chip.register(0x1E), 0xA4);
+    assert_eq!(chip.register(0x26), 0x0C);
+}
+
+#[test]
+fn sf6_uses_implicit_header() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+
+    rfm.set_modulation(LoRaModulation::new(
+        SpreadingFactor::SF6,
+        Bandwidth::BW125,
+        CodingRate::CR4_5,
+    ));
+    rfm.send_packet(b"sf6").unwrap();
+    assert_eq!(chip.register(0x1D), 0x73);
+    assert_eq!(chip.register(0x31) & 0x07, 0x05);
+    assert_eq!(chip.register(0x37), 0x0C);
+
+    rfm.set_modulation(DataRate::SF7_BW125);
+    rfm.send_packet(b"sf7").unwrap();
+    assert_eq!(chip.register(0x1D), 0x72);
+    assert_eq!(chip.register(0x31) & 0x07, 0x03);
+    assert_eq!(chip.register(0x37), 0x0A);
+}
+
+#[test]
+fn set_tx_power_configures_pa() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    // 17 dBm on PA_BOOST, 140 mA limit
+    assert_eq!(chip.register(0x09), 0xFF);
+    assert_eq!(chip.register(0x4D), 0x84);
+    assert_eq!(chip.register(0x0B), 0x31);
+
+    rfm.set_tx_power(20, PaOutput::PaBoost).unwrap();
+    assert_eq!(chip.register(0x09), 0xFF);
+    assert_eq!(chip.register(0x4D), 0x87);
+    assert_eq!(chip.register(0x0B), 0x3B);
+
+    rfm.set_tx_power(2, PaOutput::PaBoost).unwrap();
+    assert_eq!(chip.register(0x09), 0xF0);
+    assert_eq!(chip.register(0x4D), 0x84);
+
+    rfm.set_tx_power(10, PaOutput::Rfo).unwrap();
+    assert_eq!(chip.register(0x09), 0x7A);
+    assert_eq!(chip.register(0x0B), 0x2B);
+
+    rfm.set_tx_power(-4, PaOutput::Rfo).unwrap();
+    assert_eq!(chip.register(0x09), 0x00);
+}
+
+#[test]
+fn set_tx_power_rejects_impossible_power() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    for (dbm, output) in [
+        (15, PaOutput::Rfo),
+        (-5, PaOutput::Rfo),
+        (1, PaOutput::PaBoost),
+        (21, PaOutput::PaBoost),
+    ] {
+        assert_eq!(
+            rfm.set_tx_power(dbm, output),
+            Err(Error::InvalidTxPower(dbm, output))
+        );
+    }
+    // Registers are left untouched
+    assert_eq!(chip.register(0x09), 0xFF);
+}
+
+#[test]
+fn fifo_is_transferred_in_a_single_transaction() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    // Leave sleep mode first, so both packets start from standby
+    rfm.send_packet(b"warm-up").unwrap();
+
+    let mut transactions = Vec::new();
+    for payload in [vec![1u8], vec![2u8; 254]] {
+        let before = chip.transactions();
+        rfm.send_packet(&payload).unwrap();
+        let sent = chip.transactions() - before;
+
+        chip.receive(IncomingPacket::new(&payload));
+        let before = chip.transactions();
+        let packet = rfm
+            .receive_packet_on_tx(true, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(packet.payload, payload);
+        transactions.push((sent, chip.transactions() - before));
+    }
+    // The number of transactions does not depend on the payload length
+    assert_eq!(transactions[0], transactions[1]);
+    assert_eq!(chip.transmitted()[2], vec![2u8; 254]);
+}
+
+/** Chip select pin that counts its transitions and can be made to fail */
+#[derive(Clone, Default)]
+struct RecordingPin {
+    transitions: Rc<RefCell<Vec<bool>>>,
+    fail: Rc<Cell<bool>>,
+}
+
+impl digital::ErrorType for RecordingPin {
+    type Error = digital::ErrorKind;
+}
+
+impl OutputPin for RecordingPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        if self.fail.get() {
+            return Err(digital::ErrorKind::Other);
+        }
+        self.transitions.borrow_mut().push(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.transitions.borrow_mut().push(true);
+        Ok(())
+    }
+}
+
+#[test]
+fn software_chip_select_wraps_transactions() {
+    let chip = Sx1276::new();
+    let cs = RecordingPin::default();
+    let mut rfm: RFM95<_, _, _, NoPin> = RFM95::new(
+        chip.spi(),
+        chip.dio0(),
+        Some(cs.clone()),
+        None,
+        DataRate::SF7_BW125,
+        Band::EU863,
+        Channel::Ch0,
+    );
+    rfm.set_chip_select_timing(ChipSelectTiming::new(
+        Duration::from_nanos(30),
+        Duration::from_nanos(100),
+    ));
+
+    assert_eq!(rfm.get_version().unwrap(), 0x12);
+    rfm.write_registers(Register::FIFO, &[1, 2, 3]).unwrap();
+    assert_eq!(*cs.transitions.borrow(), vec![false, true, false, true]);
+    assert_eq!(chip.transactions(), 2);
+
+    cs.fail.set(true);
+    let error = rfm.get_version().unwrap_err();
+    assert!(matches!(error, Error::Gpio(digital::ErrorKind::Other, _)));
+    // The error of the pin is kept as the source
+    assert_eq!(
+        std::error::Error::source(&error).unwrap().to_string(),
+        "Other"
+    );
+    assert_eq!(chip.transactions(), 2);
+}
+
+#[test]
+fn set_frequency_hz_selects_port() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+
+    rfm.set_frequency_hz(433_175_000).unwrap();
+    assert_eq!(chip.register(0x01) & 0x08, 0x08);
+    assert_eq!(rfm.get_frequency_hz().unwrap(), 433_174_988);
+
+    // Transmissions stay on the LF port
+    rfm.send_packet(b"433").unwrap();
+    assert_eq!(chip.register(0x01), 0x89);
+
+    rfm.set_frequency_hz(915_000_000).unwrap();
+    assert_eq!(chip.register(0x01) & 0x08, 0);
+    assert_eq!(rfm.get_frequency_hz().unwrap(), 915_000_000);
+
+    assert_eq!(
+        rfm.set_frequency_hz(600_000_000),
+        Err(Error::InvalidFrequency(600_000_000))
+    );
+}
+
+#[test]
+fn receive_packet_reads_fifo() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    chip.receive(IncomingPacket::new(b"pong"));
+    let packet = rfm
+        .receive_packet(
+            Channel::Ch1,
+            DataRate::SF9_BW125,
+            true,
+            Duration::from_millis(100),
+        )
+        .unwrap();
+    assert_eq!(packet.payload, b"pong");
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+#[test]
+fn receive_packet_reports_signal_quality() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    let mut incoming = IncomingPacket::new(b"quality");
+    incoming.rssi = 60;
+    incoming.snr = -10;
+    incoming.frequency_error = -2048;
+    incoming.coding_rate = CodingRate::CR4_8;
+    chip.receive(incoming);
+
+    let packet = rfm
+        .receive_packet(
+            Channel::Ch0,
+            DataRate::SF7_BW125,
+            true,
+            Duration::from_millis(100),
+        )
+        .unwrap();
+    assert_eq!(packet.snr, -2.5);
+    // -157 + 60 - 2.5 (rounded down)
+    assert_eq!(packet.rssi, -100);
+    // -2048 * 2^24 / 32 MHz * 125 kHz / 500 kHz
+    assert_eq!(packet.frequency_error, -268);
+    assert_eq!(packet.coding_rate, Some(CodingRate::CR4_8));
+    assert!(packet.crc_on);
+}
+
+#[test]
+fn reset_rejects_unknown_chip() {
+    let (chip, mut rfm) = radio();
+    chip.set_register(0x42, 0x22);
+    assert_eq!(rfm.reset(), Err(Error::InvalidVersion(0x22)));
+}
+
+#[test]
+fn receive_packet_times_out() {
+    let (_chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    let result = rfm.receive_packet(
+        Channel::Ch0,
+        DataRate::SF7_BW125,
+        true,
+        Duration::from_millis(20),
+    );
+    assert_eq!(result, Err(Error::ReceiveTimedOut));
+}
+
+#[test]
+fn receive_packet_reports_crc_error() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    let mut packet = IncomingPacket::new(b"garbled");
+    packet.crc_error = true;
+
+    chip.receive(packet.clone());
+    let result = rfm.receive_packet(
+        Channel::Ch0,
+        DataRate::SF7_BW125,
+        true,
+        Duration::from_millis(100),
+    );
+    assert_eq!(result, Err(Error::CrcError(None)));
+
+    rfm.set_keep_corrupted_packets(true);
+    chip.receive(packet);
+    let result = rfm.receive_packet(
+        Channel::Ch0,
+        DataRate::SF7_BW125,
+        true,
+        Duration::from_millis(100),
+    );
+    match result {
+        Err(Error::CrcError(Some(packet))) => assert_eq!(packet.payload, b"garbled"),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn start_receiving_delivers_every_packet() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    let mut corrupted = IncomingPacket::new(b"two");
+    corrupted.crc_error = true;
+    chip.receive(IncomingPacket::new(b"one"));
+    chip.receive(corrupted);
+    chip.receive(IncomingPacket::new(b"three"));
+
+    let mut receiver = rfm
+        .start_receiving(Channel::Ch0, DataRate::SF7_BW125, true, 8)
+        .unwrap();
+    assert_eq!(receiver.next().unwrap().unwrap().payload, b"one");
+    assert_eq!(receiver.next().unwrap(), Err(Error::CrcError(None)));
+    assert_eq!(receiver.next().unwrap().unwrap().payload, b"three");
+    assert_eq!(
+        receiver.next_timeout(Duration::from_millis(20)),
+        Some(Err(Error::ReceiveTimedOut))
+    );
+    // Still receiving
+    assert_eq!(chip.register(0x01), 0x85);
+
+    receiver.stop().unwrap();
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+#[test]
+fn start_receiving_drops_packets_when_queue_is_full() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    for payload in [b"a", b"b", b"c"] {
+        chip.receive(IncomingPacket::new(payload));
+    }
+
+    let mut receiver = rfm
+        .start_receiving(Channel::Ch0, DataRate::SF7_BW125, true, 1)
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(receiver.dropped(), 2);
+    assert_eq!(receiver.next().unwrap().unwrap().payload, b"a");
+
+    // Dropping the receiver stops reception
+    drop(receiver);
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+#[test]
+fn receive_single_uses_symbol_timeout() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+
+    assert_eq!(
+        rfm.receive_single(Channel::Ch0, DataRate::SF9_BW125, true, 0x2A5),
+        Err(Error::SymbolTimeout)
+    );
+    assert_eq!(chip.register(0x1E), 0x96);
+    assert_eq!(chip.register(0x1F), 0xA5);
+    assert_eq!(chip.register(0x40) & 0x30, 0x00);
+    assert_eq!(chip.register(0x01), 0x81);
+
+    chip.receive(IncomingPacket::new(b"window"));
+    let packet = rfm
+        .receive_single(Channel::Ch0, DataRate::SF9_BW125, true, 8)
+        .unwrap();
+    assert_eq!(packet.payload, b"window");
+    assert_eq!(chip.register(0x1E), 0x94);
+    assert_eq!(chip.register(0x1F), 0x08);
+    assert_eq!(chip.register(0x01), 0x81);
+
+    assert_eq!(
+        rfm.receive_single(Channel::Ch0, DataRate::SF9_BW125, true, 0x400),
+        Err(Error::InvalidSymbolTimeout(0x400))
+    );
+}
+
+#[test]
+fn detect_channel_activity_reports_preamble() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+
+    assert_eq!(rfm.detect_channel_activity(DataRate::SF7_BW125), Ok(false));
+    assert_eq!(chip.register(0x40), 0xA0);
+    assert_eq!(chip.register(0x01), 0x81);
+
+    chip.set_channel_activity(true);
+    assert_eq!(rfm.detect_channel_activity(DataRate::SF7_BW125), Ok(true));
+    assert_eq!(chip.register(0x01), 0x81);
+}
+
+fn lbt(sensing: ChannelSensing) -> ListenBeforeTalk {
+    ListenBeforeTalk {
+        sensing,
+        listen_time: Duration::from_millis(1),
+        max_extra_listen_time: Duration::ZERO,
+        min_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(2),
+        max_retries: 2,
+    }
+}
+
+#[test]
+fn listen_before_talk_with_channel_activity_detection() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    rfm.set_listen_before_talk(Some(lbt(ChannelSensing::ChannelActivity)));
+
+    chip.set_channel_activity(true);
+    assert_eq!(rfm.send_packet(b"busy"), Err(Error::ChannelBusy));
+    assert!(chip.transmitted().is_empty());
+
+    chip.set_channel_activity(false);
+    rfm.send_packet(b"clear").unwrap();
+    assert_eq!(chip.transmitted(), vec![b"clear".to_vec()]);
+}
+
+#[test]
+fn listen_before_talk_with_rssi_threshold() {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    rfm.set_listen_before_talk(Some(ListenBeforeTalk {
+        max_backoff: Duration::from_millis(1),
+        ..ListenBeforeTalk::etsi_en_300_220()
+    }));
+
+    // -157 + 80 = -77 dBm
+    chip.set_register(0x1B, 80);
+    assert_eq!(rfm.send_packet(b"busy"), Err(Error::ChannelBusy));
+    assert_eq!(chip.register(0x01), 0x81);
+
+    // -157 + 60 = -97 dBm
+    chip.set_register(0x1B, 60);
+    rfm.send_packet(b"clear").unwrap();
+    assert_eq!(chip.transmitted(), vec![b"clear".to_vec()]);
+}
+
+#[test]
+fn time_on_air_matches_datasheet_formula() {
+    let sf7 = LoRaModulation::from(DataRate::SF7_BW125);
+    let airtime = rfm9x::time_on_air(&PacketParameters::new(sf7), 10);
+    assert_eq!(airtime.preamble_symbols, 12.25);
+    assert_eq!(airtime.header_symbols, 8);
+    assert_eq!(airtime.payload_symbols, 20);
+    assert_eq!(airtime.total, Duration::from_micros(41_216));
+
+    // Low data rate optimisation is required at SF12/125 kHz
+    let sf12 = LoRaModulation::new(SpreadingFactor::SF12, Bandwidth::BW125, CodingRate::CR4_5);
+    let parameters = PacketParameters::new(sf12);
+    assert!(parameters.low_data_rate_optimize);
+    let airtime = rfm9x::time_on_air(&parameters, 51);
+    assert_eq!(airtime.payload_symbols, 55);
+    assert_eq!(airtime.total, Duration::from_micros(2_465_792));
+
+    // An empty packet without header and CRC consists of the preamble and first block only
+    let parameters = PacketParameters {
+        explicit_header: false,
+        crc: false,
+        ..PacketParameters::new(sf7)
+    };
+    let airtime = rfm9x::time_on_air(&parameters, 0);
+    assert_eq!(airtime.payload_symbols, 0);
+    assert_eq!(airtime.total, Duration::from_micros(20_736));
+}
+
+#[test]
+fn time_on_air_uses_configured_modulation() {
+    let (_chip, mut rfm) = radio();
+    assert_eq!(rfm.time_on_air(10), Duration::from_micros(41_216));
+    rfm.set_modulation(DataRate::SF12_BW125);
+    assert!(rfm.time_on_air(10) > Duration::from_secs(1));
+}
+
+#[test]
+fn duty_cycle_tracker_budget_per_sub_band() {
+    let clock = ManualClock::new();
+    let start = clock.now();
+    let mut tracker =
+        DutyCycleTracker::with_clock(Band::EU863, DutyCycleEnforcement::Refuse, clock.clone());
+
+    // 1% of an hour on 868.1 MHz, 10% on 869.525 MHz
+    assert_eq!(
+        tracker.remaining(868_100_000),
+        Some(Duration::from_secs(36))
+    );
+    assert_eq!(
+        tracker.remaining(869_525_000),
+        Some(Duration::from_secs(360))
+    );
+
+    tracker.record(868_100_000, Duration::from_secs(20));
+    clock.advance(Duration::from_secs(60));
+    tracker.record(868_300_000, Duration::from_secs(10));
+    assert_eq!(tracker.remaining(868_500_000), Some(Duration::from_secs(6)));
+    assert_eq!(
+        tracker.remaining(869_525_000),
+        Some(Duration::from_secs(360))
+    );
+
+    // Fits now, or once the first transmission left the window
+    let now = clock.now();
+    assert_eq!(
+        tracker.next_allowed(868_100_000, Duration::from_secs(6)),
+        Some(now)
+    );
+    assert_eq!(
+        tracker.next_allowed(868_100_000, Duration::from_secs(7)),
+        Some(start + Duration::from_secs(3600))
+    );
+    assert_eq!(
+        tracker.next_allowed(868_100_000, Duration::from_secs(30)),
+        Some(start + Duration::from_secs(3660))
+    );
+    assert_eq!(
+        tracker.next_allowed(868_100_000, Duration::from_secs(37)),
+        None
+    );
+
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(
+        tracker.remaining(868_100_000),
+        Some(Duration::from_secs(36))
+    );
+
+    // Outside the sub-bands and in bands without duty-cycle limits
+    assert_eq!(tracker.remaining(870_500_000), None);
+    let tracker = DutyCycleTracker::new(Band::US901, DutyCycleEnforcement::Refuse);
+    assert_eq!(tracker.remaining(903_900_000), None);
+}
+
+fn duty_cycle_radio(
+    enforcement: DutyCycleEnforcement,
+) -> (Sx1276, ManualClock, RFM95<MockSpi, MockDio0, NoPin, NoPin>) {
+    let (chip, mut rfm) = radio();
+    rfm.reset().unwrap();
+    rfm.set_modulation(DataRate::SF12_BW125);
+    // 868.7 - 869.2 MHz is limited to 0.1%, 3.6 s per hour
+    rfm.set_frequency_hz(868_850_000).unwrap();
+    let clock = ManualClock::new();
+    rfm.set_duty_cycle_tracker(Some(DutyCycleTracker::with_clock(
+        Band::EU863,
+        enforcement,
+        clock.clone(),
+    )))
+    .unwrap();
+    (chip, clock, rfm)
+}
+
+#[test]
+fn duty_cycle_tracker_of_other_band_is_rejected() {
+    let (_chip, mut rfm) = radio();
+    assert_eq!(
+        rfm.set_duty_cycle_tracker(Some(DutyCycleTracker::new(
+            Band::US901,
+            DutyCycleEnforcement::Refuse
+        ))),
+        Err(Error::BandMismatch(Band::US901, Band::EU863))
+    );
+    assert!(rfm.duty_cycle_tracker().is_none());
+    assert_eq!(rfm.set_duty_cycle_tracker(None), Ok(()));
+}
+
+#[test]
+fn duty_cycle_refuses_transmission_over_budget() {
+    let (chip, clock, mut rfm) = duty_cycle_radio(DutyCycleEnforcement::Refuse);
+    let start = clock.now();
+    let packet = [0x55; 51];
+
+    rfm.send_packet(&packet).unwrap();
+    let remaining = rfm.duty_cycle_tracker().unwrap().remaining(868_850_000);
+    assert_eq!(
+        remaining,
+        Some(Duration::from_millis(3600) - rfm.time_on_air(packet.len()))
+    );
+
+    assert_eq!(
+        rfm.send_packet(&packet),
+        Err(Error::DutyCycleExceeded(Some(
+            start + Duration::from_secs(3600)
+        )))
+    );
+    assert_eq!(chip.transmitted().len(), 1);
+}
+
+#[test]
+fn duty_cycle_delays_transmission_over_budget() {
+    let (chip, clock, mut rfm) = duty_cycle_radio(DutyCycleEnforcement::Delay);
+    let start = clock.now();
+    let packet = [0x55; 51];
+
+    rfm.send_packet(&packet).unwrap();
+    rfm.send_packet(&packet).unwrap();
+    assert_eq!(clock.now(), start + Duration::from_secs(3600));
+    assert_eq!(chip.transmitted().len(), 2);
+}
+
+#[test]
+fn send_packet_rejects_channel_outside_band() {
+    let chip = Sx1276::new();
+    let mut rfm = RFM95::new(
+        chip.spi(),
+        chip.dio0(),
+        None::<NoPin>,
+        None::<NoPin>,
+        DataRate::SF7_BW125,
+        Band::US901,
+        Channel::Ch9,
+    );
+    rfm.reset().unwrap();
+    assert_eq!(
+        rfm.send_packet(b"hello"),
+        Err(Error::InvalidChannel(Channel::Ch9, Band::US901))
+    );
+    assert!(chip.transmitted().is_empty());
+}
+
+fn multi_channel_radio() -> (Sx1276, RFM95<MockSpi, MockDio0, NoPin, NoPin>) {
+    let chip = Sx1276::new();
+    let mut rfm = RFM95::new(
+        chip.spi(),
+        chip.dio0(),
+        None,
+        None,
+        DataRate::SF7_BW125,
+        Band::EU863,
+        Channel::Multi,
+    );
+    rfm.reset().unwrap();
+    (chip, rfm)
+}
+
+/** The chip is tuned to the channel, within one frequency synthesizer step (61 Hz) */
+fn assert_tuned_to(rfm: &mut RFM95<MockSpi, MockDio0, NoPin, NoPin>, channel: Channel) {
+    let expected = channel.frequency_hz(&Band::EU863).unwrap();
+    let tuned = rfm.get_frequency_hz().unwrap();
+    assert!(tuned.abs_diff(expected) < 62, "{} vs {}", tuned, expected);
+}
+
+#[test]
+fn multi_channel_round_robin() {
+    let (_chip, mut rfm) = multi_channel_radio();
+    rfm.set_channel_selector(RoundRobin::new());
+    assert_eq!(rfm.last_tx_channel(), None);
+
+    let mut used = Vec::new();
+    for _ in 0..9 {
+        rfm.send_packet(b"hop").unwrap();
+        let channel = rfm.last_tx_channel().unwrap();
+        assert_tuned_to(&mut rfm, channel);
+        used.push(channel);
+    }
+    assert_eq!(&used[..3], &[Channel::Ch0, Channel::Ch1, Channel::Ch2]);
+    assert_eq!(used[7], Channel::Ch7);
+    assert_eq!(used[8], Channel::Ch0);
+}
+
+#[test]
+fn multi_channel_detects_activity_on_channel_of_next_transmission() {
+    let (_chip, mut rfm) = multi_channel_radio();
+    rfm.set_channel_selector(RoundRobin::new());
+    assert!(!rfm.detect_channel_activity(DataRate::SF7_BW125).unwrap());
+    assert_tuned_to(&mut rfm, Channel::Ch0);
+
+    rfm.send_packet(b"hop").unwrap();
+    assert_eq!(rfm.last_tx_channel(), Some(Channel::Ch0));
+    rfm.send_packet(b"hop").unwrap();
+    assert_eq!(rfm.last_tx_channel(), Some(Channel::Ch1));
+}
+
+#[test]
+fn multi_channel_receives_on_channel_of_last_transmission() {
+    let (chip, mut rfm) = multi_channel_radio();
+    rfm.set_channel_selector(SeededRandom::new(7));
+    rfm.send_packet(b"ping").unwrap();
+    let channel = rfm.last_tx_channel().unwrap();
+
+    chip.receive(IncomingPacket::new(b"pong"));
+    let packet = rfm
+        .receive_packet_on_tx(true, Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(packet.channel, channel);
+    assert_tuned_to(&mut rfm, channel);
+}
+
+/** Picks a channel that does not exist */
+struct OutOfRange;
+
+impl ChannelSelector for OutOfRange {
+    fn select(&mut self, count: usize) -> usize {
+        count
+    }
+}
+
+#[test]
+fn multi_channel_rejects_selection_out_of_range() {
+    let (chip, mut rfm) = multi_channel_radio();
+    rfm.set_channel_selector(OutOfRange);
+    assert_eq!(
+        rfm.send_packet(b"hop"),
+        Err(Error::InvalidChannelSelection(8, 8))
+    );
+    assert!(chip.transmitted().is_empty());
+    assert_eq!(rfm.last_tx_channel(), None);
+}
+
+#[test]
+fn seeded_channel_selection_is_reproducible() {
+    let sequence = |mut selector: Box<dyn ChannelSelector>| -> Vec<usize> {
+        (0..32).map(|_| selector.select(8)).collect()
+    };
+    assert_eq!(
+        sequence(Box::new(SeededRandom::new(42))),
+        sequence(Box::new(SeededRandom::new(42)))
+    );
+    assert_ne!(
+        sequence(Box::new(SeededRandom::new(42))),
+        sequence(Box::new(SeededRandom::new(43)))
+    );
+    assert_eq!(
+        sequence(Box::new(RandomWithoutRepeat::new(1))),
+        sequence(Box::new(RandomWithoutRepeat::new(1)))
+    );
+}
+
+#[test]
+fn random_without_repeat_uses_every_channel_once_per_round() {
+    let mut selector = RandomWithoutRepeat::new(3);
+    let mut previous = None;
+    for _ in 0..10 {
+        let mut round: Vec<usize> = (0..8).map(|_| selector.select(8)).collect();
+        assert_ne!(Some(round[0]), previous);
+        assert!(round.windows(2).all(|pair| pair[0] != pair[1]));
+        previous = round.last().copied();
+        round.sort_unstable();
+        assert_eq!(round, (0..8).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn random_without_repeat_starts_over_when_channels_change() {
+    let mut selector = RandomWithoutRepeat::new(5);
+    for _ in 0..3 {
+        selector.select(8);
+    }
+    // Two channels were added halfway through the round
+    let mut round: Vec<usize> = (0..10).map(|_| selector.select(10)).collect();
+    round.sort_unstable();
+    assert_eq!(round, (0..10).collect::<Vec<_>>());
+}
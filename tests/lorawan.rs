@@ -0,0 +1,1053 @@
+use rfm9x::lorawan::crypto::{
+    self, data_mic, decrypt_frm_payload, decrypt_join_accept, encrypt_frm_payload,
+    encrypt_join_accept, join_mic, verify_data_mic, verify_join_mic, Key, SessionKeys,
+};
+use rfm9x::lorawan::frame::{
+    DataFrame, Direction, FCtrl, Frame, JoinAccept, JoinRequest, MType, PhyPayload,
+};
+use rfm9x::lorawan::mac::{DownlinkCommand, UplinkCommand};
+use rfm9x::lorawan::{
+    ChannelPlan, Device, DeviceState, FileSessionStore, OtaaCredentials, RxWindow, Session,
+    SessionStore, UplinkChannel,
+};
+use rfm9x::registers::Register;
+use rfm9x::testing::{IncomingPacket, ManualClock, MockDio0, MockSpi, Sx1276};
+use rfm9x::{Band, Channel, Clock, DataRate, Error, NoPin, RoundRobin, RFM95};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn hex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn key(text: &str) -> Key {
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&hex(text));
+    key
+}
+
+const NWK_S_KEY: &str = "44024241ed4ce9a68c6a8bc055233fd3";
+const APP_S_KEY: &str = "ec925802ae430ca77fd3dd73cb2cc588";
+const APP_KEY: &str = "B6B53F4A168A7A88BDF7EA135CE9CFCA";
+const JOIN_ACCEPT: &str = "204DD85AE608B87FC4889970B7D2042C9E72959B0057AED6094B16003DF12DE145";
+
+#[test]
+fn parse_unconfirmed_data_up() {
+    let bytes = hex("40F17DBE4900020001954378762B11FF0D");
+    let phy = PhyPayload::parse(&bytes).unwrap();
+    assert_eq!(phy.mtype(), MType::UnconfirmedDataUp);
+    assert_eq!(phy.mic, [0x2B, 0x11, 0xFF, 0x0D]);
+    assert_eq!(
+        phy.frame,
+        Frame::Data(DataFrame {
+            direction: Direction::Uplink,
+            confirmed: false,
+            dev_addr: 0x49BE7DF1,
+            fctrl: FCtrl::default(),
+            fcnt: 2,
+            fopts: Vec::new(),
+            fport: Some(1),
+            frm_payload: hex("95437876"),
+        })
+    );
+    assert_eq!(phy.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn parse_join_request() {
+    let bytes = hex("00DC0000D07ED5B3701E6FEDF57CEEAF00C886030AF2C9");
+    let phy = PhyPayload::parse(&bytes).unwrap();
+    assert_eq!(
+        phy.frame,
+        Frame::JoinRequest(JoinRequest {
+            join_eui: 0x70B3D57ED00000DC,
+            dev_eui: 0x00AFEE7CF5ED6F1E,
+            dev_nonce: 0x86C8,
+        })
+    );
+    assert_eq!(phy.mic, [0x03, 0x0A, 0xF2, 0xC9]);
+    assert_eq!(phy.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn join_accept_round_trip() {
+    let accept = JoinAccept {
+        join_nonce: 0xABCDEF,
+        net_id: 0x000013,
+        dev_addr: 0x26011BDA,
+        rx1_dr_offset: 2,
+        rx2_data_rate: 3,
+        rx_delay: 1,
+        cf_list: None,
+    };
+    let mut phy = PhyPayload::new(Frame::JoinAccept(accept));
+    phy.mic = [1, 2, 3, 4];
+    let bytes = phy.to_bytes().unwrap();
+    assert_eq!(bytes, hex("20EFCDAB130000DA1B0126230101020304"));
+    assert_eq!(PhyPayload::parse(&bytes).unwrap(), phy);
+
+    // With a CFList of additional channels
+    let mut cf_list = [0u8; 16];
+    cf_list[0..3].copy_from_slice(&[0x18, 0x4F, 0x84]);
+    phy.frame = Frame::JoinAccept(JoinAccept {
+        cf_list: Some(cf_list),
+        ..accept
+    });
+    let bytes = phy.to_bytes().unwrap();
+    assert_eq!(bytes.len(), 33);
+    assert_eq!(PhyPayload::parse(&bytes).unwrap(), phy);
+}
+
+#[test]
+fn data_frame_with_fctrl_and_fopts() {
+    let frame = DataFrame {
+        direction: Direction::Downlink,
+        confirmed: true,
+        dev_addr: 0x01020304,
+        fctrl: FCtrl {
+            adr: true,
+            adr_ack_req: false,
+            ack: true,
+            pending: true,
+        },
+        fcnt: 0x1234,
+        fopts: vec![0x02, 0x07, 0x01],
+        fport: None,
+        frm_payload: Vec::new(),
+    };
+    let phy = PhyPayload::new(Frame::Data(frame));
+    let bytes = phy.to_bytes().unwrap();
+    assert_eq!(bytes, hex("A004030201B3341202070100000000"));
+    let parsed = PhyPayload::parse(&bytes).unwrap();
+    assert_eq!(parsed.mtype(), MType::ConfirmedDataDown);
+    assert_eq!(parsed, phy);
+}
+
+#[test]
+fn invalid_frames_are_rejected() {
+    assert!(matches!(
+        PhyPayload::parse(&hex("400102")),
+        Err(Error::InvalidFrame(_))
+    ));
+    // Truncated Join-Request
+    assert!(PhyPayload::parse(&hex("00DC0000D07ED5B3701E6FEDF57CEEAF00C886030AF2")).is_err());
+    // FOptsLen beyond the end of the frame
+    assert!(PhyPayload::parse(&hex("40F17DBE490F020001020304")).is_err());
+    // Major version other than R1
+    assert!(PhyPayload::parse(&hex("41F17DBE4900020001954378762B11FF0D")).is_err());
+
+    let frame = |fopts: Vec<u8>, fport| {
+        PhyPayload::new(Frame::Data(DataFrame {
+            direction: Direction::Uplink,
+            confirmed: false,
+            dev_addr: 0,
+            fctrl: FCtrl::default(),
+            fcnt: 0,
+            fopts,
+            fport,
+            frm_payload: Vec::new(),
+        }))
+    };
+    assert!(frame(vec![0; 16], Some(1)).to_bytes().is_err());
+    assert!(frame(vec![0x02], Some(0)).to_bytes().is_err());
+    assert!(frame(vec![0x02], Some(1)).to_bytes().is_ok());
+}
+
+#[test]
+fn data_frame_mic_and_payload_decryption() {
+    let phy = PhyPayload::parse(&hex("40F17DBE4900020001954378762B11FF0D")).unwrap();
+    assert_eq!(
+        data_mic(&key(NWK_S_KEY), &phy, 2),
+        Ok([0x2B, 0x11, 0xFF, 0x0D])
+    );
+    assert_eq!(verify_data_mic(&key(NWK_S_KEY), &phy, 2), Ok(()));
+    // The upper 16 bits of the frame counter are part of the MIC
+    assert_eq!(
+        verify_data_mic(&key(NWK_S_KEY), &phy, 0x10002),
+        Err(Error::InvalidMic)
+    );
+    assert_eq!(
+        verify_data_mic(&key(APP_S_KEY), &phy, 2),
+        Err(Error::InvalidMic)
+    );
+
+    let data = match &phy.frame {
+        Frame::Data(data) => data,
+        _ => unreachable!(),
+    };
+    let payload = decrypt_frm_payload(
+        &key(APP_S_KEY),
+        Direction::Uplink,
+        data.dev_addr,
+        2,
+        &data.frm_payload,
+    );
+    assert_eq!(payload, b"test");
+}
+
+#[test]
+fn frm_payload_encryption_spans_several_blocks() {
+    let payload: Vec<u8> = (0..40).collect();
+    let key = key(APP_S_KEY);
+    let encrypted = encrypt_frm_payload(&key, Direction::Downlink, 0x26011BDA, 7, &payload);
+    assert_eq!(encrypted.len(), 40);
+    assert_ne!(encrypted, payload);
+    // Each block uses its own keystream
+    assert_ne!(encrypted[..16], encrypted[16..32]);
+    assert_eq!(
+        decrypt_frm_payload(&key, Direction::Downlink, 0x26011BDA, 7, &encrypted),
+        payload
+    );
+    // The direction is part of the keystream
+    assert_ne!(
+        decrypt_frm_payload(&key, Direction::Uplink, 0x26011BDA, 7, &encrypted),
+        payload
+    );
+}
+
+#[test]
+fn join_request_mic() {
+    let phy = PhyPayload::parse(&hex("00DC0000D07ED5B3701E6FEDF57CEEAF00C886030AF2C9")).unwrap();
+    assert_eq!(join_mic(&key(APP_KEY), &phy), Ok([0x03, 0x0A, 0xF2, 0xC9]));
+    assert_eq!(verify_join_mic(&key(APP_KEY), &phy), Ok(()));
+    assert_eq!(
+        crypto::data_mic(&key(APP_KEY), &phy, 0),
+        Err(Error::InvalidFrame("not a data frame"))
+    );
+}
+
+#[test]
+fn join_accept_decryption() {
+    let encrypted = hex(JOIN_ACCEPT);
+    let decrypted = decrypt_join_accept(&key(APP_KEY), &encrypted).unwrap();
+    let phy = PhyPayload::parse(&decrypted).unwrap();
+    assert_eq!(verify_join_mic(&key(APP_KEY), &phy), Ok(()));
+
+    let mut cf_list = [0u8; 16];
+    cf_list[..15].copy_from_slice(&hex("184F84E85684B85E84886684586E84"));
+    assert_eq!(
+        phy.frame,
+        Frame::JoinAccept(JoinAccept {
+            join_nonce: 0xE5063A,
+            net_id: 0x000013,
+            dev_addr: 0x26012E43,
+            rx1_dr_offset: 0,
+            rx2_data_rate: 3,
+            rx_delay: 1,
+            cf_list: Some(cf_list),
+        })
+    );
+
+    assert_eq!(
+        encrypt_join_accept(&key(APP_KEY), &decrypted),
+        Ok(encrypted)
+    );
+    assert!(decrypt_join_accept(&key(APP_KEY), &hex("20010203")).is_err());
+}
+
+#[test]
+fn session_key_derivation() {
+    let keys = SessionKeys::derive(&key(APP_KEY), 0xE5063A, 0x000013, 0x86C8);
+    assert_eq!(keys.nwk_s_key, key("d4d629103a5c5a5a46852b0005d6959f"));
+    assert_eq!(keys.app_s_key, key("70decd14247e737e9b571441ab090632"));
+}
+
+const DEV_ADDR: u32 = 0x26011BDA;
+
+type MockDevice = Device<MockSpi, MockDio0, NoPin, NoPin>;
+
+fn device(band: Band, data_rate: u8) -> (Sx1276, ManualClock, MockDevice) {
+    let chip = Sx1276::new();
+    let mut rfm = RFM95::new(
+        chip.spi(),
+        chip.dio0(),
+        None,
+        None,
+        DataRate::SF7_BW125,
+        band,
+        Channel::Ch0,
+    );
+    rfm.reset().unwrap();
+    let clock = ManualClock::new();
+    let device = Device::with_clock(rfm, data_rate, clock.clone()).unwrap();
+    (chip, clock, device)
+}
+
+fn session_keys() -> SessionKeys {
+    SessionKeys {
+        nwk_s_key: key(NWK_S_KEY),
+        app_s_key: key(APP_S_KEY),
+    }
+}
+
+/** Downlink as a gateway transmits it, with inverted IQ */
+fn downlink(dev_addr: u32, fcnt: u32, confirmed: bool, fport: u8, data: &[u8]) -> IncomingPacket {
+    let keys = session_keys();
+    let mut phy = PhyPayload::new(Frame::Data(DataFrame {
+        direction: Direction::Downlink,
+        confirmed,
+        dev_addr,
+        fctrl: FCtrl {
+            ack: true,
+            ..FCtrl::default()
+        },
+        fcnt: fcnt as u16,
+        fopts: Vec::new(),
+        fport: Some(fport),
+        frm_payload: encrypt_frm_payload(
+            &keys.app_s_key,
+            Direction::Downlink,
+            dev_addr,
+            fcnt,
+            data,
+        ),
+    }));
+    phy.mic = data_mic(&keys.nwk_s_key, &phy, fcnt).unwrap();
+    let mut packet = IncomingPacket::new(&phy.to_bytes().unwrap());
+    packet.inverted_iq = true;
+    packet
+}
+
+/** Downlink carrying MAC commands in FOpts, or as the FRMPayload of FPort 0 */
+fn mac_downlink(fcnt: u32, commands: &[u8], in_fopts: bool) -> IncomingPacket {
+    let keys = session_keys();
+    let (fopts, fport, frm_payload) = if in_fopts {
+        (commands.to_vec(), None, Vec::new())
+    } else {
+        let encrypted = encrypt_frm_payload(
+            &keys.nwk_s_key,
+            Direction::Downlink,
+            DEV_ADDR,
+            fcnt,
+            commands,
+        );
+        (Vec::new(), Some(0), encrypted)
+    };
+    let mut phy = PhyPayload::new(Frame::Data(DataFrame {
+        direction: Direction::Downlink,
+        confirmed: false,
+        dev_addr: DEV_ADDR,
+        fctrl: FCtrl::default(),
+        fcnt: fcnt as u16,
+        fopts,
+        fport,
+        frm_payload,
+    }));
+    phy.mic = data_mic(&keys.nwk_s_key, &phy, fcnt).unwrap();
+    let mut packet = IncomingPacket::new(&phy.to_bytes().unwrap());
+    packet.inverted_iq = true;
+    packet
+}
+
+/** The uplink transmitted as the nth packet, checked against its MIC */
+fn uplink(chip: &Sx1276, n: usize, fcnt: u32) -> DataFrame {
+    let phy = PhyPayload::parse(&chip.transmitted()[n]).unwrap();
+    assert_eq!(verify_data_mic(&key(NWK_S_KEY), &phy, fcnt), Ok(()));
+    match phy.frame {
+        Frame::Data(data) => data,
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+#[test]
+fn uplink_is_followed_by_both_receive_windows() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+    let start = clock.now();
+
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+
+    let data = uplink(&chip, 0, 0);
+    assert_eq!(data.dev_addr, DEV_ADDR);
+    assert_eq!(data.fcnt, 0);
+    assert_eq!(data.fport, Some(10));
+    let payload = decrypt_frm_payload(
+        &key(APP_S_KEY),
+        Direction::Uplink,
+        DEV_ADDR,
+        0,
+        &data.frm_payload,
+    );
+    assert_eq!(payload, b"hello");
+    assert_eq!(device.session().unwrap().fcnt_up, 1);
+
+    // RX2 opened two seconds after the uplink, on 869.525 MHz
+    let elapsed = clock.now() - start;
+    assert!(elapsed > Duration::from_millis(1900) && elapsed < Duration::from_secs(2));
+    let frequency = device.radio().get_frequency_hz().unwrap() as i64;
+    assert!((frequency - 869_525_000).abs() < 62);
+    // IQ inversion is only enabled for the receive windows
+    assert_eq!(
+        chip.register(Register::InvertIQ as u8),
+        Register::InvertIQ.reset_value()
+    );
+    assert_eq!(chip.register(Register::InvertIQ2 as u8), 0x1D);
+}
+
+#[test]
+fn confirmed_downlink_in_rx1_is_acknowledged() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+    let start = clock.now();
+
+    chip.receive(downlink(DEV_ADDR, 0, true, 5, b"world"));
+    let downlink = device.send_uplink(10, b"hello", true).unwrap().unwrap();
+    assert_eq!(downlink.port, Some(5));
+    assert_eq!(downlink.data, b"world");
+    assert_eq!(downlink.window, RxWindow::Rx1);
+    assert!(downlink.ack);
+    assert!(downlink.confirmed);
+    assert_eq!(downlink.fcnt, 0);
+    assert_eq!(device.session().unwrap().fcnt_down, 1);
+    // RX2 was not opened
+    assert!(clock.now() - start < Duration::from_secs(1));
+
+    assert!(uplink(&chip, 0, 0).confirmed);
+    assert!(!uplink(&chip, 0, 0).fctrl.ack);
+    device.send_uplink(10, b"hello", false).unwrap();
+    assert!(uplink(&chip, 1, 1).fctrl.ack);
+    device.send_uplink(10, b"hello", false).unwrap();
+    assert!(!uplink(&chip, 2, 2).fctrl.ack);
+}
+
+#[test]
+fn downlink_in_rx2() {
+    let (chip, _clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    // RX1 hears a frame for another device
+    chip.receive(downlink(0x26011BDB, 7, false, 5, b"other"));
+    chip.receive(downlink(DEV_ADDR, 7, false, 5, b"world"));
+    let downlink = device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(downlink.window, RxWindow::Rx2);
+    assert_eq!(downlink.data, b"world");
+    assert_eq!(downlink.fcnt, 7);
+    assert_eq!(device.session().unwrap().fcnt_down, 8);
+}
+
+#[test]
+fn replayed_and_forged_downlinks_are_ignored() {
+    let (chip, _clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    chip.receive(downlink(DEV_ADDR, 3, false, 5, b"world"));
+    assert!(device.send_uplink(10, b"hello", false).unwrap().is_some());
+    chip.receive(downlink(DEV_ADDR, 3, false, 5, b"world"));
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+
+    let mut forged = downlink(DEV_ADDR, 4, false, 5, b"world");
+    let last = forged.payload.len() - 1;
+    forged.payload[last] ^= 0x01;
+    chip.receive(forged);
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+
+    // Not inverted, so the device does not hear it
+    let mut uplink = downlink(DEV_ADDR, 4, false, 5, b"world");
+    uplink.inverted_iq = false;
+    chip.receive(uplink);
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+    assert_eq!(device.session().unwrap().fcnt_down, 4);
+}
+
+#[test]
+fn otaa_join() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    let credentials = OtaaCredentials {
+        dev_eui: 0x0004A30B001C0530,
+        join_eui: 0x70B3D57ED0000000,
+        app_key: key(APP_KEY),
+    };
+    device.set_dev_nonce(0x86C8);
+    let start = clock.now();
+
+    let mut accept = IncomingPacket::new(&hex(JOIN_ACCEPT));
+    accept.inverted_iq = true;
+    chip.receive(accept);
+    device.join(&credentials).unwrap();
+
+    let request = PhyPayload::parse(&chip.transmitted()[0]).unwrap();
+    assert_eq!(
+        request.frame,
+        Frame::JoinRequest(JoinRequest {
+            join_eui: 0x70B3D57ED0000000,
+            dev_eui: 0x0004A30B001C0530,
+            dev_nonce: 0x86C8,
+        })
+    );
+    assert_eq!(verify_join_mic(&key(APP_KEY), &request), Ok(()));
+    assert_eq!(device.dev_nonce(), 0x86C9);
+    // The Join-Accept arrived in RX1, five seconds after the Join-Request
+    let elapsed = clock.now() - start;
+    assert!(elapsed > Duration::from_millis(4900) && elapsed < Duration::from_secs(5));
+
+    let session = device.session().unwrap();
+    assert_eq!(session.dev_addr, 0x26012E43);
+    assert_eq!(
+        session.keys,
+        SessionKeys::derive(&key(APP_KEY), 0xE5063A, 0x000013, 0x86C8)
+    );
+    assert_eq!(session.fcnt_up, 0);
+    assert_eq!(session.rx2_data_rate, 3);
+    assert_eq!(session.rx_delay, 1);
+
+    // The CFList adds channels 3 - 7
+    let plan = device.channel_plan();
+    assert_eq!(plan.channel(3).unwrap().frequency, 867_100_000);
+    assert_eq!(plan.channel(7).unwrap().frequency, 867_900_000);
+    assert_eq!(plan.enabled_channels(5), (0..8).collect::<Vec<u8>>());
+}
+
+#[test]
+fn join_without_accept_times_out() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    let credentials = OtaaCredentials {
+        dev_eui: 1,
+        join_eui: 2,
+        app_key: key(APP_KEY),
+    };
+    let start = clock.now();
+    assert_eq!(device.join(&credentials), Err(Error::ReceiveTimedOut));
+    assert!(clock.now() - start > Duration::from_millis(5900));
+    assert_eq!(chip.transmitted().len(), 1);
+    assert!(device.session().is_none());
+    // The DevNonce is never reused
+    assert_eq!(device.dev_nonce(), 1);
+}
+
+#[test]
+fn send_uplink_checks_activation_port_and_length() {
+    let (_chip, _clock, mut device) = device(Band::EU863, 0);
+    assert_eq!(
+        device.send_uplink(1, b"data", false),
+        Err(Error::NotActivated)
+    );
+    device.activate_abp(DEV_ADDR, session_keys());
+    assert_eq!(
+        device.send_uplink(0, b"data", false),
+        Err(Error::InvalidPort(0))
+    );
+    assert_eq!(
+        device.send_uplink(224, b"data", false),
+        Err(Error::InvalidPort(224))
+    );
+    // DR0 allows a MACPayload of 59 bytes
+    assert_eq!(
+        device.send_uplink(1, &[0; 52], false),
+        Err(Error::PayloadTooLong(52, 51))
+    );
+    assert_eq!(device.session().unwrap().fcnt_up, 0);
+    assert_eq!(
+        device.set_data_rate(7),
+        Err(Error::InvalidDataRate(7, Band::EU863))
+    );
+}
+
+#[test]
+fn fixed_channel_plan_uses_downlink_channels() {
+    let (chip, _clock, mut device) = device(Band::US901, 3);
+    let mask = Band::US901.parameters().sub_band_mask(1).unwrap();
+    device.channel_plan_mut().set_mask(mask);
+    device.radio().set_channel_selector(RoundRobin::new());
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    chip.receive(downlink(DEV_ADDR, 0, false, 5, b"world"));
+    chip.receive(downlink(DEV_ADDR, 1, false, 5, b"world"));
+    for channel in 0..2 {
+        let downlink = device.send_uplink(10, b"hello", false).unwrap().unwrap();
+        assert_eq!(downlink.window, RxWindow::Rx1);
+        // Uplink channel 8 + n is answered on downlink channel n at DR13
+        let frequency = device.radio().get_frequency_hz().unwrap() as i64;
+        assert!((frequency - (923_300_000 + 600_000 * channel)).abs() < 62);
+        assert_eq!(
+            device.radio().last_tx_channel(),
+            Some(Channel::Frequency(903_900_000 + 200_000 * channel as u32))
+        );
+    }
+}
+
+#[test]
+fn channel_plan() {
+    let mut plan = ChannelPlan::new(Band::US901);
+    assert_eq!(plan.capacity(), 72);
+    plan.set_mask(Band::US901.parameters().sub_band_mask(1).unwrap());
+    assert_eq!(plan.enabled_channels(3), (8..16).collect::<Vec<u8>>());
+    assert_eq!(plan.enabled_channels(4), vec![65]);
+    let channel = UplinkChannel {
+        frequency: 903_000_000,
+        min_data_rate: 0,
+        max_data_rate: 3,
+    };
+    assert!(plan.set_channel(20, Some(channel)).is_err());
+
+    let mut plan = ChannelPlan::new(Band::EU863);
+    assert_eq!(plan.capacity(), 16);
+    assert_eq!(plan.enabled_channels(5), vec![0, 1, 2]);
+    assert_eq!(plan.enabled_channels(6), Vec::<u8>::new());
+    let channel = UplinkChannel {
+        frequency: 867_100_000,
+        min_data_rate: 0,
+        max_data_rate: 5,
+    };
+    plan.set_channel(3, Some(channel)).unwrap();
+    assert_eq!(plan.enabled_channels(5), vec![0, 1, 2, 3]);
+    plan.set_channel(3, None).unwrap();
+    assert_eq!(plan.mask(), 0b111);
+    assert_eq!(
+        plan.set_channel(0, Some(channel)),
+        Err(Error::InvalidChannel(Channel::Uplink(0), Band::EU863))
+    );
+    assert!(plan.set_channel(16, Some(channel)).is_err());
+}
+
+#[test]
+fn mac_command_encoding() {
+    let commands = DownlinkCommand::parse_all(&hex(
+        "033203000104030510D2AD84060703184F845008050D1000000080",
+    ));
+    assert_eq!(
+        commands,
+        vec![
+            DownlinkCommand::LinkAdrReq {
+                data_rate: 3,
+                tx_power: 2,
+                channel_mask: 0x0003,
+                channel_mask_control: 0,
+                nb_trans: 1,
+            },
+            DownlinkCommand::DutyCycleReq { max_duty_cycle: 3 },
+            DownlinkCommand::RxParamSetupReq {
+                rx1_dr_offset: 1,
+                rx2_data_rate: 0,
+                frequency: 869_525_000,
+            },
+            DownlinkCommand::DevStatusReq,
+            DownlinkCommand::NewChannelReq {
+                index: 3,
+                frequency: 867_100_000,
+                min_data_rate: 0,
+                max_data_rate: 5,
+            },
+            DownlinkCommand::RxTimingSetupReq { delay: 5 },
+            DownlinkCommand::DeviceTimeAns {
+                gps_time: Duration::from_millis(16_500),
+            },
+        ]
+    );
+    // Decoding stops at an unknown or truncated command
+    assert_eq!(
+        DownlinkCommand::parse_all(&hex("0604")),
+        vec![DownlinkCommand::DevStatusReq]
+    );
+    assert_eq!(
+        DownlinkCommand::parse_all(&hex("06FF06")),
+        vec![DownlinkCommand::DevStatusReq]
+    );
+
+    let encode = |command: UplinkCommand| command.to_bytes();
+    assert_eq!(
+        encode(UplinkCommand::LinkAdrAns {
+            power_ack: true,
+            data_rate_ack: false,
+            channel_mask_ack: true,
+        }),
+        hex("0305")
+    );
+    assert_eq!(
+        encode(UplinkCommand::RxParamSetupAns {
+            rx1_dr_offset_ack: true,
+            rx2_data_rate_ack: true,
+            channel_ack: true,
+        }),
+        hex("0507")
+    );
+    assert_eq!(
+        encode(UplinkCommand::DevStatusAns {
+            battery: 200,
+            margin: -5,
+        }),
+        hex("06C83B")
+    );
+    assert_eq!(
+        encode(UplinkCommand::DevStatusAns {
+            battery: 0,
+            margin: 40,
+        }),
+        hex("06001F")
+    );
+    assert_eq!(
+        encode(UplinkCommand::NewChannelAns {
+            data_rate_range_ok: true,
+            channel_frequency_ok: false,
+        }),
+        hex("0702")
+    );
+    assert_eq!(encode(UplinkCommand::DeviceTimeReq), hex("0D"));
+}
+
+#[test]
+fn link_adr_req_is_applied_and_answered() {
+    let (chip, _clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+    device.set_adr(true);
+
+    // DR3, TXPower 2 (12 dBm), channels 0 and 1, NbTrans 2
+    chip.receive(mac_downlink(0, &hex("0332030002"), true));
+    let downlink = device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert!(uplink(&chip, 0, 0).fctrl.adr);
+    assert_eq!(downlink.port, None);
+    assert_eq!(downlink.commands.len(), 1);
+    assert_eq!(device.data_rate(), 3);
+    assert_eq!(device.channel_plan().mask(), 0b11);
+    let session = device.session().unwrap();
+    assert_eq!(session.tx_power, Some(2));
+    assert_eq!(session.nb_trans, 2);
+
+    // The answer goes along with the next uplink, which is transmitted twice
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+    assert_eq!(chip.transmitted().len(), 3);
+    assert_eq!(uplink(&chip, 1, 1).fopts, hex("0307"));
+    assert_eq!(chip.transmitted()[2], chip.transmitted()[1]);
+    assert_eq!(chip.register(Register::PAConfig as u8), 0xFA);
+    assert!(device.uplink_commands().is_empty());
+
+    // Channel 8 is not defined and TXPower 8 does not exist in EU863: the request is rejected as a whole
+    chip.receive(mac_downlink(1, &hex("0318000101"), true));
+    device.send_uplink(10, b"hello", true).unwrap().unwrap();
+    assert_eq!(
+        device.uplink_commands(),
+        &[UplinkCommand::LinkAdrAns {
+            power_ack: false,
+            data_rate_ack: true,
+            channel_mask_ack: false,
+        }]
+    );
+    assert_eq!(device.data_rate(), 3);
+    assert_eq!(device.channel_plan().mask(), 0b11);
+}
+
+#[test]
+fn link_adr_req_block_on_fixed_channel_plan() {
+    let (chip, _clock, mut device) = device(Band::US901, 0);
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    // All channels off, then channels 8 - 15 on at DR3; each request of the block is answered
+    chip.receive(mac_downlink(0, &hex("030F000070033300FF00"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    let ack = UplinkCommand::LinkAdrAns {
+        power_ack: true,
+        data_rate_ack: true,
+        channel_mask_ack: true,
+    };
+    assert_eq!(device.uplink_commands(), &[ack, ack]);
+    assert_eq!(device.channel_plan().mask(), 0xFF00);
+    assert_eq!(device.data_rate(), 3);
+    assert_eq!(device.session().unwrap().tx_power, Some(3));
+
+    // NewChannelReq is ignored by fixed channel plans
+    chip.receive(mac_downlink(1, &hex("0703184F8450"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert!(device.uplink_commands().is_empty());
+}
+
+#[test]
+fn rx_param_and_timing_answers_are_repeated_until_a_downlink() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    // RX1 offset 1, RX2 at DR0 on 869.525 MHz, RX1 after 3 s
+    chip.receive(mac_downlink(0, &hex("0510D2AD840803"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    let session = device.session().unwrap();
+    assert_eq!(session.rx1_dr_offset, 1);
+    assert_eq!(session.rx2_data_rate, 0);
+    assert_eq!(session.rx2_frequency, 869_525_000);
+    assert_eq!(session.rx_delay, 3);
+
+    let start = clock.now();
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+    assert_eq!(uplink(&chip, 1, 1).fopts, hex("050708"));
+    let elapsed = clock.now() - start;
+    assert!(elapsed > Duration::from_millis(3900) && elapsed < Duration::from_secs(4));
+
+    chip.receive(downlink(DEV_ADDR, 1, false, 5, b"world"));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(uplink(&chip, 2, 2).fopts, hex("050708"));
+    device.send_uplink(10, b"hello", false).unwrap();
+    assert!(uplink(&chip, 3, 3).fopts.is_empty());
+
+    // Invalid data rate and frequency
+    chip.receive(mac_downlink(2, &hex("050F000000"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(
+        device.uplink_commands(),
+        &[UplinkCommand::RxParamSetupAns {
+            rx1_dr_offset_ack: true,
+            rx2_data_rate_ack: false,
+            channel_ack: false,
+        }]
+    );
+    assert_eq!(device.session().unwrap().rx2_frequency, 869_525_000);
+}
+
+#[test]
+fn dev_status_req_reports_the_battery_level() {
+    let (chip, _clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+    device.set_battery_level(|| 42);
+
+    // MAC commands on FPort 0, received at an SNR of -5 dB
+    let mut packet = mac_downlink(0, &hex("06"), false);
+    packet.snr = -20;
+    chip.receive(packet);
+    let downlink = device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(downlink.port, Some(0));
+    assert!(downlink.data.is_empty());
+    assert_eq!(downlink.commands, vec![DownlinkCommand::DevStatusReq]);
+
+    device.send_uplink(10, b"hello", false).unwrap();
+    assert_eq!(uplink(&chip, 1, 1).fopts, hex("062A3B"));
+}
+
+#[test]
+fn new_channel_req() {
+    let (chip, _clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    // Channel 3 on 867.1 MHz for DR0 - DR5; channel 1 is a default channel
+    chip.receive(mac_downlink(0, &hex("0703184F84500701184F8450"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(
+        device.channel_plan().channel(3),
+        Some(UplinkChannel {
+            frequency: 867_100_000,
+            min_data_rate: 0,
+            max_data_rate: 5,
+        })
+    );
+    device.send_uplink(10, b"hello", false).unwrap();
+    assert_eq!(uplink(&chip, 1, 1).fopts, hex("07030702"));
+
+    // Removed again; an invalid data rate range is rejected
+    chip.receive(mac_downlink(1, &hex("0703000000500704184F8405"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(device.channel_plan().channel(3), None);
+    assert_eq!(device.channel_plan().channel(4), None);
+    assert_eq!(
+        device.uplink_commands()[1],
+        UplinkCommand::NewChannelAns {
+            data_rate_range_ok: false,
+            channel_frequency_ok: true,
+        }
+    );
+}
+
+#[test]
+fn duty_cycle_req_limits_uplinks() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+
+    // 1 / 128
+    chip.receive(mac_downlink(0, &hex("0407"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(device.session().unwrap().max_duty_cycle, 7);
+    let start = clock.now();
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+    assert_eq!(uplink(&chip, 1, 1).fopts, hex("04"));
+
+    let next = match device.send_uplink(10, b"hello", false) {
+        Err(Error::DutyCycleExceeded(Some(next))) => next,
+        result => panic!("unexpected result {:?}", result),
+    };
+    assert_eq!(device.session().unwrap().fcnt_up, 2);
+    // 127 times the airtime of the uplink after it
+    let airtime = device.radio().time_on_air(chip.transmitted()[1].len());
+    assert!(next >= start + airtime * 127 && next < start + airtime * 128);
+    clock.advance(next - clock.now());
+    assert_eq!(device.send_uplink(10, b"hello", false), Ok(None));
+}
+
+#[test]
+fn device_time() {
+    let (chip, clock, mut device) = device(Band::EU863, 5);
+    device.activate_abp(DEV_ADDR, session_keys());
+    assert_eq!(device.gps_time(), None);
+
+    device.request_device_time();
+    device.request_device_time();
+    // 1318000000.5 s after the GPS epoch
+    chip.receive(mac_downlink(0, &hex("0D80158F4E80"), true));
+    device.send_uplink(10, b"hello", false).unwrap().unwrap();
+    assert_eq!(uplink(&chip, 0, 0).fopts, hex("0D"));
+
+    // RX1 opened 980 ms after the uplink
+    let gps_time = Duration::from_millis(1_318_000_000_500);
+    assert_eq!(
+        device.gps_time(),
+        Some(gps_time + Duration::from_millis(980))
+    );
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(
+        device.gps_time(),
+        Some(gps_time + Duration::from_millis(10_980))
+    );
+}
+
+/** Path of a session file in the temporary directory that no other test uses, removed beforehand */
+fn session_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rfm9x-{}-{}.session", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn device_state() -> DeviceState {
+    let mut channels = ChannelPlan::new(Band::EU863);
+    let channel = UplinkChannel {
+        frequency: 867_500_000,
+        min_data_rate: 0,
+        max_data_rate: 5,
+    };
+    channels.set_channel(5, Some(channel)).unwrap();
+    channels.set_mask(0b10_0011);
+    DeviceState {
+        dev_nonce: 0x1234,
+        data_rate: 3,
+        channels,
+        session: Some(Session {
+            fcnt_up: 70_000,
+            fcnt_down: 12,
+            rx_delay: 5,
+            tx_power: Some(2),
+            nb_trans: 2,
+            max_duty_cycle: 4,
+            ..Session::new(Band::EU863, DEV_ADDR, session_keys())
+        }),
+    }
+}
+
+#[test]
+fn device_state_encoding() {
+    let state = device_state();
+    let bytes = state.to_bytes();
+    assert_eq!(DeviceState::parse(&bytes), Ok(state.clone()));
+
+    let mut channels = ChannelPlan::new(Band::US901);
+    channels.set_mask(Band::US901.parameters().sub_band_mask(2).unwrap());
+    let inactive = DeviceState {
+        dev_nonce: 0,
+        data_rate: 0,
+        channels,
+        session: None,
+    };
+    assert_eq!(DeviceState::parse(&inactive.to_bytes()), Ok(inactive));
+
+    assert_eq!(
+        DeviceState::parse(&bytes[..bytes.len() - 1]),
+        Err(Error::InvalidSessionState("truncated"))
+    );
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(DeviceState::parse(&trailing).is_err());
+    let mut version = bytes;
+    version[0] = 2;
+    assert_eq!(
+        DeviceState::parse(&version),
+        Err(Error::InvalidSessionState("unsupported format version"))
+    );
+}
+
+#[test]
+fn file_session_store() {
+    let path = session_path("store");
+    let mut store = FileSessionStore::new(&path);
+    assert_eq!(store.load(), Ok(None));
+
+    let mut state = device_state();
+    store.save(&state).unwrap();
+    assert_eq!(store.load(), Ok(Some(state.clone())));
+    // Saving again replaces the file, leaving no temporary file behind
+    state.dev_nonce += 1;
+    store.save(&state).unwrap();
+    assert_eq!(FileSessionStore::new(&path).load(), Ok(Some(state)));
+    assert!(!PathBuf::from(format!("{}.tmp", path.display())).exists());
+
+    std::fs::write(&path, b"garbage").unwrap();
+    assert!(matches!(store.load(), Err(Error::InvalidSessionState(_))));
+    std::fs::remove_file(&path).unwrap();
+
+    let mut store = FileSessionStore::new(std::env::temp_dir().join("rfm9x-missing/session"));
+    let error = store.save(&device_state()).unwrap_err();
+    assert!(matches!(error, Error::Io(std::io::ErrorKind::NotFound, _)));
+    assert!(std::error::Error::source(&error).is_some());
+}
+
+#[test]
+fn session_is_restored_after_restart() {
+    let path = session_path("restart");
+    let (chip, _clock, mut before) = device(Band::EU863, 5);
+    assert_eq!(
+        before.set_session_store(FileSessionStore::new(&path)),
+        Ok(false)
+    );
+    before.activate_abp(DEV_ADDR, session_keys());
+    // NewChannelReq for channel 3
+    chip.receive(mac_downlink(0, &hex("0703184F8450"), true));
+    before.send_uplink(10, b"hello", false).unwrap().unwrap();
+    before.send_uplink(10, b"hello", false).unwrap();
+    let state = before.state();
+    assert_eq!(FileSessionStore::new(&path).load(), Ok(Some(state.clone())));
+    let session = state.session.as_ref().unwrap();
+    assert_eq!((session.fcnt_up, session.fcnt_down), (2, 1));
+
+    // The device continues the session with the next frame counter instead of rejoining
+    let (chip, _clock, mut after) = device(Band::EU863, 5);
+    assert_eq!(
+        after.set_session_store(FileSessionStore::new(&path)),
+        Ok(true)
+    );
+    assert_eq!(after.state(), state);
+    assert!(after.channel_plan().channel(3).is_some());
+    chip.receive(downlink(DEV_ADDR, 0, false, 5, b"replay"));
+    assert_eq!(after.send_uplink(10, b"hello", false), Ok(None));
+    assert_eq!(uplink(&chip, 0, 2).fcnt, 2);
+    assert_eq!(after.session().unwrap().fcnt_up, 3);
+    assert_eq!(
+        FileSessionStore::new(&path).load().unwrap().unwrap(),
+        after.state()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dev_nonce_is_saved_before_join_request() {
+    let path = session_path("join");
+    let credentials = OtaaCredentials {
+        dev_eui: 0x0004A30B001C0530,
+        join_eui: 0x70B3D57ED0000000,
+        app_key: key(APP_KEY),
+    };
+    let (_chip, _clock, mut before) = device(Band::EU863, 5);
+    before.set_dev_nonce(7);
+    assert_eq!(
+        before.set_session_store(FileSessionStore::new(&path)),
+        Ok(false)
+    );
+    assert_eq!(before.join(&credentials), Err(Error::ReceiveTimedOut));
+
+    let (_chip, _clock, mut after) = device(Band::EU863, 5);
+    assert_eq!(
+        after.set_session_store(FileSessionStore::new(&path)),
+        Ok(false)
+    );
+    assert_eq!(after.dev_nonce(), 8);
+    assert_eq!(after.session(), None);
+
+    let (_chip, _clock, mut other) = device(Band::US901, 0);
+    assert_eq!(
+        other.set_session_store(FileSessionStore::new(&path)),
+        Err(Error::InvalidSessionState("saved for another band"))
+    );
+    std::fs::remove_file(&path).unwrap();
+}
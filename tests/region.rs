@@ -0,0 +1,127 @@
+use rfm9x::{Band, Bandwidth, Channel, Error, SpreadingFactor};
+use std::time::Duration;
+
+#[test]
+fn us901_has_64_plus_8_uplink_channels() {
+    let us = Band::US901.parameters();
+    assert_eq!(us.uplink_channel_count(), 72);
+    assert_eq!(us.uplink_frequency(0), Ok(902_300_000));
+    assert_eq!(us.uplink_frequency(63), Ok(914_900_000));
+    assert_eq!(us.uplink_frequency(64), Ok(903_000_000));
+    assert_eq!(us.uplink_frequency(71), Ok(914_200_000));
+    assert_eq!(
+        us.uplink_frequency(72),
+        Err(Error::InvalidChannel(Channel::Uplink(72), Band::US901))
+    );
+    assert_eq!(us.uplink_data_rates(10), Ok(0..=3));
+    assert_eq!(us.uplink_data_rates(65), Ok(4..=4));
+
+    // Downlinks on eight 500 kHz channels
+    assert_eq!(us.downlink_channels().len(), 8);
+    assert_eq!(us.rx1_frequency(0), Ok(923_300_000));
+    assert_eq!(us.rx1_frequency(9), Ok(923_900_000));
+    assert_eq!(us.rx1_frequency(71), Ok(927_500_000));
+    assert_eq!((us.rx2_frequency, us.rx2_data_rate), (923_300_000, 8));
+}
+
+#[test]
+fn sub_band_masks() {
+    let us = Band::US901.parameters();
+    // Sub-band 2 of the usual numbering from 1: channels 8 - 15 and 65
+    assert_eq!(us.sub_band_mask(1), Ok(0xFF00 | 1 << 65));
+    assert_eq!(
+        us.sub_band_mask(8),
+        Err(Error::InvalidSubBand(8, Band::US901))
+    );
+
+    let cn = Band::CN470.parameters();
+    assert_eq!(cn.sub_band_mask(11), Ok(0xFF << 88));
+    assert_eq!(
+        Band::EU863.parameters().sub_band_mask(0),
+        Err(Error::InvalidSubBand(0, Band::EU863))
+    );
+}
+
+#[test]
+fn rx1_data_rates() {
+    let eu = Band::EU863.parameters();
+    assert_eq!(eu.rx1_data_rate(5, 0), Ok(5));
+    assert_eq!(eu.rx1_data_rate(1, 3), Ok(0));
+    assert_eq!(
+        eu.rx1_data_rate(5, 6),
+        Err(Error::InvalidDataRateOffset(6, Band::EU863))
+    );
+
+    let us = Band::US901.parameters();
+    assert_eq!(us.rx1_data_rate(0, 0), Ok(10));
+    assert_eq!(us.rx1_data_rate(4, 1), Ok(13));
+    assert_eq!(us.rx1_data_rate(0, 3), Ok(8));
+    assert_eq!(
+        us.rx1_data_rate(8, 0),
+        Err(Error::InvalidDataRate(8, Band::US901))
+    );
+
+    let au = Band::AU915.parameters();
+    assert_eq!(au.rx1_data_rate(6, 0), Ok(13));
+    assert_eq!(au.rx1_data_rate(2, 1), Ok(9));
+
+    // Offsets 6 and 7 raise the data rate
+    let as923 = Band::AS920.parameters();
+    assert_eq!(as923.rx1_data_rate(2, 7), Ok(4));
+    assert_eq!(as923.rx1_data_rate(5, 6), Ok(5));
+}
+
+#[test]
+fn data_rates_and_payload_limits() {
+    let us = Band::US901.parameters();
+    let dr0 = us.data_rate(0).unwrap();
+    assert_eq!((dr0.sf, dr0.bw), (SpreadingFactor::SF10, Bandwidth::BW125));
+    assert_eq!(us.max_mac_payload(0, true), Ok(19));
+    assert_eq!(us.data_rate(5), Err(Error::InvalidDataRate(5, Band::US901)));
+    assert_eq!(us.max_dwell_time, Some(Duration::from_millis(400)));
+
+    let as923 = Band::AS920.parameters();
+    assert_eq!(as923.max_mac_payload(2, false), Ok(59));
+    assert_eq!(as923.max_mac_payload(2, true), Ok(19));
+    assert_eq!(
+        as923.max_mac_payload(0, true),
+        Err(Error::InvalidDataRate(0, Band::AS920))
+    );
+
+    // No dwell time rules in EU863
+    assert_eq!(Band::EU863.parameters().max_mac_payload(0, true), Ok(59));
+    assert_eq!(Band::EU863.parameters().max_eirp, 16.0);
+    assert_eq!(Band::KR920.parameters().data_rates.len(), 6);
+}
+
+#[test]
+fn channels_resolve_per_band() {
+    assert_eq!(Channel::Ch0.frequency_hz(&Band::EU863), Ok(868_100_000));
+    assert_eq!(Channel::Ch9.frequency_hz(&Band::EU863), Ok(869_525_000));
+    assert_eq!(Channel::Ch0.frequency_hz(&Band::US901), Ok(903_900_000));
+    assert_eq!(Channel::Ch2.frequency_hz(&Band::IN865), Ok(865_985_000));
+    assert_eq!(
+        Channel::Uplink(1).frequency_hz(&Band::EU433),
+        Ok(433_375_000)
+    );
+    assert_eq!(
+        Channel::Frequency(915_000_000).frequency_hz(&Band::KR920),
+        Ok(915_000_000)
+    );
+
+    // Ch9 used to panic outside EU863
+    assert_eq!(
+        Channel::Ch9.frequency_hz(&Band::US901),
+        Err(Error::InvalidChannel(Channel::Ch9, Band::US901))
+    );
+    assert_eq!(
+        Channel::Ch3.frequency_hz(&Band::KR920),
+        Err(Error::InvalidChannel(Channel::Ch3, Band::KR920))
+    );
+
+    // Only the channel selector of a radio resolves Multi
+    assert_eq!(
+        Channel::Multi.frequency_hz(&Band::KR920),
+        Err(Error::InvalidChannel(Channel::Multi, Band::KR920))
+    );
+}